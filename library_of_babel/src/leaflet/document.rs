@@ -1,4 +1,5 @@
 use super::line::Line;
+use super::metadata::FieldValue;
 use super::parse_error::ParseError;
 use super::raw_section::RawSection;
 use super::schema::Schema;
@@ -18,7 +19,6 @@ impl Document {
             .filter(|raw_section| raw_section.is_a_leaflet_section());
 
         let schema_raw_section = raw_sections.next().ok_or(ParseError::NoLeafletSections)?;
-        dbg!(&schema_raw_section);
         let schema = Schema::from_raw_section(&schema_raw_section)?;
 
         type Sections = Vec<Section>;
@@ -28,6 +28,15 @@ impl Document {
 
         Ok(Document { schema, sections })
     }
+
+    /// Every field in every paragraph, by normalized field name.
+    pub fn field_values(&self) -> impl Iterator<Item = (&str, &FieldValue)> {
+        self.sections
+            .iter()
+            .flat_map(|section| &section.paragraphs)
+            .flat_map(|paragraph| &paragraph.metadata.fields)
+            .map(|(name, value)| (name.as_str(), value))
+    }
 }
 
 fn get_raw_sections(document_text: String) -> Vec<RawSection> {
@@ -49,6 +58,9 @@ fn get_raw_sections(document_text: String) -> Vec<RawSection> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::leaflet::FieldValue;
+    use crate::obsidian::VaultItemId;
+    use std::time::SystemTime;
 
     #[test]
     fn test_get_raw_sections() {
@@ -70,4 +82,55 @@ line 5"#
         assert_eq!(raw_sections[1].text, "line 5");
         assert_eq!(raw_sections[1].starting_line_number, 5);
     }
+
+    #[test]
+    fn test_from_str() {
+        let document_text = "This is a leaflet schema
+date: yyyy.mm.dd
+person: link
+pages: optional u64
+---
+date: 2023.05.14
+person: [[Richard Feynman]]
+Read about the Manhattan Project.
+
+pages: 12
+Then about safecracking.";
+        let created_at = SystemTime::UNIX_EPOCH;
+        let vault = Vault::from_files([
+            ("Richard Feynman.md", "", created_at),
+            ("Reading log.md", document_text, created_at),
+        ])
+        .unwrap();
+
+        let page = vault
+            .item(&VaultItemId::from("Reading log.md"))
+            .unwrap()
+            .try_into_page()
+            .unwrap();
+        let document = Document::from_str(&vault, page.contents.clone()).unwrap();
+
+        assert_eq!(document.schema.expected_fields.len(), 3);
+        assert_eq!(document.sections.len(), 1);
+        assert_eq!(document.sections[0].paragraphs.len(), 2);
+
+        let person = document
+            .field_values()
+            .find_map(|(name, value)| match value {
+                FieldValue::Link { vault_item_id, .. } if name == "person" => {
+                    Some(vault_item_id.clone())
+                }
+                _ => None,
+            });
+        assert_eq!(person, Some(Some(VaultItemId::from("Richard Feynman.md"))));
+
+        let pages: Vec<u64> = document
+            .field_values()
+            .filter_map(|(_, value)| match value {
+                FieldValue::U64(pages) => Some(*pages),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(pages, vec![12]);
+    }
 }
@@ -1,5 +1,6 @@
 mod document;
 pub use self::document::Document;
+pub use self::metadata::FieldValue;
 
 mod line;
 mod metadata;
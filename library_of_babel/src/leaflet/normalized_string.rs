@@ -5,4 +5,8 @@ impl NormalizedString {
     pub fn new(s: &str) -> Self {
         NormalizedString(s.trim().to_lowercase())
     }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
 }
@@ -1,12 +1,13 @@
 #![allow(non_upper_case_globals)]
 
-use date::*;
+use chrono::NaiveDate;
 use obsidian::*;
+use std::collections::HashSet;
 
 mod date;
 pub mod extensions;
 mod leaflet;
-mod obsidian;
+pub mod obsidian;
 mod years;
 
 // pub fn main() {
@@ -14,7 +15,7 @@ mod years;
 // let vault_path = "/Users/photon-garden/library-of-babel";
 // let vault_path = "/Users/photon-garden/obsidian-dev";
 
-// let mut vault = Vault::load_from_disk(vault_path);
+// let mut vault = Vault::open(vault_path, VaultConfig::default()).unwrap();
 // move_people_into_people_folder(&mut vault);
 // create_dates_for_year(&mut vault, 2024);
 // }
@@ -23,6 +24,13 @@ pub use years::year_2023::convert_2023_haiku_to_csv;
 pub use years::year_2024::test_leaflet;
 
 pub fn move_people_into_people_folder(vault: &mut Vault) {
+    plan_moving_people_into_people_folder(vault)
+        .apply(vault)
+        .expect("Error moving people into the people folder.");
+}
+
+/// Pages that link to the people topic but aren't in the people folder yet.
+pub fn plan_moving_people_into_people_folder(vault: &Vault) -> VaultChangeSet {
     let people_topic_id = vault
         .item_at_path("topics + tags/People.md")
         .expect("Couldn't find the people topic in the vault.")
@@ -31,82 +39,64 @@ pub fn move_people_into_people_folder(vault: &mut Vault) {
         .id
         .clone();
 
-    let people_not_in_the_people_folder = vault
-        .pages_mut()
-        .filter(|page| page.has_a_reference_to(&people_topic_id))
-        .filter(|page| !page.file.path_from_vault_root.starts_with("people"));
-
-    for person in people_not_in_the_people_folder {
-        let path_from_vault_root = &person.file.path_from_vault_root;
+    // A person can link to the people topic more than once, so we dedupe with a set.
+    let people_not_in_the_people_folder: HashSet<_> = vault
+        .backlinks(&people_topic_id)
+        .iter()
+        .map(|backlink| backlink.source.clone())
+        .filter(|person_id| !person_id.path_from_vault_root().starts_with("people"))
+        .collect();
+
+    let mut change_set = VaultChangeSet::new();
+    for person_id in people_not_in_the_people_folder {
+        let path_from_vault_root = person_id.path_from_vault_root();
         let new_path = format!("people/{path_from_vault_root}");
-        person.file.move_file(&new_path);
+        change_set.move_item(person_id, new_path);
     }
+    change_set
 }
 
 pub fn create_dates_for_year(vault: &mut Vault, year: i32) {
-    let year = Year::new(year);
-
-    let year_id = get_id_for_unit_of_time(UnitOfTime::Year(year));
-    vault.find_or_create_page(year_id, || {
-        "[[Years]]\n\nTheme:\n\nImportant events\n- ".to_string()
-    });
-
-    for month in date::months() {
-        let month_id = get_id_for_unit_of_time(UnitOfTime::Month(year, month));
-        vault.find_or_create_page(month_id, || format!("[[{year}]], [[Months]]"));
-
-        for day_of_the_month in date::days_in_month(year, month) {
-            let day_id = get_id_for_unit_of_time(UnitOfTime::Day(year, month, day_of_the_month));
-            vault.find_or_create_page(day_id, || {
-                format!("[[{year}]], [[{year}.{month}]], [[Days]]")
-            });
-        }
-    }
-}
-
-fn get_id_for_unit_of_time(unit_of_time: UnitOfTime) -> VaultItemId {
-    let file_name = match unit_of_time {
-        UnitOfTime::Year(year) => {
-            format!("{year}.md")
-        }
-
-        UnitOfTime::Month(year, month) => {
-            format!("{year}.{month}.md")
-        }
-
-        UnitOfTime::Day(year, month, day) => {
-            format!("{year}.{month}.{day}.md")
-        }
-    };
-
-    let year = unit_of_time.year();
-    let path = format!("{year}/{file_name}");
-
-    let path = if unit_of_time.in_current_year() {
-        path
-    } else {
-        format!("years/{path}")
-    };
-
-    VaultItemId::from_path_from_vault_root(path)
+    plan_dates_for_year(vault, year)
+        .apply(vault)
+        .expect("Error creating year, month and day pages.");
 }
 
-enum UnitOfTime {
-    Year(Year),
-    Month(Year, date::Month),
-    Day(Year, date::Month, DayOfTheMonth),
+/// Year, month and day pages that don't exist yet.
+pub fn plan_dates_for_year(vault: &Vault, year: i32) -> VaultChangeSet {
+    let first_day = NaiveDate::from_ymd_opt(year, 1, 1).expect("Error creating the first day.");
+    let last_day = NaiveDate::from_ymd_opt(year, 12, 31).expect("Error creating the last day.");
+    periodic_notes().plan_range(vault, first_day, last_day)
 }
 
-impl UnitOfTime {
-    fn year(&self) -> &Year {
-        match self {
-            UnitOfTime::Year(year) => year,
-            UnitOfTime::Month(year, ..) => year,
-            UnitOfTime::Day(year, ..) => year,
-        }
-    }
+/// Year, month and day pages live in a folder for their year, like
+/// `2024/2024.01.31.md`. Past and future years go in `years`.
+pub fn periodic_notes() -> PeriodicNotes {
+    let settings = |periodicity, format: &str, template: &str| PeriodicNoteSettings {
+        periodicity,
+        folder: String::new(),
+        format: format.to_string(),
+        template: Some(NoteTemplate::Text(template.to_string())),
+    };
 
-    fn in_current_year(&self) -> bool {
-        self.year() == &date::current_year()
+    PeriodicNotes {
+        settings: vec![
+            settings(
+                Periodicity::Year,
+                "YYYY/YYYY",
+                "[[Years]]\n\nTheme:\n\nImportant events\n- ",
+            ),
+            settings(
+                Periodicity::Month,
+                "YYYY/YYYY.MM",
+                "{{period.parent}}, [[Months]]",
+            ),
+            settings(
+                Periodicity::Day,
+                "YYYY/YYYY.MM.DD",
+                "[[{{date:YYYY}}]], {{period.parent}}, [[Days]]",
+            ),
+        ],
+        other_years_folder: Some("years".to_string()),
     }
 }
@@ -0,0 +1,141 @@
+use super::{Link, Vault, VaultItem, VaultItemId};
+use std::fmt::Display;
+use std::ops::Range;
+
+/// A link whose text matches more than one file equally well, like
+/// `[[Meeting notes]]` when two folders each have a `Meeting notes.md`.
+/// The link points at the oldest, which might not be the one its writer
+/// meant. See `Vault::ambiguous_links`.
+#[derive(Debug, Clone)]
+pub struct AmbiguousLink {
+    /// The page or canvas the link is in.
+    pub source: VaultItemId,
+    pub link: Link,
+    /// Where the link is in the page's contents, or in its canvas node's text.
+    pub range: Range<usize>,
+    /// For canvases, the node the link is in.
+    pub canvas_node_id: Option<String>,
+    /// Every file the link matches, oldest first. The first is the one it
+    /// points at.
+    pub matches: Vec<VaultItemId>,
+}
+
+impl AmbiguousLink {
+    /// The file the link points at.
+    pub fn chosen(&self) -> &VaultItemId {
+        &self.matches[0]
+    }
+
+    /// The files the link could have meant instead.
+    pub fn others(&self) -> &[VaultItemId] {
+        &self.matches[1..]
+    }
+}
+
+impl Display for AmbiguousLink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let others: Vec<&str> = self
+            .others()
+            .iter()
+            .map(VaultItemId::path_from_vault_root)
+            .collect();
+        write!(
+            f,
+            "{}: {} points at {}, but could also mean {}",
+            self.source.path_from_vault_root(),
+            self.link.text,
+            self.chosen().path_from_vault_root(),
+            others.join(", ")
+        )
+    }
+}
+
+/// Every ambiguous link in the vault, by source and then position.
+pub(super) fn find_ambiguous_links(vault: &Vault) -> Vec<AmbiguousLink> {
+    let link_resolver = vault.link_resolver();
+    let mut ambiguous_links = vec![];
+
+    for item in vault.items() {
+        let links: Vec<(&Link, Range<usize>, Option<&String>)> = match item {
+            VaultItem::Page(page) => page
+                .reference_spans
+                .iter()
+                .map(|reference_span| (&reference_span.link, reference_span.range().clone(), None))
+                .collect(),
+            VaultItem::Canvas(canvas) => canvas
+                .links
+                .iter()
+                .map(|canvas_link| {
+                    let range = canvas_link.range.clone().unwrap_or(0..0);
+                    (&canvas_link.link, range, Some(&canvas_link.node_id))
+                })
+                .collect(),
+            VaultItem::NonPage { .. } => continue,
+        };
+
+        for (link, range, canvas_node_id) in links {
+            let matches = link_resolver.matches_from(link.target(), link.syntax, item.id());
+            if matches.len() < 2 {
+                continue;
+            }
+            ambiguous_links.push(AmbiguousLink {
+                source: item.id().clone(),
+                link: link.clone(),
+                range,
+                canvas_node_id: canvas_node_id.cloned(),
+                matches: matches.into_iter().cloned().collect(),
+            });
+        }
+    }
+
+    ambiguous_links.sort_by(|a, b| {
+        (&a.source, &a.canvas_node_id, a.range.start).cmp(&(
+            &b.source,
+            &b.canvas_node_id,
+            b.range.start,
+        ))
+    });
+    ambiguous_links
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::obsidian::*;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_ambiguous_links() {
+        let older = SystemTime::UNIX_EPOCH;
+        let newer = older + Duration::from_secs(60);
+        let vault = Vault::from_files([
+            ("2023/Meeting notes.md", "", older),
+            ("2024/Meeting notes.md", "", newer),
+            ("Richard Feynman.md", "", older),
+            (
+                "Journal.md",
+                "[[Meeting notes]], [[2024/Meeting notes]] and [[Richard Feynman]]",
+                older,
+            ),
+        ])
+        .unwrap();
+
+        let ambiguous_links = vault.ambiguous_links();
+        assert_eq!(ambiguous_links.len(), 1);
+        assert_eq!(
+            ambiguous_links[0].to_string(),
+            "Journal.md: [[Meeting notes]] points at 2023/Meeting notes.md, but could also mean 2024/Meeting notes.md"
+        );
+
+        let journal = VaultItemId::from("Journal.md");
+        let link_text_for = |path: &str| vault.link_text_for(&VaultItemId::from(path), &journal);
+        assert_eq!(
+            link_text_for("2024/Meeting notes.md").as_deref(),
+            Some("2024/Meeting notes")
+        );
+        assert_eq!(
+            link_text_for("Richard Feynman.md").as_deref(),
+            Some("Richard Feynman")
+        );
+        assert_eq!(link_text_for("Missing.md"), None);
+    }
+}
@@ -0,0 +1,752 @@
+use super::{content_hash, Contents, File, VaultError, VaultStorage};
+use chrono::NaiveDateTime;
+use lazy_static::lazy_static;
+use regex::bytes::Regex;
+use std::time::Duration;
+
+/// What we can tell about an attachment from its bytes. Read on demand by
+/// `Vault::attachment_metadata`, since it means reading the whole file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttachmentMetadata {
+    /// In bytes.
+    pub size: u64,
+    /// See `content_hash`. Identical files have the same hash.
+    pub content_hash: u64,
+    pub media: MediaMetadata,
+}
+
+/// What's in the file's headers, for the kinds of file we know how to
+/// read. Anything we couldn't find is `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MediaMetadata {
+    Image(ImageMetadata),
+    Audio {
+        duration: Option<Duration>,
+    },
+    Video {
+        duration: Option<Duration>,
+    },
+    Pdf {
+        page_count: Option<u32>,
+    },
+    /// Files that aren't images, audio, video or PDFs.
+    Other,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImageMetadata {
+    /// In pixels, as stored. See `displayed_dimensions` for photos taken
+    /// sideways.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// When the photo was taken, from its EXIF data. Cameras record it in
+    /// local time, without a time zone.
+    pub taken_at: Option<NaiveDateTime>,
+    /// The EXIF orientation, from 1 to 8. 1 means upright. 5 to 8 mean the
+    /// image has to be turned on its side to display it.
+    pub orientation: Option<u16>,
+}
+
+impl ImageMetadata {
+    /// The width and height once the EXIF orientation is applied.
+    pub fn displayed_dimensions(&self) -> Option<(u32, u32)> {
+        let (width, height) = (self.width?, self.height?);
+        match self.orientation {
+            Some(5..=8) => Some((height, width)),
+            _ => Some((width, height)),
+        }
+    }
+}
+
+impl AttachmentMetadata {
+    /// Reads `file` from `storage` and looks through its headers.
+    pub fn read(storage: &dyn VaultStorage, file: &File) -> Result<AttachmentMetadata, VaultError> {
+        let bytes = storage.read(&file.absolute_path)?;
+        let extension = file
+            .file_name
+            .rsplit_once('.')
+            .map(|(_, extension)| extension.to_ascii_lowercase())
+            .unwrap_or_default();
+
+        let media = match file.contents {
+            Contents::Image {} => MediaMetadata::Image(image_metadata(&extension, &bytes)),
+            Contents::Audio {} => MediaMetadata::Audio {
+                duration: duration(&extension, &bytes),
+            },
+            Contents::Video {} => MediaMetadata::Video {
+                duration: duration(&extension, &bytes),
+            },
+            Contents::Pdf {} => MediaMetadata::Pdf {
+                page_count: pdf_page_count(&bytes),
+            },
+            Contents::Markdown { .. } | Contents::Canvas { .. } | Contents::Unknown {} => {
+                MediaMetadata::Other
+            }
+        };
+
+        Ok(AttachmentMetadata {
+            size: bytes.len() as u64,
+            content_hash: content_hash(&bytes),
+            media,
+        })
+    }
+}
+
+fn image_metadata(extension: &str, bytes: &[u8]) -> ImageMetadata {
+    match extension {
+        "png" => png_metadata(bytes),
+        "jpg" | "jpeg" => jpeg_metadata(bytes),
+        "gif" => ImageMetadata {
+            width: le16(bytes, 6).map(u32::from),
+            height: le16(bytes, 8).map(u32::from),
+            ..ImageMetadata::default()
+        },
+        // Rows are stored bottom up when the height is positive.
+        "bmp" => ImageMetadata {
+            width: le32(bytes, 18).map(|width| (width as i32).unsigned_abs()),
+            height: le32(bytes, 22).map(|height| (height as i32).unsigned_abs()),
+            ..ImageMetadata::default()
+        },
+        "svg" => svg_metadata(bytes),
+        _ => ImageMetadata::default(),
+    }
+}
+
+fn png_metadata(bytes: &[u8]) -> ImageMetadata {
+    let mut metadata = ImageMetadata::default();
+    if !bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return metadata;
+    }
+
+    let mut offset = 8;
+    while let (Some(length), Some(kind)) = (be32(bytes, offset), bytes.get(offset + 4..offset + 8))
+    {
+        let data_start = offset + 8;
+        let Some(data) = bytes.get(data_start..data_start + length as usize) else {
+            break;
+        };
+        match kind {
+            b"IHDR" => {
+                metadata.width = be32(data, 0);
+                metadata.height = be32(data, 4);
+            }
+            b"eXIf" => read_exif(data, &mut metadata),
+            b"IEND" => break,
+            _ => {}
+        }
+        // Skip the data and its checksum.
+        offset = data_start + length as usize + 4;
+    }
+
+    metadata
+}
+
+fn jpeg_metadata(bytes: &[u8]) -> ImageMetadata {
+    let mut metadata = ImageMetadata::default();
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return metadata;
+    }
+
+    let mut offset = 2;
+    while let (Some(0xFF), Some(&marker)) = (bytes.get(offset), bytes.get(offset + 1)) {
+        // Start of scan. The image data comes next, and the headers are done.
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        // Padding.
+        if marker == 0xFF {
+            offset += 1;
+            continue;
+        }
+
+        let Some(length) = be16(bytes, offset + 2) else {
+            break;
+        };
+        let Some(data) = bytes.get(offset + 4..offset + 2 + length as usize) else {
+            break;
+        };
+        match marker {
+            0xE1 => {
+                if let Some(tiff) = data.strip_prefix(b"Exif\0\0") {
+                    read_exif(tiff, &mut metadata);
+                }
+            }
+            // Start of frame, except for the markers in that range that mean something else.
+            0xC0..=0xCF if ![0xC4, 0xC8, 0xCC].contains(&marker) => {
+                metadata.height = be16(data, 1).map(u32::from);
+                metadata.width = be16(data, 3).map(u32::from);
+            }
+            _ => {}
+        }
+        offset += 2 + length as usize;
+    }
+
+    metadata
+}
+
+lazy_static! {
+    static ref match_svg_tag: Regex =
+        Regex::new(r"(?s)<svg\b[^>]*>").expect("Error compiling regex.");
+    static ref match_svg_width: Regex =
+        Regex::new(r#"\swidth\s*=\s*["']\s*([0-9.]+)\s*(px)?\s*["']"#)
+            .expect("Error compiling regex.");
+    static ref match_svg_height: Regex =
+        Regex::new(r#"\sheight\s*=\s*["']\s*([0-9.]+)\s*(px)?\s*["']"#)
+            .expect("Error compiling regex.");
+    static ref match_svg_view_box: Regex = Regex::new(
+        r#"\sviewBox\s*=\s*["']\s*[-0-9.]+[\s,]+[-0-9.]+[\s,]+([0-9.]+)[\s,]+([0-9.]+)\s*["']"#
+    )
+    .expect("Error compiling regex.");
+}
+
+/// SVGs don't have to have a size. We use their `width` and `height` if
+/// they're in pixels, and fall back to their `viewBox`.
+fn svg_metadata(bytes: &[u8]) -> ImageMetadata {
+    let Some(svg_tag) = match_svg_tag.find(bytes) else {
+        return ImageMetadata::default();
+    };
+    let svg_tag = svg_tag.as_bytes();
+    let number = |regex: &Regex, group: usize| -> Option<u32> {
+        let captures = regex.captures(svg_tag)?;
+        let number: f64 = std::str::from_utf8(&captures[group]).ok()?.parse().ok()?;
+        Some(number.round() as u32)
+    };
+
+    let width = number(&match_svg_width, 1);
+    let height = number(&match_svg_height, 1);
+    let (width, height) = match (width, height) {
+        (Some(width), Some(height)) => (Some(width), Some(height)),
+        _ => (
+            number(&match_svg_view_box, 1),
+            number(&match_svg_view_box, 2),
+        ),
+    };
+
+    ImageMetadata {
+        width,
+        height,
+        ..ImageMetadata::default()
+    }
+}
+
+/// Reads the orientation and capture date from EXIF data, which is laid
+/// out like a TIFF file.
+fn read_exif(tiff: &[u8], metadata: &mut ImageMetadata) {
+    let is_little_endian = match tiff.get(0..2) {
+        Some(b"II") => true,
+        Some(b"MM") => false,
+        _ => return,
+    };
+    let u16_at = |offset: usize| {
+        if is_little_endian {
+            le16(tiff, offset)
+        } else {
+            be16(tiff, offset)
+        }
+    };
+    let u32_at = |offset: usize| {
+        if is_little_endian {
+            le32(tiff, offset)
+        } else {
+            be32(tiff, offset)
+        }
+    };
+    // Each entry is a tag, a type, a count and either the value or, if it
+    // doesn't fit in four bytes, where to find it.
+    let entries = |ifd_offset: usize| {
+        let count = u16_at(ifd_offset).unwrap_or(0) as usize;
+        (0..count).filter_map(move |index| {
+            let entry = ifd_offset + 2 + index * 12;
+            Some((u16_at(entry)?, entry))
+        })
+    };
+    let date_at = |entry: usize| {
+        let offset = u32_at(entry + 8)? as usize;
+        let text = std::str::from_utf8(tiff.get(offset..offset + 19)?).ok()?;
+        NaiveDateTime::parse_from_str(text, "%Y:%m:%d %H:%M:%S").ok()
+    };
+
+    let Some(ifd0) = u32_at(4) else {
+        return;
+    };
+    let mut modified_at = None;
+    let mut exif_ifd = None;
+    for (tag, entry) in entries(ifd0 as usize) {
+        match tag {
+            0x0112 => metadata.orientation = u16_at(entry + 8).filter(|o| (1..=8).contains(o)),
+            0x0132 => modified_at = date_at(entry),
+            0x8769 => exif_ifd = u32_at(entry + 8),
+            _ => {}
+        }
+    }
+
+    // DateTimeOriginal is when the photo was taken. DateTime is when the
+    // file was last changed, which is the next best thing.
+    let taken_at = exif_ifd.and_then(|exif_ifd| {
+        entries(exif_ifd as usize)
+            .find(|(tag, _)| *tag == 0x9003)
+            .and_then(|(_, entry)| date_at(entry))
+    });
+    metadata.taken_at = taken_at.or(modified_at);
+}
+
+fn duration(extension: &str, bytes: &[u8]) -> Option<Duration> {
+    let seconds = match extension {
+        "wav" => wav_seconds(bytes),
+        "mp3" => mp3_seconds(bytes),
+        "flac" => flac_seconds(bytes),
+        "ogg" | "ogv" => ogg_seconds(bytes),
+        "m4a" | "mp4" | "mov" | "3gp" => mp4_seconds(bytes),
+        "webm" | "mkv" => matroska_seconds(bytes),
+        _ => None,
+    }?;
+
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+fn wav_seconds(bytes: &[u8]) -> Option<f64> {
+    if bytes.get(0..4)? != b"RIFF" || bytes.get(8..12)? != b"WAVE" {
+        return None;
+    }
+
+    let mut byte_rate = None;
+    let mut offset = 12;
+    while let Some(kind) = bytes.get(offset..offset + 4) {
+        let size = le32(bytes, offset + 4)? as usize;
+        match kind {
+            b"fmt " => byte_rate = le32(bytes, offset + 16),
+            b"data" => return Some(size as f64 / f64::from(byte_rate.filter(|rate| *rate > 0)?)),
+            _ => {}
+        }
+        // Chunks are padded to an even length.
+        offset += 8 + size + size % 2;
+    }
+
+    None
+}
+
+/// Uses the Xing or Info header that variable bitrate files have, and
+/// otherwise assumes the first frame's bitrate holds for the whole file.
+/// Only reads MPEG layer III, which is what nearly every `.mp3` is.
+fn mp3_seconds(bytes: &[u8]) -> Option<f64> {
+    let mut start = 0;
+    if bytes.starts_with(b"ID3") {
+        let size = bytes
+            .get(6..10)?
+            .iter()
+            .fold(0usize, |size, byte| (size << 7) | usize::from(byte & 0x7F));
+        let has_footer = bytes.get(5)? & 0x10 != 0;
+        start = 10 + size + if has_footer { 10 } else { 0 };
+    }
+
+    let frame = (start..bytes.len().saturating_sub(4))
+        .find(|&offset| bytes[offset] == 0xFF && bytes[offset + 1] & 0xE0 == 0xE0)?;
+    let header = bytes.get(frame..frame + 4)?;
+
+    let version = (header[1] >> 3) & 0b11;
+    let layer = (header[1] >> 1) & 0b11;
+    if layer != 0b01 || version == 0b01 {
+        return None;
+    }
+    let is_mpeg1 = version == 0b11;
+
+    const mpeg1_bitrates: [u32; 15] = [
+        0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ];
+    const mpeg2_bitrates: [u32; 15] =
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+    let bitrate_index = usize::from(header[2] >> 4);
+    let kilobits_per_second = if is_mpeg1 {
+        mpeg1_bitrates.get(bitrate_index)
+    } else {
+        mpeg2_bitrates.get(bitrate_index)
+    }?;
+
+    let sample_rate_index = usize::from((header[2] >> 2) & 0b11);
+    let sample_rate = [44_100, 48_000, 32_000].get(sample_rate_index)?
+        / match version {
+            0b11 => 1,
+            0b10 => 2,
+            _ => 4,
+        };
+    let samples_per_frame = if is_mpeg1 { 1152 } else { 576 };
+
+    let is_mono = header[3] >> 6 == 0b11;
+    let side_info_size = match (is_mpeg1, is_mono) {
+        (true, false) => 32,
+        (true, true) | (false, false) => 17,
+        (false, true) => 9,
+    };
+    let xing = frame + 4 + side_info_size;
+    if let Some(b"Xing" | b"Info") = bytes.get(xing..xing + 4) {
+        let has_frame_count = be32(bytes, xing + 4)? & 1 != 0;
+        if has_frame_count {
+            let frame_count = be32(bytes, xing + 8)?;
+            return Some(
+                f64::from(frame_count) * f64::from(samples_per_frame) / f64::from(sample_rate),
+            );
+        }
+    }
+
+    if *kilobits_per_second == 0 {
+        return None;
+    }
+    let audio_bytes = (bytes.len() - frame) as f64;
+    Some(audio_bytes * 8.0 / f64::from(kilobits_per_second * 1000))
+}
+
+fn flac_seconds(bytes: &[u8]) -> Option<f64> {
+    if !bytes.starts_with(b"fLaC") || bytes.get(4)? & 0x7F != 0 {
+        return None;
+    }
+
+    // STREAMINFO is always the first metadata block.
+    let stream_info = bytes.get(8..8 + 34)?;
+    let sample_rate = (u32::from(stream_info[10]) << 12)
+        | (u32::from(stream_info[11]) << 4)
+        | (u32::from(stream_info[12]) >> 4);
+    let total_samples =
+        (u64::from(stream_info[13] & 0x0F) << 32) | u64::from(be32(stream_info, 14)?);
+    if sample_rate == 0 || total_samples == 0 {
+        return None;
+    }
+
+    Some(total_samples as f64 / f64::from(sample_rate))
+}
+
+/// Only reads Vorbis and Opus, since video codecs in Ogg count time in
+/// ways that depend on the codec.
+fn ogg_seconds(bytes: &[u8]) -> Option<f64> {
+    if !bytes.starts_with(b"OggS") {
+        return None;
+    }
+
+    let segment_count = usize::from(*bytes.get(26)?);
+    let packet = bytes.get(27 + segment_count..)?;
+    let (sample_rate, pre_skip) = if packet.starts_with(b"\x01vorbis") {
+        (le32(packet, 12)?, 0)
+    } else if packet.starts_with(b"OpusHead") {
+        (48_000, u64::from(le16(packet, 10)?))
+    } else {
+        return None;
+    };
+
+    // The last page says how many samples came before its end.
+    let last_page = bytes.windows(4).rposition(|window| window == b"OggS")?;
+    let granule_position =
+        u64::from(le32(bytes, last_page + 6)?) | (u64::from(le32(bytes, last_page + 10)?) << 32);
+    if sample_rate == 0 {
+        return None;
+    }
+
+    Some(granule_position.saturating_sub(pre_skip) as f64 / f64::from(sample_rate))
+}
+
+/// MP4, M4A, MOV and 3GP files are made of nested boxes. The `mvhd` box
+/// inside `moov` has the duration.
+fn mp4_seconds(bytes: &[u8]) -> Option<f64> {
+    let moov = find_mp4_box(bytes, b"moov")?;
+    let mvhd = find_mp4_box(moov, b"mvhd")?;
+
+    let (timescale, duration) = match mvhd.first()? {
+        0 => (be32(mvhd, 12)?, u64::from(be32(mvhd, 16)?)),
+        1 => (
+            be32(mvhd, 20)?,
+            (u64::from(be32(mvhd, 24)?) << 32) | u64::from(be32(mvhd, 28)?),
+        ),
+        _ => return None,
+    };
+    if timescale == 0 {
+        return None;
+    }
+
+    Some(duration as f64 / f64::from(timescale))
+}
+
+/// The contents of the first box of type `kind` among `boxes`.
+fn find_mp4_box<'b>(boxes: &'b [u8], kind: &[u8; 4]) -> Option<&'b [u8]> {
+    let mut offset = 0;
+    while offset + 8 <= boxes.len() {
+        let (header_size, size) = match be32(boxes, offset)? {
+            // The size doesn't fit in 32 bits, so it comes after the type.
+            1 => {
+                let size = (u64::from(be32(boxes, offset + 8)?) << 32)
+                    | u64::from(be32(boxes, offset + 12)?);
+                (16, size as usize)
+            }
+            // The box runs to the end.
+            0 => (8, boxes.len() - offset),
+            size => (8, size as usize),
+        };
+        let end = offset.checked_add(size).filter(|_| size >= header_size)?;
+
+        if &boxes[offset + 4..offset + 8] == kind {
+            return boxes.get(offset + header_size..end);
+        }
+        offset = end;
+    }
+
+    None
+}
+
+/// WebM and MKV files are EBML, a binary kind of XML. The duration is in
+/// `Segment` → `Info`, counted in units of `TimestampScale` nanoseconds.
+fn matroska_seconds(bytes: &[u8]) -> Option<f64> {
+    const ebml_header: u32 = 0x1A45_DFA3;
+    const segment: u32 = 0x1853_8067;
+    const info: u32 = 0x1549_A966;
+    const timestamp_scale: u32 = 0x2A_D7B1;
+    const duration: u32 = 0x4489;
+
+    let (id, _) = ebml_elements(bytes).next()?;
+    if id != ebml_header {
+        return None;
+    }
+    let (_, segment_body) = ebml_elements(bytes).find(|(id, _)| *id == segment)?;
+    let (_, info_body) = ebml_elements(segment_body).find(|(id, _)| *id == info)?;
+
+    let mut nanoseconds_per_unit = 1_000_000.0;
+    let mut units = None;
+    for (id, body) in ebml_elements(info_body) {
+        match id {
+            timestamp_scale => {
+                nanoseconds_per_unit = body
+                    .iter()
+                    .fold(0u64, |scale, byte| (scale << 8) | u64::from(*byte))
+                    as f64
+            }
+            duration => {
+                units = match body.len() {
+                    4 => Some(f64::from(f32::from_bits(be32(body, 0)?))),
+                    8 => Some(f64::from_bits(
+                        (u64::from(be32(body, 0)?) << 32) | u64::from(be32(body, 4)?),
+                    )),
+                    _ => None,
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Some(units? * nanoseconds_per_unit / 1e9)
+}
+
+/// The ids and bodies of the EBML elements in `bytes`, one after the other.
+/// An element of unknown size, which live streams use, runs to the end.
+fn ebml_elements(bytes: &[u8]) -> impl Iterator<Item = (u32, &[u8])> {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        let (id, id_length) = ebml_variable_int(bytes.get(offset..)?, true)?;
+        let (size, size_length) = ebml_variable_int(bytes.get(offset + id_length..)?, false)?;
+        let body_start = offset + id_length + size_length;
+        let body_end = match size {
+            Some(size) => body_start.checked_add(size as usize)?.min(bytes.len()),
+            None => bytes.len(),
+        };
+        let body = bytes.get(body_start..body_end)?;
+        offset = body_end;
+        Some((id.unwrap_or(0) as u32, body))
+    })
+}
+
+/// Reads a variable-length EBML integer and how many bytes it took. Ids
+/// keep their length marker. A size of all ones means it's unknown.
+fn ebml_variable_int(bytes: &[u8], is_id: bool) -> Option<(Option<u64>, usize)> {
+    let first = *bytes.first()?;
+    let length = first.leading_zeros() as usize + 1;
+    if length > 8 {
+        return None;
+    }
+
+    let marker_mask = if is_id {
+        0xFF
+    } else {
+        (0xFF_u16 >> length) as u8
+    };
+    let value = bytes
+        .get(1..length)?
+        .iter()
+        .fold(u64::from(first & marker_mask), |value, byte| {
+            (value << 8) | u64::from(*byte)
+        });
+
+    let all_ones = (1u64 << (7 * length)) - 1;
+    if !is_id && value == all_ones {
+        return Some((None, length));
+    }
+    Some((Some(value), length))
+}
+
+lazy_static! {
+    static ref match_pdf_object: Regex =
+        Regex::new(r"(?s)\d+\s+\d+\s+obj\b(.*?)\bendobj").expect("Error compiling regex.");
+    static ref match_pages_type: Regex =
+        Regex::new(r"/Type\s*/Pages\b").expect("Error compiling regex.");
+    static ref match_page_type: Regex =
+        Regex::new(r"/Type\s*/Page\b").expect("Error compiling regex.");
+    static ref match_count: Regex = Regex::new(r"/Count\s+(\d+)").expect("Error compiling regex.");
+}
+
+/// The root of a PDF's page tree counts every page under it, so we take the
+/// biggest count. If the page tree is compressed, we count the pages we
+/// can see instead, and give up if there aren't any.
+fn pdf_page_count(bytes: &[u8]) -> Option<u32> {
+    if !bytes.starts_with(b"%PDF") {
+        return None;
+    }
+
+    let biggest_count = match_pdf_object
+        .captures_iter(bytes)
+        .map(|captures| {
+            captures
+                .get(1)
+                .expect("The regex has one group.")
+                .as_bytes()
+        })
+        .filter(|object| match_pages_type.is_match(object))
+        .filter_map(|object| {
+            let captures = match_count.captures(object)?;
+            std::str::from_utf8(&captures[1]).ok()?.parse::<u32>().ok()
+        })
+        .max();
+
+    biggest_count.or_else(|| {
+        let page_count = match_page_type.find_iter(bytes).count() as u32;
+        Some(page_count).filter(|page_count| *page_count > 0)
+    })
+}
+
+fn be16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn be32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn le16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn le32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::obsidian::*;
+    use chrono::NaiveDate;
+    use std::time::{Duration, SystemTime};
+
+    /// A JPEG with just enough in it to have a size and EXIF data: a
+    /// little-endian TIFF with the orientation and a pointer to the EXIF
+    /// IFD, which has DateTimeOriginal.
+    fn jpeg(width: u16, height: u16, orientation: u16, taken_at: &str) -> Vec<u8> {
+        let mut tiff = b"II\x2a\x00\x08\x00\x00\x00".to_vec();
+        // IFD0 at 8: two entries, then no next IFD.
+        tiff.extend(2u16.to_le_bytes());
+        tiff.extend([0x12, 0x01, 3, 0, 1, 0, 0, 0]);
+        tiff.extend(u32::from(orientation).to_le_bytes());
+        tiff.extend([0x69, 0x87, 4, 0, 1, 0, 0, 0]);
+        tiff.extend(38u32.to_le_bytes());
+        tiff.extend(0u32.to_le_bytes());
+        // The EXIF IFD at 38: one entry, pointing at the date at 56.
+        tiff.extend(1u16.to_le_bytes());
+        tiff.extend([0x03, 0x90, 2, 0, 20, 0, 0, 0]);
+        tiff.extend(56u32.to_le_bytes());
+        tiff.extend(0u32.to_le_bytes());
+        tiff.extend(taken_at.as_bytes());
+        tiff.push(0);
+
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend(tiff);
+
+        let mut bytes = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        bytes.extend((app1.len() as u16 + 2).to_be_bytes());
+        bytes.extend(app1);
+        bytes.extend([0xFF, 0xC0, 0, 11, 8]);
+        bytes.extend(height.to_be_bytes());
+        bytes.extend(width.to_be_bytes());
+        bytes.extend([1, 1, 0x11, 0]);
+        bytes.extend([0xFF, 0xDA, 0, 2, 0xFF, 0xD9]);
+        bytes
+    }
+
+    fn wav(seconds: u32) -> Vec<u8> {
+        let byte_rate = 8_000u32;
+        let data_size = byte_rate * seconds;
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend((36 + data_size).to_le_bytes());
+        bytes.extend(b"WAVEfmt ");
+        bytes.extend(16u32.to_le_bytes());
+        bytes.extend([1, 0, 1, 0]);
+        bytes.extend(8_000u32.to_le_bytes());
+        bytes.extend(byte_rate.to_le_bytes());
+        bytes.extend([1, 0, 8, 0]);
+        bytes.extend(b"data");
+        bytes.extend(data_size.to_le_bytes());
+        bytes.extend(vec![0; data_size as usize]);
+        bytes
+    }
+
+    #[test]
+    fn test_attachment_metadata() {
+        let created_at = SystemTime::UNIX_EPOCH;
+        let photo = jpeg(4032, 3024, 6, "2023:10:14 07:45:12");
+        let pdf = "%PDF-1.4\n1 0 obj << /Type /Catalog /Pages 2 0 R >> endobj\n2 0 obj << /Type /Pages /Kids [3 0 R 4 0 R] /Count 2 >> endobj\n3 0 obj << /Type /Page /Parent 2 0 R >> endobj\n4 0 obj << /Type /Page /Parent 2 0 R >> endobj\n%%EOF";
+        let vault = Vault::from_files([
+            ("photos/haiku.jpg", photo.clone(), created_at),
+            ("recordings/birdsong.wav", wav(3), created_at),
+            ("papers/qed.pdf", pdf.as_bytes().to_vec(), created_at),
+            ("Notes.md", b"![[haiku.jpg]]".to_vec(), created_at),
+        ])
+        .unwrap();
+
+        let metadata = vault
+            .attachment_metadata(&VaultItemId::from("photos/haiku.jpg"))
+            .unwrap();
+        assert_eq!(metadata.size, photo.len() as u64);
+        assert_eq!(metadata.content_hash, content_hash(&photo));
+        let MediaMetadata::Image(image) = &metadata.media else {
+            panic!("Expected an image, got {:?}.", metadata.media);
+        };
+        assert_eq!(image.displayed_dimensions(), Some((3024, 4032)));
+        assert_eq!(
+            image.taken_at,
+            NaiveDate::from_ymd_opt(2023, 10, 14).and_then(|date| date.and_hms_opt(7, 45, 12))
+        );
+
+        let metadata = vault
+            .attachment_metadata(&VaultItemId::from("recordings/birdsong.wav"))
+            .unwrap();
+        assert_eq!(
+            metadata.media,
+            MediaMetadata::Audio {
+                duration: Some(Duration::from_secs(3))
+            }
+        );
+
+        let metadata = vault
+            .attachment_metadata(&VaultItemId::from("papers/qed.pdf"))
+            .unwrap();
+        assert_eq!(
+            metadata.media,
+            MediaMetadata::Pdf {
+                page_count: Some(2)
+            }
+        );
+
+        let error = vault
+            .attachment_metadata(&VaultItemId::from("Notes.md"))
+            .unwrap_err();
+        assert!(matches!(error, VaultError::NotAnAttachment { .. }));
+    }
+}
@@ -0,0 +1,275 @@
+use super::{Contents, Vault, VaultChangeSet, VaultError, VaultItem, VaultItemId};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+/// Attachments nothing uses, and attachments that are copies of each
+/// other. See `Vault::attachment_report`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AttachmentReport {
+    /// Attachments that no page or canvas links to or embeds, by path.
+    /// Only images, audio, video and PDFs count as attachments, so files
+    /// like scripts, stylesheets and archives are never reported.
+    pub orphans: Vec<VaultItemId>,
+    /// By the path of the copy we'd keep.
+    pub duplicate_groups: Vec<DuplicateGroup>,
+}
+
+/// Attachments with exactly the same bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    pub content_hash: u64,
+    /// In bytes, for each copy.
+    pub size: u64,
+    /// The copy to keep: the one the most pages and canvases already link
+    /// to, so the cleanup rewrites as few links as possible. Ties go to the
+    /// oldest copy.
+    pub canonical: VaultItemId,
+    /// The other copies, by path.
+    pub duplicates: Vec<VaultItemId>,
+}
+
+impl AttachmentReport {
+    pub(super) fn new(vault: &Vault) -> Result<AttachmentReport, VaultError> {
+        let mut attachments: Vec<&VaultItemId> = vault
+            .items()
+            .filter(|item| is_attachment(item))
+            .map(VaultItem::id)
+            .collect();
+        attachments.sort();
+
+        let orphans = attachments
+            .iter()
+            .filter(|id| !vault.link_graph().has_backlinks(id))
+            .map(|id| (*id).clone())
+            .collect();
+
+        let mut by_hash_and_size: HashMap<(u64, u64), Vec<&VaultItemId>> = HashMap::new();
+        for id in &attachments {
+            let metadata = vault.attachment_metadata(id)?;
+            by_hash_and_size
+                .entry((metadata.content_hash, metadata.size))
+                .or_default()
+                .push(id);
+        }
+
+        let mut duplicate_groups = vec![];
+        for ((content_hash, size), ids) in by_hash_and_size {
+            if ids.len() < 2 {
+                continue;
+            }
+            // Different files can have the same hash, and the cleanup deletes
+            // files, so we make sure the bytes really are the same.
+            for ids in group_by_bytes(vault, ids)? {
+                if let Some(group) = DuplicateGroup::new(vault, content_hash, size, ids) {
+                    duplicate_groups.push(group);
+                }
+            }
+        }
+        duplicate_groups.sort_by(|a, b| a.canonical.cmp(&b.canonical));
+
+        Ok(AttachmentReport {
+            orphans,
+            duplicate_groups,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orphans.is_empty() && self.duplicate_groups.is_empty()
+    }
+
+    /// Changes that point every link to a duplicate at its canonical copy,
+    /// then delete the duplicates and the orphans. Preview it with
+    /// `VaultChangeSet::preview` before applying it.
+    pub fn cleanup_plan(&self, vault: &Vault) -> VaultChangeSet {
+        let mut change_set = VaultChangeSet::new();
+
+        let canonical_copies: HashMap<VaultItemId, VaultItemId> = self
+            .duplicate_groups
+            .iter()
+            .flat_map(|group| {
+                group
+                    .duplicates
+                    .iter()
+                    .map(|duplicate| (duplicate.clone(), group.canonical.clone()))
+            })
+            .collect();
+
+        let mut sources: Vec<&VaultItemId> = canonical_copies
+            .keys()
+            .flat_map(|duplicate| vault.backlinks(duplicate))
+            .map(|edge| &edge.source)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        sources.sort();
+        for source in sources {
+            if let Some(contents) = vault.contents_with_links_retargeted(source, &canonical_copies)
+            {
+                change_set.edit_page(source.clone(), contents);
+            }
+        }
+
+        let mut deletions: Vec<&VaultItemId> = canonical_copies
+            .keys()
+            .chain(&self.orphans)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        deletions.sort();
+        for id in deletions {
+            change_set.delete_item(id.clone());
+        }
+
+        change_set
+    }
+}
+
+impl DuplicateGroup {
+    fn new(
+        vault: &Vault,
+        content_hash: u64,
+        size: u64,
+        mut ids: Vec<&VaultItemId>,
+    ) -> Option<DuplicateGroup> {
+        if ids.len() < 2 {
+            return None;
+        }
+
+        let canonical_index = (0..ids.len()).min_by_key(|index| {
+            let id = ids[*index];
+            let link_count = vault.backlinks(id).len();
+            let created_at = vault.item(id).map(|item| item.file().created_at);
+            (std::cmp::Reverse(link_count), created_at, id)
+        })?;
+        let canonical = ids.remove(canonical_index).clone();
+        let mut duplicates: Vec<VaultItemId> = ids.into_iter().cloned().collect();
+        duplicates.sort();
+
+        Some(DuplicateGroup {
+            content_hash,
+            size,
+            canonical,
+            duplicates,
+        })
+    }
+}
+
+/// Files we know how to embed. Other files in the vault, like scripts or
+/// archives, are there for some other reason, so we leave them alone.
+fn is_attachment(item: &VaultItem) -> bool {
+    matches!(
+        item.file().contents,
+        Contents::Image {} | Contents::Audio {} | Contents::Video {} | Contents::Pdf {}
+    )
+}
+
+/// Splits `ids` into groups whose files have the same bytes.
+fn group_by_bytes<'v>(
+    vault: &Vault,
+    ids: Vec<&'v VaultItemId>,
+) -> Result<Vec<Vec<&'v VaultItemId>>, VaultError> {
+    let mut groups: Vec<(Vec<u8>, Vec<&VaultItemId>)> = vec![];
+    for id in ids {
+        let bytes = vault.storage().read(&vault.absolute_path_to_item(id))?;
+        match groups
+            .iter_mut()
+            .find(|(group_bytes, _)| *group_bytes == bytes)
+        {
+            Some((_, group)) => group.push(id),
+            None => groups.push((bytes, vec![id])),
+        }
+    }
+
+    Ok(groups.into_iter().map(|(_, group)| group).collect())
+}
+
+impl Display for AttachmentReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.orphans.is_empty() {
+            writeln!(f, "Orphaned attachments:")?;
+            for id in &self.orphans {
+                writeln!(f, "  {}", id.path_from_vault_root())?;
+            }
+        }
+
+        if !self.duplicate_groups.is_empty() {
+            writeln!(f, "Duplicate attachments:")?;
+            for group in &self.duplicate_groups {
+                writeln!(
+                    f,
+                    "  {} ({} bytes, kept)",
+                    group.canonical.path_from_vault_root(),
+                    group.size
+                )?;
+                for id in &group.duplicates {
+                    writeln!(f, "    {}", id.path_from_vault_root())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::obsidian::*;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_attachment_cleanup() {
+        let older = SystemTime::UNIX_EPOCH;
+        let newer = older + Duration::from_secs(60);
+        let mut vault = Vault::from_files([
+            ("attachments/sunrise.png", "sunrise bytes", older),
+            ("Pasted image 20230514.png", "sunrise bytes", newer),
+            ("Pasted image 20230601.png", "sunrise bytes", newer),
+            ("attachments/unused.png", "unused bytes", older),
+            ("attachments/moon.png", "moon bytes", older),
+            ("scripts/cleanup.js", "unused bytes", older),
+            ("Morning.md", "![[sunrise.png]]\n![[moon.png]]", older),
+            (
+                "Haiku.md",
+                "![[Pasted image 20230514.png|300]] and [pasted](Pasted%20image%2020230601.png)",
+                older,
+            ),
+        ])
+        .unwrap();
+
+        let report = vault.attachment_report().unwrap();
+        assert_eq!(
+            report.orphans,
+            vec![VaultItemId::from("attachments/unused.png")]
+        );
+        assert_eq!(report.duplicate_groups.len(), 1);
+        let group = &report.duplicate_groups[0];
+        assert_eq!(
+            group.canonical,
+            VaultItemId::from("attachments/sunrise.png")
+        );
+        assert_eq!(
+            group.duplicates,
+            vec![
+                VaultItemId::from("Pasted image 20230514.png"),
+                VaultItemId::from("Pasted image 20230601.png"),
+            ]
+        );
+
+        let plan = report.cleanup_plan(&vault);
+        plan.apply(&mut vault).unwrap();
+
+        let haiku = vault
+            .item(&VaultItemId::from("Haiku.md"))
+            .unwrap()
+            .try_into_page()
+            .unwrap();
+        assert_eq!(
+            haiku.contents,
+            "![[sunrise.png|300]] and [pasted](attachments/sunrise.png)"
+        );
+        assert!(vault.item_at_path("Pasted image 20230514.png").is_none());
+        assert!(vault.item_at_path("attachments/unused.png").is_none());
+        assert!(vault.item_at_path("scripts/cleanup.js").is_some());
+        assert!(vault.attachment_report().unwrap().is_empty());
+    }
+}
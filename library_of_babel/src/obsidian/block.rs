@@ -0,0 +1,734 @@
+use super::Frontmatter;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::ops::Range;
+
+/// The block structure of a page: headings, paragraphs, lists, code
+/// blocks, blockquotes and callouts. Every block knows where it is in the
+/// text it was parsed from, so we can work with "the section under
+/// heading X" or "the list item with block id Y" without re-scanning.
+///
+/// This only covers what Obsidian vaults tend to use. Setext headings,
+/// indented code blocks and HTML blocks are parsed as paragraphs.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct BlockTree {
+    pub blocks: Vec<Block>,
+    /// The length of the text the tree was parsed from.
+    pub len: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Block {
+    pub kind: BlockKind,
+    /// Where the block is in the text it was parsed from. For blocks inside
+    /// blockquotes and lists, it starts after the `>` or list marker.
+    pub range: Range<usize>,
+    /// From a trailing `^block-id`, which `[[Page#^block-id]]` links point at.
+    pub block_id: Option<String>,
+    pub children: Vec<Block>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum BlockKind {
+    Frontmatter,
+    Heading {
+        level: usize,
+        text: String,
+    },
+    Paragraph,
+    /// A run of list items. Its children are `ListItem`s.
+    List {
+        ordered: bool,
+    },
+    /// Its children are the blocks inside the item, starting with the
+    /// item's own text.
+    ListItem {
+        /// Like `-`, `*` or `1.`.
+        marker: String,
+        /// The character between the brackets of a task, like `x` in
+        /// `- [x] Done`. `None` if the item isn't a task.
+        task_status: Option<char>,
+    },
+    CodeBlock {
+        /// The text after the opening fence, like `rust` or `dataview`.
+        info: String,
+    },
+    Blockquote,
+    /// A blockquote that starts with `[!type]`, like `> [!note] Title`.
+    Callout {
+        /// Lowercase, like `note` or `warning`.
+        callout_type: String,
+        title: Option<String>,
+        /// `Some(true)` for `[!note]-`, `Some(false)` for `[!note]+`, and
+        /// `None` if the callout can't be folded.
+        folded: Option<bool>,
+    },
+    ThematicBreak,
+}
+
+/// A heading and everything under it, up to the next heading at the same
+/// level or higher.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub heading: String,
+    pub level: usize,
+    pub heading_range: Range<usize>,
+    pub range: Range<usize>,
+}
+
+lazy_static! {
+    static ref match_list_markers: Regex =
+        Regex::new(r"^([-*+]|\d{1,9}[.)])(?:[ \t]+|$)").expect("Error compiling regex.");
+    static ref match_tasks: Regex =
+        Regex::new(r"^\[(.)\](?:[ \t]|$)").expect("Error compiling regex.");
+    static ref match_callouts: Regex =
+        Regex::new(r"^\[!([^\]]+)\]([+-]?)[ \t]*(.*)$").expect("Error compiling regex.");
+    static ref match_block_ids: Regex =
+        Regex::new(r"(?:^|\s)\^([A-Za-z0-9-]+)\s*$").expect("Error compiling regex.");
+}
+
+impl BlockTree {
+    pub fn parse(contents: &str) -> BlockTree {
+        let mut blocks = vec![];
+        let mut body_start = 0;
+
+        if let Some(frontmatter) = Frontmatter::parse(contents) {
+            body_start = frontmatter.body_offset();
+            blocks.push(Block::new(BlockKind::Frontmatter, frontmatter.range));
+        }
+
+        let lines = split_lines(contents, body_start);
+        blocks.extend(parse_blocks(contents, &lines));
+
+        BlockTree {
+            blocks,
+            len: contents.len(),
+        }
+    }
+
+    /// Every block, parents before their children.
+    pub fn iter(&self) -> impl Iterator<Item = &Block> {
+        let mut stack: Vec<&Block> = self.blocks.iter().rev().collect();
+        std::iter::from_fn(move || {
+            let block = stack.pop()?;
+            stack.extend(block.children.iter().rev());
+            Some(block)
+        })
+    }
+
+    /// Code blocks and inline code. Links and tags inside them don't count.
+    pub fn code_ranges(&self, contents: &str) -> Vec<Range<usize>> {
+        let mut ranges = vec![];
+        for block in self.iter() {
+            match &block.kind {
+                BlockKind::CodeBlock { .. } => ranges.push(block.range.clone()),
+                BlockKind::Paragraph | BlockKind::Heading { .. } => {
+                    let text = &contents[block.range.clone()];
+                    ranges.extend(inline_code_ranges(text, block.range.start));
+                }
+                _ => {}
+            }
+        }
+        ranges
+    }
+
+    pub fn is_in_code(code_ranges: &[Range<usize>], offset: usize) -> bool {
+        code_ranges.iter().any(|range| range.contains(&offset))
+    }
+
+    /// Sections for the page's top-level headings, in order.
+    pub fn sections(&self) -> Vec<Section> {
+        let headings: Vec<_> = self
+            .blocks
+            .iter()
+            .filter_map(|block| match &block.kind {
+                BlockKind::Heading { level, text } => Some((*level, text, &block.range)),
+                _ => None,
+            })
+            .collect();
+
+        headings
+            .iter()
+            .enumerate()
+            .map(|(index, (level, text, heading_range))| {
+                let end = headings[index + 1..]
+                    .iter()
+                    .find(|(next_level, _, _)| next_level <= level)
+                    .map_or(self.len, |(_, _, next_range)| next_range.start);
+
+                Section {
+                    heading: text.to_string(),
+                    level: *level,
+                    heading_range: (*heading_range).clone(),
+                    range: heading_range.start..end,
+                }
+            })
+            .collect()
+    }
+
+    /// Finds a section by its heading, like `["Early life"]`, or by a path of
+    /// nested headings, like `["Early life", "School"]`, the way links like
+    /// `[[Richard Feynman#Early life#School]]` do. Headings further down the
+    /// path don't have to be directly under the ones before them, and case
+    /// doesn't matter.
+    pub fn section(&self, heading_path: &[impl AsRef<str>]) -> Option<Section> {
+        let sections = self.sections();
+        let mut current: Option<&Section> = None;
+
+        for heading in heading_path {
+            let heading = heading.as_ref().trim().to_lowercase();
+            current = Some(sections.iter().find(|section| {
+                let is_inside_current = current.is_none_or(|current| {
+                    section.level > current.level
+                        && current.range.start < section.range.start
+                        && section.range.start < current.range.end
+                });
+                is_inside_current && section.heading.to_lowercase() == heading
+            })?);
+        }
+
+        current.cloned()
+    }
+
+    /// The outermost block marked `^block_id`.
+    pub fn find_block(&self, block_id: &str) -> Option<&Block> {
+        self.iter()
+            .find(|block| block.block_id.as_deref() == Some(block_id))
+    }
+}
+
+impl Block {
+    fn new(kind: BlockKind, range: Range<usize>) -> Block {
+        Block {
+            kind,
+            range,
+            block_id: None,
+            children: vec![],
+        }
+    }
+}
+
+/// A line of text, minus whatever blockquote or list markers the blocks
+/// around it use.
+#[derive(Debug, Clone, Copy)]
+struct Line {
+    start: usize,
+    /// Before the line break.
+    end: usize,
+}
+
+impl Line {
+    fn text<'s>(&self, source: &'s str) -> &'s str {
+        &source[self.start..self.end]
+    }
+
+    fn is_blank(&self, source: &str) -> bool {
+        self.text(source).trim().is_empty()
+    }
+
+    /// Drops up to `columns` columns of indentation. Tabs count as four.
+    fn dedent(&self, source: &str, columns: usize) -> Line {
+        let mut removed_columns = 0;
+        let mut removed_bytes = 0;
+        for character in self.text(source).chars() {
+            if removed_columns >= columns {
+                break;
+            }
+            match character {
+                ' ' => removed_columns += 1,
+                '\t' => removed_columns += 4,
+                _ => break,
+            }
+            removed_bytes += 1;
+        }
+
+        Line {
+            start: self.start + removed_bytes,
+            end: self.end,
+        }
+    }
+}
+
+fn split_lines(source: &str, from: usize) -> Vec<Line> {
+    let mut lines = vec![];
+    let mut line_start = from;
+
+    for line in source[from..].split_inclusive('\n') {
+        let text = line.trim_end_matches(['\n', '\r']);
+        lines.push(Line {
+            start: line_start,
+            end: line_start + text.len(),
+        });
+        line_start += line.len();
+    }
+
+    lines
+}
+
+fn indentation(text: &str) -> usize {
+    text.chars()
+        .take_while(|character| *character == ' ' || *character == '\t')
+        .map(|character| if character == '\t' { 4 } else { 1 })
+        .sum()
+}
+
+fn range_of(lines: &[Line]) -> Range<usize> {
+    match (lines.first(), lines.last()) {
+        (Some(first), Some(last)) => first.start..last.end,
+        _ => 0..0,
+    }
+}
+
+fn parse_blocks(source: &str, lines: &[Line]) -> Vec<Block> {
+    let mut blocks: Vec<Block> = vec![];
+    let mut index = 0;
+
+    while index < lines.len() {
+        let line = lines[index];
+        if line.is_blank(source) {
+            index += 1;
+            continue;
+        }
+
+        let text = line.text(source).trim_start();
+        let (block, line_count) = if let Some(fence) = opening_fence(text) {
+            parse_code_block(source, &lines[index..], fence)
+        } else if let Some(heading) = parse_heading(source, line) {
+            (heading, 1)
+        } else if is_thematic_break(text) {
+            (
+                Block::new(BlockKind::ThematicBreak, line.start..line.end),
+                1,
+            )
+        } else if text.starts_with('>') {
+            parse_blockquote(source, &lines[index..])
+        } else if match_list_markers.is_match(text) {
+            parse_list(source, &lines[index..])
+        } else {
+            parse_paragraph(source, &lines[index..])
+        };
+        index += line_count;
+
+        // A block id on a line of its own belongs to the block before it.
+        let is_lone_block_id = block.kind == BlockKind::Paragraph
+            && source[block.range.clone()].trim().starts_with('^');
+        match blocks.last_mut() {
+            Some(previous) if is_lone_block_id && block.block_id.is_some() => {
+                previous.block_id = block.block_id;
+            }
+            _ => blocks.push(block),
+        }
+    }
+
+    blocks
+}
+
+fn opening_fence(text: &str) -> Option<&str> {
+    let fence_character = text.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let fence_length = text
+        .chars()
+        .take_while(|character| *character == fence_character)
+        .count();
+    (fence_length >= 3).then(|| &text[..fence_length])
+}
+
+fn parse_code_block(source: &str, lines: &[Line], fence: &str) -> (Block, usize) {
+    let info = lines[0].text(source).trim_start()[fence.len()..].trim();
+    let fence_character = fence.chars().next().unwrap_or('`');
+
+    // An unclosed code block runs to the end of its container.
+    let closing_index = lines[1..]
+        .iter()
+        .position(|line| {
+            let text = line.text(source).trim();
+            text.len() >= fence.len() && text.chars().all(|character| character == fence_character)
+        })
+        .map_or(lines.len() - 1, |position| position + 1);
+
+    let block = Block::new(
+        BlockKind::CodeBlock {
+            info: info.to_string(),
+        },
+        range_of(&lines[..=closing_index]),
+    );
+    (block, closing_index + 1)
+}
+
+fn parse_heading(source: &str, line: Line) -> Option<Block> {
+    let text = line.text(source).trim_start();
+    let level = text
+        .chars()
+        .take_while(|character| *character == '#')
+        .count();
+    let rest = &text[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return None;
+    }
+
+    // `## Heading ##` has an optional closing sequence.
+    let heading_text = rest.trim();
+    let heading_text = match heading_text.trim_end_matches('#') {
+        without_closing if without_closing.ends_with([' ', '\t']) || without_closing.is_empty() => {
+            without_closing.trim()
+        }
+        _ => heading_text,
+    };
+
+    let kind = BlockKind::Heading {
+        level,
+        text: heading_text.to_string(),
+    };
+    Some(Block::new(kind, line.start..line.end))
+}
+
+fn is_thematic_break(text: &str) -> bool {
+    let characters: Vec<char> = text
+        .chars()
+        .filter(|character| !character.is_whitespace())
+        .collect();
+
+    characters.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|marker| characters.iter().all(|character| character == marker))
+}
+
+fn parse_blockquote(source: &str, lines: &[Line]) -> (Block, usize) {
+    let quoted_lines: Vec<Line> = lines
+        .iter()
+        .take_while(|line| line.text(source).trim_start().starts_with('>'))
+        .map(|line| {
+            let text = line.text(source);
+            let marker_end = text.len() - text.trim_start().len() + 1;
+            let after_marker = &text[marker_end..];
+            let space = usize::from(after_marker.starts_with([' ', '\t']));
+            Line {
+                start: line.start + marker_end + space,
+                end: line.end,
+            }
+        })
+        .collect();
+    let line_count = quoted_lines.len();
+    let range = lines[0].start..lines[line_count - 1].end;
+
+    let first_line = quoted_lines[0].text(source).trim();
+    let block = match match_callouts.captures(first_line) {
+        Some(captures) => {
+            let title = captures[3].trim();
+            let kind = BlockKind::Callout {
+                callout_type: captures[1].trim().to_lowercase(),
+                title: (!title.is_empty()).then(|| title.to_string()),
+                folded: match &captures[2] {
+                    "-" => Some(true),
+                    "+" => Some(false),
+                    _ => None,
+                },
+            };
+            Block {
+                children: parse_blocks(source, &quoted_lines[1..]),
+                ..Block::new(kind, range)
+            }
+        }
+        None => Block {
+            children: parse_blocks(source, &quoted_lines),
+            ..Block::new(BlockKind::Blockquote, range)
+        },
+    };
+
+    (block, line_count)
+}
+
+fn is_ordered_marker(marker: &str) -> bool {
+    marker.ends_with(['.', ')'])
+}
+
+fn parse_list(source: &str, lines: &[Line]) -> (Block, usize) {
+    let list_indentation = indentation(lines[0].text(source));
+    let first_marker = list_marker(source, lines[0]).unwrap_or("-");
+    let ordered = is_ordered_marker(first_marker);
+
+    let mut items = vec![];
+    let mut index = 0;
+    loop {
+        let (item, line_count) = parse_list_item(source, &lines[index..]);
+        items.push(item);
+        index += line_count;
+
+        // Blank lines between items don't end the list.
+        let next_item_index = (index..lines.len()).find(|&i| !lines[i].is_blank(source));
+        let continues = next_item_index.is_some_and(|i| {
+            indentation(lines[i].text(source)) == list_indentation
+                && list_marker(source, lines[i])
+                    .is_some_and(|marker| is_ordered_marker(marker) == ordered)
+                && !is_thematic_break(lines[i].text(source).trim_start())
+        });
+
+        match next_item_index {
+            Some(next_item_index) if continues => index = next_item_index,
+            _ => break,
+        }
+    }
+
+    let range = items[0].range.start..items[items.len() - 1].range.end;
+    let block = Block {
+        children: items,
+        ..Block::new(BlockKind::List { ordered }, range)
+    };
+    (block, index)
+}
+
+fn list_marker(source: &str, line: Line) -> Option<&str> {
+    let text = line.text(source).trim_start();
+    let captures = match_list_markers.captures(text)?;
+    captures.get(1).map(|marker| marker.as_str())
+}
+
+fn parse_list_item(source: &str, lines: &[Line]) -> (Block, usize) {
+    let first_line = lines[0];
+    let text = first_line.text(source);
+    let item_indentation = indentation(text);
+    let leading_whitespace = text.len() - text.trim_start().len();
+
+    let marker_captures = match_list_markers
+        .captures(&text[leading_whitespace..])
+        .expect("parse_list_item is only called on list items.");
+    let marker = marker_captures[1].to_string();
+    let marker_length = marker_captures[0].len();
+    let content_indentation = item_indentation + marker_length;
+
+    let mut content_lines = vec![Line {
+        start: first_line.start + leading_whitespace + marker_length,
+        end: first_line.end,
+    }];
+
+    // Lines indented past the marker belong to the item, including blank
+    // lines between them.
+    let mut line_count = 1;
+    for (index, line) in lines.iter().enumerate().skip(1) {
+        if line.is_blank(source) {
+            continue;
+        }
+        if indentation(line.text(source)) <= item_indentation {
+            break;
+        }
+        for blank_or_content_line in &lines[line_count..=index] {
+            content_lines.push(blank_or_content_line.dedent(source, content_indentation));
+        }
+        line_count = index + 1;
+    }
+
+    let first_content = content_lines[0].text(source);
+    let task_status = match_tasks
+        .captures(first_content)
+        .and_then(|captures| captures[1].chars().next());
+
+    let children = parse_blocks(source, &content_lines);
+    let block_id = match children.first() {
+        Some(Block {
+            kind: BlockKind::Paragraph,
+            block_id,
+            ..
+        }) => block_id.clone(),
+        _ => None,
+    };
+
+    let block = Block {
+        kind: BlockKind::ListItem {
+            marker,
+            task_status,
+        },
+        range: first_line.start + leading_whitespace..content_lines[content_lines.len() - 1].end,
+        block_id,
+        children,
+    };
+    (block, line_count)
+}
+
+fn starts_another_block(source: &str, line: Line) -> bool {
+    let text = line.text(source).trim_start();
+    opening_fence(text).is_some()
+        || parse_heading(source, line).is_some()
+        || is_thematic_break(text)
+        || text.starts_with('>')
+        || match_list_markers.is_match(text)
+}
+
+fn parse_paragraph(source: &str, lines: &[Line]) -> (Block, usize) {
+    let line_count = 1 + lines[1..]
+        .iter()
+        .take_while(|line| !line.is_blank(source) && !starts_another_block(source, **line))
+        .count();
+
+    let paragraph_lines = &lines[..line_count];
+    let last_line = paragraph_lines[line_count - 1].text(source);
+    let block_id = match_block_ids
+        .captures(last_line)
+        .map(|captures| captures[1].to_string());
+
+    let block = Block {
+        block_id,
+        ..Block::new(BlockKind::Paragraph, range_of(paragraph_lines))
+    };
+    (block, line_count)
+}
+
+/// Code spans like `` `[[Not a link]]` ``, where the closing run of
+/// backticks matches the opening one.
+fn inline_code_ranges(text: &str, offset: usize) -> Vec<Range<usize>> {
+    let bytes = text.as_bytes();
+    let mut ranges = vec![];
+    let mut index = 0;
+
+    while index < bytes.len() {
+        if bytes[index] != b'`' {
+            index += 1;
+            continue;
+        }
+
+        let run_start = index;
+        while index < bytes.len() && bytes[index] == b'`' {
+            index += 1;
+        }
+        let run_length = index - run_start;
+
+        let closing_run = text[index..]
+            .match_indices(&"`".repeat(run_length))
+            .map(|(closing_offset, _)| index + closing_offset)
+            .find(|&start| {
+                let end = start + run_length;
+                bytes[start - 1] != b'`' && bytes.get(end) != Some(&b'`')
+            });
+
+        if let Some(closing_start) = closing_run {
+            let end = closing_start + run_length;
+            ranges.push(offset + run_start..offset + end);
+            index = end;
+        }
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = "---
+tags: [people]
+---
+# Richard Feynman
+
+He liked `[[code]]`. ^intro
+
+## Early life
+
+- [x] Born in Queens
+\t- Far Rockaway ^far-rockaway
+- Went to MIT
+
+> [!quote]- Favorite quote
+> What I cannot create, I do not understand.
+
+```
+## Not a heading
+```
+
+## Physics
+
+---
+";
+
+    #[test]
+    fn test_parse() {
+        let tree = BlockTree::parse(PAGE);
+        let kinds: Vec<_> = tree
+            .blocks
+            .iter()
+            .map(|block| std::mem::discriminant(&block.kind))
+            .collect();
+        let expected = [
+            BlockKind::Frontmatter,
+            BlockKind::Heading {
+                level: 1,
+                text: String::new(),
+            },
+            BlockKind::Paragraph,
+            BlockKind::Heading {
+                level: 2,
+                text: String::new(),
+            },
+            BlockKind::List { ordered: false },
+            BlockKind::Callout {
+                callout_type: String::new(),
+                title: None,
+                folded: None,
+            },
+            BlockKind::CodeBlock {
+                info: String::new(),
+            },
+            BlockKind::Heading {
+                level: 2,
+                text: String::new(),
+            },
+            BlockKind::ThematicBreak,
+        ];
+        let expected: Vec<_> = expected.iter().map(std::mem::discriminant).collect();
+        assert_eq!(kinds, expected);
+
+        let list = &tree.blocks[4];
+        assert_eq!(list.children.len(), 2);
+        let first_item = &list.children[0];
+        assert!(matches!(
+            first_item.kind,
+            BlockKind::ListItem {
+                task_status: Some('x'),
+                ..
+            }
+        ));
+        assert_eq!(
+            &PAGE[first_item.range.clone()],
+            "- [x] Born in Queens\n\t- Far Rockaway ^far-rockaway"
+        );
+
+        let callout = &tree.blocks[5];
+        assert_eq!(
+            callout.kind,
+            BlockKind::Callout {
+                callout_type: "quote".to_string(),
+                title: Some("Favorite quote".to_string()),
+                folded: Some(true),
+            }
+        );
+        assert_eq!(
+            &PAGE[callout.children[0].range.clone()],
+            "What I cannot create, I do not understand."
+        );
+
+        assert_eq!(tree.blocks[2].block_id.as_deref(), Some("intro"));
+        let nested_item = tree.find_block("far-rockaway").unwrap();
+        assert_eq!(
+            &PAGE[nested_item.range.clone()],
+            "- Far Rockaway ^far-rockaway"
+        );
+    }
+
+    #[test]
+    fn test_code_ranges() {
+        let tree = BlockTree::parse(PAGE);
+        let code: Vec<_> = tree
+            .code_ranges(PAGE)
+            .into_iter()
+            .map(|range| &PAGE[range])
+            .collect();
+        assert_eq!(code, vec!["`[[code]]`", "```\n## Not a heading\n```"]);
+    }
+
+    #[test]
+    fn test_section() {
+        let tree = BlockTree::parse(PAGE);
+        let section = tree.section(&["Richard Feynman", "early life"]).unwrap();
+        assert!(PAGE[section.range.clone()].starts_with("## Early life\n"));
+        assert!(PAGE[section.range.clone()].ends_with("```\n\n"));
+        assert!(tree.section(&["Physics", "Early life"]).is_none());
+    }
+}
@@ -0,0 +1,369 @@
+use super::page::replace_ranges_in;
+use super::*;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// An Obsidian canvas: cards, notes, web pages and groups laid out on a
+/// board, with arrows between them. Stored as JSON in a `.canvas` file.
+#[derive(Debug, Clone)]
+pub struct Canvas {
+    pub id: VaultItemId,
+    pub file: File,
+    /// The canvas's JSON, exactly as it is in the file.
+    pub contents: String,
+    /// What the file looked like when we loaded or last saved it.
+    pub disk_state: DiskState,
+    /// Nodes we can't make sense of, like ones from newer versions of
+    /// Obsidian, are left out, but they're still kept in `contents`.
+    pub nodes: Vec<CanvasNode>,
+    pub edges: Vec<CanvasEdge>,
+    /// Links in text nodes, and the files that file nodes show.
+    pub links: Vec<CanvasLink>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+pub struct CanvasNode {
+    pub id: String,
+    #[serde(flatten)]
+    pub kind: CanvasNodeKind,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    /// One of Obsidian's preset colors, `"1"` to `"6"`, or a hex color.
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum CanvasNodeKind {
+    /// A card with Markdown in it.
+    Text { text: String },
+    /// A note, image or other file from the vault. `file` is its path from
+    /// the vault root, and `subpath` is a heading or block in it, like
+    /// `#Early life`.
+    File {
+        file: String,
+        #[serde(default)]
+        subpath: Option<String>,
+    },
+    /// A web page.
+    Link { url: String },
+    /// A box around other nodes.
+    Group {
+        #[serde(default)]
+        label: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanvasEdge {
+    pub id: String,
+    pub from_node: String,
+    /// `top`, `right`, `bottom` or `left`.
+    #[serde(default)]
+    pub from_side: Option<String>,
+    pub to_node: String,
+    #[serde(default)]
+    pub to_side: Option<String>,
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+/// A link in a canvas, along with the node it's in.
+#[derive(Debug, Clone)]
+pub struct CanvasLink {
+    pub node_id: String,
+    /// File nodes count as embeds, since they show the file. Their
+    /// `link.text` is the path in the node's `file`.
+    pub link: Link,
+    /// Where the link is in its text node's text. `None` for file nodes.
+    pub range: Option<Range<usize>>,
+}
+
+impl Canvas {
+    /// A canvas that isn't valid JSON comes out empty, like it does in
+    /// Obsidian.
+    pub fn parse(link_resolver: &LinkResolver, file: File, contents: String) -> Canvas {
+        let id = VaultItemId::from_file(&file);
+        let json: Value = serde_json::from_str(&contents).unwrap_or(Value::Null);
+
+        let nodes: Vec<CanvasNode> = parse_list(&json, "nodes");
+        let edges: Vec<CanvasEdge> = parse_list(&json, "edges");
+        let links = nodes
+            .iter()
+            .flat_map(|node| links_in_node(node, &id, link_resolver))
+            .collect();
+
+        let disk_state = DiskState {
+            modified_at: file.modified_at,
+            content_hash: content_hash(contents.as_bytes()),
+        };
+
+        Canvas {
+            id,
+            file,
+            contents,
+            disk_state,
+            nodes,
+            edges,
+            links,
+        }
+    }
+
+    pub fn node(&self, node_id: &str) -> Option<&CanvasNode> {
+        self.nodes.iter().find(|node| node.id == node_id)
+    }
+
+    /// Replaces the canvas's contents and re-parses it. Doesn't touch the
+    /// file on disk.
+    pub fn set_contents(&mut self, contents: String, link_resolver: &LinkResolver) {
+        self.file.contents = Contents::Canvas {
+            text: contents.clone(),
+        };
+        let disk_state = self.disk_state;
+        *self = Canvas::parse(link_resolver, self.file.clone(), contents);
+        self.disk_state = disk_state;
+    }
+
+    /// Rewrites links in the canvas. Each replacement is an index into
+    /// `links` and the new text for that link: a whole link like
+    /// `[[Richard Feynman]]` in text nodes, or a path from the vault root
+    /// in file nodes.
+    ///
+    /// Everything else in the JSON is kept as it was, but it's written
+    /// back out the way Obsidian formats it.
+    pub fn replace_links(
+        &mut self,
+        replacements: Vec<(usize, String)>,
+        link_resolver: &LinkResolver,
+    ) {
+        let Ok(mut json) = serde_json::from_str::<Value>(&self.contents) else {
+            return;
+        };
+
+        let mut text_replacements: HashMap<&str, Vec<(Range<usize>, String)>> = HashMap::new();
+        let mut file_replacements: HashMap<&str, String> = HashMap::new();
+        for (index, new_text) in replacements {
+            let Some(canvas_link) = self.links.get(index) else {
+                continue;
+            };
+            match &canvas_link.range {
+                Some(range) => text_replacements
+                    .entry(&canvas_link.node_id)
+                    .or_default()
+                    .push((range.clone(), new_text)),
+                None => {
+                    file_replacements.insert(&canvas_link.node_id, new_text);
+                }
+            }
+        }
+
+        let nodes = json
+            .get_mut("nodes")
+            .and_then(Value::as_array_mut)
+            .into_iter()
+            .flatten();
+        for node in nodes {
+            let Some(node_id) = node.get("id").and_then(Value::as_str) else {
+                continue;
+            };
+
+            if let Some(new_path) = file_replacements.remove(node_id) {
+                node["file"] = Value::String(new_path);
+            } else if let Some(replacements) = text_replacements.remove(node_id) {
+                let Some(text) = node.get("text").and_then(Value::as_str) else {
+                    continue;
+                };
+                node["text"] = Value::String(replace_ranges_in(text, replacements));
+            }
+        }
+
+        self.set_contents(to_canvas_json(&json), link_resolver);
+    }
+
+    /// True if `contents` has changed since the canvas was loaded or last saved.
+    pub fn is_dirty(&self) -> bool {
+        content_hash(self.contents.as_bytes()) != self.disk_state.content_hash
+    }
+
+    /// Writes the canvas to its file if it's dirty. Like `Page::save`, it
+    /// refuses if the file changed on disk since we loaded it.
+    pub fn save(&mut self, storage: &dyn VaultStorage) -> Result<(), VaultError> {
+        if !self.is_dirty() {
+            return Ok(());
+        }
+
+        self.disk_state = save_unless_changed_on_disk(
+            storage,
+            &self.file.absolute_path,
+            &self.disk_state,
+            self.contents.as_bytes(),
+        )?;
+        self.file.modified_at = self.disk_state.modified_at;
+        Ok(())
+    }
+
+    pub fn has_a_reference_to(&self, target_id: &VaultItemId) -> bool {
+        self.links
+            .iter()
+            .any(|canvas_link| canvas_link.link.refers_to(target_id))
+    }
+}
+
+/// The entries of the list at `key` that we can make sense of.
+fn parse_list<T: serde::de::DeserializeOwned>(json: &Value, key: &str) -> Vec<T> {
+    json.get(key)
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|value| serde_json::from_value(value.clone()).ok())
+        .collect()
+}
+
+fn links_in_node(
+    node: &CanvasNode,
+    canvas_id: &VaultItemId,
+    link_resolver: &LinkResolver,
+) -> Vec<CanvasLink> {
+    match &node.kind {
+        CanvasNodeKind::Text { text } => {
+            let code_ranges = BlockTree::parse(text).code_ranges(text);
+            LinkSpan::parse_reference_spans(text, &code_ranges, canvas_id, link_resolver)
+                .into_iter()
+                .map(|reference_span| CanvasLink {
+                    node_id: node.id.clone(),
+                    link: reference_span.link,
+                    range: Some(reference_span.span.range),
+                })
+                .collect()
+        }
+
+        CanvasNodeKind::File { file, subpath } => {
+            let subpath = subpath.as_deref().unwrap_or("");
+            let parts = LinkParts {
+                target: file.clone(),
+                ..LinkParts::from_destination(subpath)
+            };
+            let mut link = Link {
+                is_embed: true,
+                syntax: LinkSyntax::Wiki,
+                link_text: format!("{file}{subpath}"),
+                parts,
+                text: file.clone(),
+                vault_item_id: None,
+            };
+            link.resolve(canvas_id, link_resolver);
+
+            vec![CanvasLink {
+                node_id: node.id.clone(),
+                link,
+                range: None,
+            }]
+        }
+
+        CanvasNodeKind::Link { .. } | CanvasNodeKind::Group { .. } => vec![],
+    }
+}
+
+/// Formats JSON the way Obsidian writes canvases: indented with tabs.
+fn to_canvas_json(json: &Value) -> String {
+    let mut bytes = vec![];
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b"\t");
+    let mut serializer = serde_json::Serializer::with_formatter(&mut bytes, formatter);
+    serde::Serialize::serialize(json, &mut serializer).expect("JSON values always serialize.");
+    String::from_utf8(bytes).expect("serde_json always writes UTF-8.")
+}
+
+impl<'a> TryFrom<&'a VaultItem> for &'a Canvas {
+    type Error = ();
+
+    fn try_from(vault_item: &'a VaultItem) -> Result<Self, Self::Error> {
+        match vault_item {
+            VaultItem::Canvas(canvas) => Ok(canvas),
+            _ => Err(()),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a mut VaultItem> for &'a mut Canvas {
+    type Error = ();
+
+    fn try_from(vault_item: &'a mut VaultItem) -> Result<Self, Self::Error> {
+        match vault_item {
+            VaultItem::Canvas(canvas) => Ok(canvas),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::obsidian::*;
+    use std::time::SystemTime;
+
+    #[test]
+    fn test_canvas_links_follow_renames() {
+        let created_at = SystemTime::UNIX_EPOCH;
+        let canvas_json = r##"{
+	"nodes":[
+		{"id":"a","type":"text","text":"See [[Richard Feynman]].","x":0,"y":0,"width":250,"height":60},
+		{"id":"b","type":"file","file":"people/Richard Feynman.md","subpath":"#Books","x":300,"y":0,"width":400,"height":400,"color":"4"},
+		{"id":"c","type":"link","url":"https://obsidian.md","x":0,"y":100,"width":400,"height":400},
+		{"id":"d","type":"group","label":"Physics","x":-20,"y":-20,"width":800,"height":600},
+		{"id":"e","type":"someday","x":0,"y":0,"width":1,"height":1}
+	],
+	"edges":[
+		{"id":"ab","fromNode":"a","fromSide":"right","toNode":"b","toSide":"left","label":"about"}
+	]
+}"##;
+        let mut vault = Vault::from_files([
+            ("Physics.canvas", canvas_json, created_at),
+            ("people/Richard Feynman.md", "# Books\n", created_at),
+        ])
+        .unwrap();
+
+        let canvas_id = VaultItemId::from("Physics.canvas");
+        let canvas = vault.item(&canvas_id).unwrap().try_into_canvas().unwrap();
+        assert_eq!(canvas.nodes.len(), 4);
+        assert_eq!(canvas.edges[0].to_node, "b");
+        assert_eq!(
+            canvas.file.path_from_vault_root_without_extension,
+            "Physics"
+        );
+
+        let feynman_id = VaultItemId::from("people/Richard Feynman.md");
+        let backlinks = vault.backlinks(&feynman_id);
+        assert_eq!(backlinks.len(), 2);
+        assert!(backlinks.iter().all(|edge| edge.source == canvas_id));
+
+        vault
+            .rename_item(&feynman_id, "physicists/Dick Feynman.md")
+            .unwrap();
+
+        let canvas = vault.item(&canvas_id).unwrap().try_into_canvas().unwrap();
+        assert_eq!(
+            canvas.node("a").unwrap().kind,
+            CanvasNodeKind::Text {
+                text: "See [[Dick Feynman]].".to_string()
+            }
+        );
+        assert_eq!(
+            canvas.node("b").unwrap().kind,
+            CanvasNodeKind::File {
+                file: "physicists/Dick Feynman.md".to_string(),
+                subpath: Some("#Books".to_string())
+            }
+        );
+        assert!(canvas.contents.contains("\"someday\""));
+        assert!(!canvas.is_dirty());
+
+        let new_id = VaultItemId::from("physicists/Dick Feynman.md");
+        assert_eq!(vault.backlinks(&new_id).len(), 2);
+    }
+}
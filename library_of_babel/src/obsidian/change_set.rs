@@ -0,0 +1,414 @@
+use super::diff::unified_diff;
+use super::{Vault, VaultError, VaultItem, VaultItemId};
+use std::collections::HashMap;
+use std::fmt::Display;
+
+/// Changes to make to a vault, recorded without touching disk so a script
+/// can show what it's about to do before it does it. `apply` makes the
+/// changes in order and undoes the ones it already made if one fails.
+#[derive(Debug, Clone, Default)]
+pub struct VaultChangeSet {
+    pub changes: Vec<VaultChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VaultChange {
+    CreatePage {
+        id: VaultItemId,
+        contents: String,
+    },
+    /// Moves the item and fixes links to it, like `Vault::rename_item`.
+    MoveItem {
+        id: VaultItemId,
+        new_path_from_vault_root: String,
+    },
+    /// Replaces the page's contents. Also works for canvases, whose
+    /// contents are their JSON.
+    EditPage {
+        id: VaultItemId,
+        contents: String,
+    },
+    /// Deletes the item's file. Links to it become unresolved.
+    DeleteItem {
+        id: VaultItemId,
+    },
+}
+
+/// Applying a change set failed partway through. The changes before
+/// `failed_change` were undone, except for any in `rollback_errors`.
+#[derive(Debug)]
+pub struct ChangeSetError {
+    /// The index into `VaultChangeSet::changes` of the change that failed.
+    pub failed_change: usize,
+    pub error: VaultError,
+    pub rollback_errors: Vec<VaultError>,
+}
+
+impl Display for ChangeSetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "change {} failed: {}", self.failed_change, self.error)?;
+        if !self.rollback_errors.is_empty() {
+            write!(
+                f,
+                " ({} earlier changes couldn't be undone)",
+                self.rollback_errors.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ChangeSetError {}
+
+/// How to take back a change that's already been applied.
+enum Undo {
+    Delete {
+        id: VaultItemId,
+    },
+    MoveBack {
+        id: VaultItemId,
+        old_path_from_vault_root: String,
+        /// Pages and canvases whose links the move rewrote, keyed by their
+        /// ids after the move.
+        old_contents: Vec<(VaultItemId, String)>,
+    },
+    RestoreContents {
+        id: VaultItemId,
+        contents: String,
+    },
+    Recreate {
+        id: VaultItemId,
+        bytes: Vec<u8>,
+    },
+}
+
+impl VaultChangeSet {
+    pub fn new() -> VaultChangeSet {
+        VaultChangeSet::default()
+    }
+
+    pub fn create_page(&mut self, id: VaultItemId, contents: String) {
+        self.changes.push(VaultChange::CreatePage { id, contents });
+    }
+
+    /// `id` is where the item will be when this change is applied, which
+    /// might be somewhere an earlier change moved it to.
+    pub fn move_item(&mut self, id: VaultItemId, new_path_from_vault_root: String) {
+        self.changes.push(VaultChange::MoveItem {
+            id,
+            new_path_from_vault_root,
+        });
+    }
+
+    pub fn edit_page(&mut self, id: VaultItemId, contents: String) {
+        self.changes.push(VaultChange::EditPage { id, contents });
+    }
+
+    pub fn delete_item(&mut self, id: VaultItemId) {
+        self.changes.push(VaultChange::DeleteItem { id });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Whether an earlier change in this set creates `id`.
+    pub fn creates(&self, id: &VaultItemId) -> bool {
+        self.changes.iter().any(|change| match change {
+            VaultChange::CreatePage { id: created_id, .. } => created_id == id,
+            _ => false,
+        })
+    }
+
+    /// The ids of the pages this set creates, in order.
+    pub fn created_ids(&self) -> Vec<VaultItemId> {
+        self.changes
+            .iter()
+            .filter_map(|change| match change {
+                VaultChange::CreatePage { id, .. } => Some(id.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// A list of moves and deletions followed by a unified diff of every
+    /// created or edited page. Moves also rewrite links in other pages,
+    /// which the preview doesn't show.
+    pub fn preview(&self, vault: &Vault) -> String {
+        // What each page will look like by the time we get to each change.
+        let mut planned_contents: HashMap<VaultItemId, String> = HashMap::new();
+        // Where items that have been moved were originally.
+        let mut original_ids: HashMap<VaultItemId, VaultItemId> = HashMap::new();
+
+        let mut moves = String::new();
+        let mut deletions = String::new();
+        let mut diffs = String::new();
+
+        for change in &self.changes {
+            match change {
+                VaultChange::CreatePage { id, contents } => {
+                    let new_label = format!("b/{}", id.path_from_vault_root());
+                    diffs.push_str(&unified_diff("", contents, "/dev/null", &new_label));
+                    planned_contents.insert(id.clone(), contents.clone());
+                }
+
+                VaultChange::MoveItem {
+                    id,
+                    new_path_from_vault_root,
+                } => {
+                    let old_path = id.path_from_vault_root();
+                    moves.push_str(&format!("{old_path} -> {new_path_from_vault_root}\n"));
+
+                    let new_id = VaultItemId::from(new_path_from_vault_root.as_str());
+                    if let Some(contents) = planned_contents.remove(id) {
+                        planned_contents.insert(new_id.clone(), contents);
+                    }
+                    let original_id = original_ids.remove(id).unwrap_or_else(|| id.clone());
+                    original_ids.insert(new_id, original_id);
+                }
+
+                VaultChange::EditPage { id, contents } => {
+                    let old_contents = planned_contents.get(id).cloned().unwrap_or_else(|| {
+                        let original_id = original_ids.get(id).unwrap_or(id);
+                        match vault.item(original_id) {
+                            Some(VaultItem::Page(page)) => page.contents.clone(),
+                            Some(VaultItem::Canvas(canvas)) => canvas.contents.clone(),
+                            _ => String::new(),
+                        }
+                    });
+
+                    let path = id.path_from_vault_root();
+                    diffs.push_str(&unified_diff(
+                        &old_contents,
+                        contents,
+                        &format!("a/{path}"),
+                        &format!("b/{path}"),
+                    ));
+                    planned_contents.insert(id.clone(), contents.clone());
+                }
+
+                VaultChange::DeleteItem { id } => {
+                    deletions.push_str(&format!("{}\n", id.path_from_vault_root()));
+                    planned_contents.remove(id);
+                }
+            }
+        }
+
+        let mut lists = vec![];
+        if !moves.is_empty() {
+            lists.push(format!("Moves:\n{moves}"));
+        }
+        if !deletions.is_empty() {
+            lists.push(format!("Deletions:\n{deletions}"));
+        }
+        if !diffs.is_empty() {
+            lists.push(diffs);
+        }
+        lists.join("\n")
+    }
+
+    /// Makes every change in order. If one fails, undoes the changes before
+    /// it, newest first, and returns what went wrong.
+    pub fn apply(&self, vault: &mut Vault) -> Result<(), ChangeSetError> {
+        let mut undos = vec![];
+
+        for (index, change) in self.changes.iter().enumerate() {
+            match apply_change(vault, change) {
+                Ok(undo) => undos.push(undo),
+                Err(error) => {
+                    let rollback_errors = undos
+                        .into_iter()
+                        .rev()
+                        .filter_map(|undo| undo.apply(vault).err())
+                        .collect();
+
+                    return Err(ChangeSetError {
+                        failed_change: index,
+                        error,
+                        rollback_errors,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn apply_change(vault: &mut Vault, change: &VaultChange) -> Result<Undo, VaultError> {
+    match change {
+        VaultChange::CreatePage { id, contents } => {
+            let path = vault.absolute_path_to_item(id);
+            if vault.item(id).is_some() || vault.storage().exists(&path) {
+                return Err(VaultError::io(
+                    &path,
+                    std::io::ErrorKind::AlreadyExists.into(),
+                ));
+            }
+
+            vault.find_or_create_page(id.clone(), || contents.clone())?;
+            Ok(Undo::Delete { id: id.clone() })
+        }
+
+        VaultChange::MoveItem {
+            id,
+            new_path_from_vault_root,
+        } => {
+            let (new_id, old_contents) =
+                vault.rename_item_and_keep_old_contents(id, new_path_from_vault_root)?;
+            Ok(Undo::MoveBack {
+                id: new_id,
+                old_path_from_vault_root: id.path_from_vault_root().to_string(),
+                old_contents,
+            })
+        }
+
+        VaultChange::EditPage { id, contents } => {
+            let old_contents = match vault.item(id) {
+                Some(VaultItem::Page(page)) => page.contents.clone(),
+                Some(VaultItem::Canvas(canvas)) => {
+                    let old_contents = canvas.contents.clone();
+                    vault.edit_canvas(id, contents.clone())?;
+                    return Ok(Undo::RestoreContents {
+                        id: id.clone(),
+                        contents: old_contents,
+                    });
+                }
+                Some(_) => {
+                    return Err(VaultError::NotAPage {
+                        path: vault.absolute_path_to_item(id),
+                    })
+                }
+                None => {
+                    return Err(VaultError::io(
+                        &vault.absolute_path_to_item(id),
+                        std::io::ErrorKind::NotFound.into(),
+                    ))
+                }
+            };
+
+            vault.edit_page(id, contents.clone())?;
+            Ok(Undo::RestoreContents {
+                id: id.clone(),
+                contents: old_contents,
+            })
+        }
+
+        VaultChange::DeleteItem { id } => {
+            let bytes = vault.storage().read(&vault.absolute_path_to_item(id))?;
+            vault.delete_item(id)?;
+            Ok(Undo::Recreate {
+                id: id.clone(),
+                bytes,
+            })
+        }
+    }
+}
+
+impl Undo {
+    fn apply(self, vault: &mut Vault) -> Result<(), VaultError> {
+        match self {
+            Undo::Delete { id } => vault.delete_item(&id).map(|_| ()),
+
+            Undo::MoveBack {
+                id,
+                old_path_from_vault_root,
+                old_contents,
+            } => {
+                let old_id = vault.move_item(&id, &old_path_from_vault_root)?;
+                for (page_id, contents) in old_contents {
+                    let page_id = if page_id == id {
+                        old_id.clone()
+                    } else {
+                        page_id
+                    };
+                    match vault.item(&page_id) {
+                        Some(VaultItem::Canvas(_)) => vault.edit_canvas(&page_id, contents)?,
+                        _ => vault.edit_page(&page_id, contents)?,
+                    }
+                }
+                Ok(())
+            }
+
+            Undo::RestoreContents { id, contents } => match vault.item(&id) {
+                Some(VaultItem::Canvas(_)) => vault.edit_canvas(&id, contents),
+                _ => vault.edit_page(&id, contents),
+            },
+
+            Undo::Recreate { id, bytes } => vault.recreate_item(&id, &bytes),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::obsidian::*;
+    use std::collections::BTreeMap;
+    use std::time::SystemTime;
+
+    /// Every item's contents, in memory and in storage.
+    fn snapshot(vault: &Vault) -> BTreeMap<VaultItemId, (Option<String>, Vec<u8>)> {
+        vault
+            .items()
+            .map(|item| {
+                let in_memory = item.try_into_page().map(|page| page.contents.clone());
+                let on_disk = vault
+                    .storage()
+                    .read(&vault.absolute_path_to_item(item.id()))
+                    .unwrap();
+                (item.id().clone(), (in_memory, on_disk))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_failed_change_set_is_rolled_back() {
+        let created_at = SystemTime::UNIX_EPOCH;
+        let mut vault = Vault::from_files([
+            ("Richard Feynman.md", "Physicist.", created_at),
+            ("Reading.md", "I'm reading [[Richard Feynman]].", created_at),
+            ("Zettel.md", "See [[Reading]].", created_at),
+            ("Inbox.md", "Nothing yet.", created_at),
+        ])
+        .unwrap();
+
+        // Obsidian edits the page the last change touches.
+        let inbox_id = VaultItemId::from("Inbox.md");
+        vault
+            .storage()
+            .write(
+                &vault.absolute_path_to_item(&inbox_id),
+                b"Edited in Obsidian.",
+            )
+            .unwrap();
+        let before = snapshot(&vault);
+
+        let feynman_id = VaultItemId::from("Richard Feynman.md");
+        let mut change_set = VaultChangeSet::new();
+        change_set.create_page(VaultItemId::from("Ideas.md"), "[[Zettel]]".to_string());
+        change_set.move_item(feynman_id.clone(), "people/Dick Feynman.md".to_string());
+        change_set.edit_page(
+            VaultItemId::from("Zettel.md"),
+            "See [[Reading]] and [[Dick Feynman]].".to_string(),
+        );
+        change_set.delete_item(VaultItemId::from("Reading.md"));
+        change_set.edit_page(inbox_id.clone(), "Read more.".to_string());
+
+        let error = change_set.apply(&mut vault).unwrap_err();
+        assert_eq!(error.failed_change, 4);
+        assert!(matches!(error.error, VaultError::ChangedOnDisk { .. }));
+        assert!(error.rollback_errors.is_empty());
+
+        assert_eq!(snapshot(&vault), before);
+        assert!(vault.dirty_pages().next().is_none());
+        assert!(!vault
+            .storage()
+            .exists(&vault.absolute_path_to_item(&VaultItemId::from("Ideas.md"))));
+        assert_eq!(vault.backlinks(&feynman_id).len(), 1);
+        assert_eq!(vault.backlinks(&VaultItemId::from("Zettel.md")).len(), 0);
+
+        // The inbox didn't take the new text either.
+        let inbox = vault.item(&inbox_id).unwrap().try_into_page().unwrap();
+        assert_eq!(inbox.contents, "Nothing yet.");
+    }
+}
@@ -0,0 +1,189 @@
+/// How many unchanged lines to show around each change.
+const context_line_count: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiffLine<'s> {
+    Same(&'s str),
+    Removed(&'s str),
+    Added(&'s str),
+}
+
+impl DiffLine<'_> {
+    fn is_in_old(&self) -> bool {
+        !matches!(self, DiffLine::Added(_))
+    }
+
+    fn is_in_new(&self) -> bool {
+        !matches!(self, DiffLine::Removed(_))
+    }
+}
+
+/// A unified diff, like `diff -u` or `git diff` print. `old_label` and
+/// `new_label` go in the `---` and `+++` lines. Returns an empty string
+/// if nothing changed.
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let diff_lines = diff_lines(&old_lines, &new_lines);
+
+    let changed_indexes: Vec<usize> = diff_lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, DiffLine::Same(_)))
+        .map(|(index, _)| index)
+        .collect();
+
+    if changed_indexes.is_empty() {
+        return String::new();
+    }
+
+    let mut output = format!("--- {old_label}\n+++ {new_label}\n");
+    for (first_change, last_change) in group_changes(&changed_indexes) {
+        let start = first_change.saturating_sub(context_line_count);
+        let end = (last_change + context_line_count + 1).min(diff_lines.len());
+        let hunk = &diff_lines[start..end];
+
+        let old_before = diff_lines[..start]
+            .iter()
+            .filter(|line| line.is_in_old())
+            .count();
+        let new_before = diff_lines[..start]
+            .iter()
+            .filter(|line| line.is_in_new())
+            .count();
+        let old_count = hunk.iter().filter(|line| line.is_in_old()).count();
+        let new_count = hunk.iter().filter(|line| line.is_in_new()).count();
+
+        // Empty ranges start at the line before them, so creating a file is `-0,0`.
+        let old_start = old_before + usize::from(old_count > 0);
+        let new_start = new_before + usize::from(new_count > 0);
+        output.push_str(&format!(
+            "@@ -{old_start},{old_count} +{new_start},{new_count} @@\n"
+        ));
+
+        for line in hunk {
+            let (prefix, text) = match line {
+                DiffLine::Same(text) => (' ', text),
+                DiffLine::Removed(text) => ('-', text),
+                DiffLine::Added(text) => ('+', text),
+            };
+            output.push(prefix);
+            output.push_str(text);
+            output.push('\n');
+        }
+    }
+
+    output
+}
+
+/// Changes close enough that their context would overlap share a hunk.
+fn group_changes(changed_indexes: &[usize]) -> Vec<(usize, usize)> {
+    let mut groups: Vec<(usize, usize)> = vec![];
+    for &index in changed_indexes {
+        match groups.last_mut() {
+            Some((_, last_change)) if index - *last_change <= 2 * context_line_count + 1 => {
+                *last_change = index;
+            }
+            _ => groups.push((index, index)),
+        }
+    }
+    groups
+}
+
+/// A longest-common-subsequence diff. Most edits touch a few lines, so we
+/// skip the lines both sides start and end with before doing the
+/// quadratic part.
+fn diff_lines<'s>(old: &[&'s str], new: &[&'s str]) -> Vec<DiffLine<'s>> {
+    let prefix_length = old
+        .iter()
+        .zip(new)
+        .take_while(|(old_line, new_line)| old_line == new_line)
+        .count();
+    let suffix_length = old[prefix_length..]
+        .iter()
+        .rev()
+        .zip(new[prefix_length..].iter().rev())
+        .take_while(|(old_line, new_line)| old_line == new_line)
+        .count();
+
+    let old_middle = &old[prefix_length..old.len() - suffix_length];
+    let new_middle = &new[prefix_length..new.len() - suffix_length];
+
+    // lcs_lengths[i][j] is the length of the longest common subsequence
+    // of old_middle[i..] and new_middle[j..].
+    let mut lcs_lengths = vec![vec![0u32; new_middle.len() + 1]; old_middle.len() + 1];
+    for i in (0..old_middle.len()).rev() {
+        for j in (0..new_middle.len()).rev() {
+            lcs_lengths[i][j] = if old_middle[i] == new_middle[j] {
+                lcs_lengths[i + 1][j + 1] + 1
+            } else {
+                lcs_lengths[i + 1][j].max(lcs_lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines: Vec<DiffLine> = old[..prefix_length]
+        .iter()
+        .map(|line| DiffLine::Same(line))
+        .collect();
+
+    let (mut i, mut j) = (0, 0);
+    while i < old_middle.len() || j < new_middle.len() {
+        if i < old_middle.len() && j < new_middle.len() && old_middle[i] == new_middle[j] {
+            lines.push(DiffLine::Same(old_middle[i]));
+            i += 1;
+            j += 1;
+        } else if j < new_middle.len()
+            && (i == old_middle.len() || lcs_lengths[i][j + 1] > lcs_lengths[i + 1][j])
+        {
+            lines.push(DiffLine::Added(new_middle[j]));
+            j += 1;
+        } else {
+            lines.push(DiffLine::Removed(old_middle[i]));
+            i += 1;
+        }
+    }
+
+    lines.extend(
+        old[old.len() - suffix_length..]
+            .iter()
+            .map(|line| DiffLine::Same(line)),
+    );
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff() {
+        let old = "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten\n";
+        let new = "one\ntwo\nthree\nfour\n4.5\nfive\nsix\nseven\neight\nnine\n10\n";
+
+        let expected = "--- a/numbers.md
++++ b/numbers.md
+@@ -2,9 +2,10 @@
+ two
+ three
+ four
++4.5
+ five
+ six
+ seven
+ eight
+ nine
+-ten
++10
+";
+        assert_eq!(
+            unified_diff(old, new, "a/numbers.md", "b/numbers.md"),
+            expected
+        );
+        assert_eq!(unified_diff(old, old, "a", "b"), "");
+        assert_eq!(
+            unified_diff("", "new\n", "/dev/null", "b/new.md"),
+            "--- /dev/null\n+++ b/new.md\n@@ -0,0 +1,1 @@\n+new\n"
+        );
+    }
+}
@@ -0,0 +1,250 @@
+use super::{File, Link, LinkParts, Page, Vault, VaultItem, VaultItemId};
+use std::fmt::Display;
+use std::ops::Range;
+
+/// What an embed like `![[Note]]`, `![[Note#Heading]]` or
+/// `![[Note#^block-id]]` shows.
+#[derive(Debug, Clone)]
+pub enum Embed<'v> {
+    /// The embedded page, section or block, with any embeds inside it
+    /// expanded too. Page embeds leave out the frontmatter, and block
+    /// embeds leave out the `^block-id`.
+    Text(String),
+    /// Images, PDFs, canvases and other files that aren't pages.
+    Attachment(&'v File),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmbedError {
+    /// The embed doesn't point at anything in the vault.
+    Unresolved {
+        link_text: String,
+    },
+    HeadingNotFound {
+        id: VaultItemId,
+        heading_path: Vec<String>,
+    },
+    BlockNotFound {
+        id: VaultItemId,
+        block_id: String,
+    },
+    /// Expanding the embed would embed itself forever. `ids` are the pages
+    /// in the loop, ending with the one that embeds the first again.
+    Cycle {
+        ids: Vec<VaultItemId>,
+    },
+}
+
+impl Display for EmbedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EmbedError::Unresolved { link_text } => {
+                write!(f, "[[{link_text}]] doesn't point at anything in the vault.")
+            }
+            EmbedError::HeadingNotFound { id, heading_path } => write!(
+                f,
+                "{} doesn't have a heading #{}.",
+                id.path_from_vault_root(),
+                heading_path.join("#")
+            ),
+            EmbedError::BlockNotFound { id, block_id } => write!(
+                f,
+                "{} doesn't have a block ^{block_id}.",
+                id.path_from_vault_root()
+            ),
+            EmbedError::Cycle { ids } => {
+                let ids: Vec<&str> = ids.iter().map(VaultItemId::path_from_vault_root).collect();
+                write!(f, "These pages embed each other: {}.", ids.join(" → "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for EmbedError {}
+
+/// Expands embeds for `Vault::resolve_embed` and `Vault::expand_embeds`.
+/// Every page section we're in the middle of expanding is on the stack,
+/// so we notice when one embeds itself.
+pub(super) struct Transcluder<'v> {
+    vault: &'v Vault,
+    stack: Vec<(VaultItemId, Range<usize>)>,
+}
+
+impl<'v> Transcluder<'v> {
+    pub(super) fn new(vault: &'v Vault) -> Transcluder<'v> {
+        Transcluder {
+            vault,
+            stack: vec![],
+        }
+    }
+
+    pub(super) fn resolve(&mut self, link: &Link) -> Result<Embed<'v>, EmbedError> {
+        let unresolved = || EmbedError::Unresolved {
+            link_text: link.link_text.clone(),
+        };
+        let id = link.vault_item_id.as_ref().ok_or_else(unresolved)?;
+        let page = match self.vault.item(id).ok_or_else(unresolved)? {
+            VaultItem::Page(page) => page,
+            VaultItem::Canvas(canvas) => return Ok(Embed::Attachment(&canvas.file)),
+            VaultItem::NonPage { file, .. } => return Ok(Embed::Attachment(file)),
+        };
+
+        let range = embedded_range(page, &link.parts)?;
+        let text = self.expand(page, range)?;
+
+        match &link.parts.block_id {
+            Some(block_id) => {
+                let text = text.trim_end();
+                let text = text.strip_suffix(&format!("^{block_id}")).unwrap_or(text);
+                Ok(Embed::Text(text.trim_end().to_string()))
+            }
+            None => Ok(Embed::Text(text)),
+        }
+    }
+
+    /// The text in `range` of the page, with embeds expanded. Embeds that
+    /// are broken or point at attachments are left as they are.
+    pub(super) fn expand(
+        &mut self,
+        page: &'v Page,
+        range: Range<usize>,
+    ) -> Result<String, EmbedError> {
+        // The same page can show up more than once without a loop, like a
+        // page embedding one of its own sections, so we compare ranges too.
+        let start_of_cycle = self
+            .stack
+            .iter()
+            .position(|(id, stacked_range)| *id == page.id && *stacked_range == range);
+        if let Some(start_of_cycle) = start_of_cycle {
+            let mut ids: Vec<VaultItemId> = self.stack[start_of_cycle..]
+                .iter()
+                .map(|(id, _)| id.clone())
+                .collect();
+            ids.push(page.id.clone());
+            return Err(EmbedError::Cycle { ids });
+        }
+
+        self.stack.push((page.id.clone(), range.clone()));
+        let mut text = String::new();
+        let mut copied_up_to = range.start;
+
+        for reference_span in &page.reference_spans {
+            let span_range = reference_span.range();
+            if !reference_span.link.is_embed
+                || span_range.start < range.start
+                || span_range.end > range.end
+            {
+                continue;
+            }
+
+            match self.resolve(&reference_span.link) {
+                Ok(Embed::Text(embedded_text)) => {
+                    text.push_str(&page.contents[copied_up_to..span_range.start]);
+                    text.push_str(&embedded_text);
+                    copied_up_to = span_range.end;
+                }
+                Err(error @ EmbedError::Cycle { .. }) => return Err(error),
+                Ok(Embed::Attachment(_)) | Err(_) => {}
+            }
+        }
+
+        text.push_str(&page.contents[copied_up_to..range.end]);
+        self.stack.pop();
+        Ok(text)
+    }
+}
+
+/// Where the part of the page the link points at is in its contents.
+fn embedded_range(page: &Page, parts: &LinkParts) -> Result<Range<usize>, EmbedError> {
+    if let Some(block_id) = &parts.block_id {
+        let block = page
+            .blocks
+            .find_block(block_id)
+            .ok_or_else(|| EmbedError::BlockNotFound {
+                id: page.id.clone(),
+                block_id: block_id.clone(),
+            })?;
+        return Ok(block.range.clone());
+    }
+
+    if !parts.heading_path.is_empty() {
+        let section = page.blocks.section(&parts.heading_path).ok_or_else(|| {
+            EmbedError::HeadingNotFound {
+                id: page.id.clone(),
+                heading_path: parts.heading_path.clone(),
+            }
+        })?;
+        return Ok(section.range);
+    }
+
+    Ok(page.body_offset..page.contents.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::obsidian::*;
+    use std::time::SystemTime;
+
+    #[test]
+    fn test_expand_embeds() {
+        let created_at = SystemTime::UNIX_EPOCH;
+        let vault = Vault::from_files([
+            (
+                "Reading.md",
+                "---\ntags: [books]\n---\nNotes on ![[Feynman#Books]]",
+                created_at,
+            ),
+            (
+                "Feynman.md",
+                "---\nborn: 1918\n---\n# Books\n\n![[Quotes#^fooling]]\n\n![[diagram.png]]\n\n# Physics\n\nQED.\n",
+                created_at,
+            ),
+            (
+                "Quotes.md",
+                "You must not fool yourself. ^fooling\n\nOther quotes.\n",
+                created_at,
+            ),
+            ("diagram.png", "", created_at),
+            ("Loop A.md", "A embeds ![[Loop B]]", created_at),
+            ("Loop B.md", "B embeds ![[Loop A]]", created_at),
+        ])
+        .unwrap();
+
+        let reading = vault.item(&VaultItemId::from("Reading.md")).unwrap();
+        assert_eq!(
+            vault
+                .expand_embeds(reading.try_into_page().unwrap())
+                .unwrap(),
+            "---\ntags: [books]\n---\nNotes on # Books\n\nYou must not fool yourself.\n\n![[diagram.png]]\n\n"
+        );
+
+        let feynman = vault
+            .item(&VaultItemId::from("Feynman.md"))
+            .unwrap()
+            .try_into_page()
+            .unwrap();
+        let embeds: Vec<_> = feynman
+            .reference_spans
+            .iter()
+            .map(|reference_span| vault.resolve_embed(&reference_span.link))
+            .collect();
+        assert!(
+            matches!(&embeds[0], Ok(Embed::Text(text)) if text == "You must not fool yourself.")
+        );
+        assert!(
+            matches!(&embeds[1], Ok(Embed::Attachment(file)) if file.path_from_vault_root == "diagram.png")
+        );
+
+        let loop_a = vault.item(&VaultItemId::from("Loop A.md")).unwrap();
+        assert_eq!(
+            vault.expand_embeds(loop_a.try_into_page().unwrap()),
+            Err(EmbedError::Cycle {
+                ids: vec![
+                    VaultItemId::from("Loop A.md"),
+                    VaultItemId::from("Loop B.md"),
+                    VaultItemId::from("Loop A.md"),
+                ]
+            })
+        );
+    }
+}
@@ -0,0 +1,43 @@
+use super::reference_match::{find_reference_matches, ReferenceMatchKind};
+use super::Span;
+use std::ops::Range;
+
+/// A Markdown link to something outside the vault, like
+/// `[Obsidian](https://obsidian.md)`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ExternalLink {
+    pub is_embed: bool,
+    /// The text between the square brackets.
+    pub label: String,
+    pub url: String,
+    pub span: Span,
+}
+
+impl ExternalLink {
+    /// `code_ranges` comes from `BlockTree::code_ranges`. Links in code don't count.
+    pub fn parse_external_links(string: &str, code_ranges: &[Range<usize>]) -> Vec<ExternalLink> {
+        find_reference_matches(string, code_ranges)
+            .into_iter()
+            .filter_map(|reference_match| match reference_match.kind {
+                ReferenceMatchKind::External {
+                    is_embed,
+                    label,
+                    url,
+                } => Some(ExternalLink {
+                    is_embed,
+                    label: label.to_string(),
+                    url: url.to_string(),
+                    span: Span::new(reference_match.text, reference_match.range),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// True for `http://` and `https://` links, as opposed to
+    /// `mailto:` or `obsidian://` ones.
+    pub fn is_web_link(&self) -> bool {
+        let url = self.url.to_lowercase();
+        url.starts_with("http://") || url.starts_with("https://")
+    }
+}
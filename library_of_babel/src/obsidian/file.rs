@@ -1,8 +1,10 @@
+use super::{VaultConfig, VaultError, VaultStorage};
+use rayon::prelude::*;
+use std::ffi::OsStr;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
-use walkdir::{DirEntry, WalkDir};
 
 #[derive(Debug, Clone)]
 pub struct File {
@@ -23,66 +25,125 @@ pub struct File {
     pub contents: Contents, // Richard Feynman was an American physicist...
 
     pub created_at: SystemTime,
+    pub modified_at: SystemTime,
 }
 
-pub fn files_in_vault(vault_path: &'static str) -> impl Iterator<Item = File> {
-    WalkDir::new(vault_path)
+/// What a page's file looked like when we last read or wrote it. We check
+/// it before saving so we don't clobber edits made in Obsidian or by sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskState {
+    pub modified_at: SystemTime,
+    pub content_hash: u64,
+}
+
+/// Writes `contents` to `path`, unless the file there no longer matches
+/// `disk_state`, because Obsidian or sync might have edited it in the
+/// meantime. Returns what the file looks like now.
+pub fn save_unless_changed_on_disk(
+    storage: &dyn VaultStorage,
+    path: &Path,
+    disk_state: &DiskState,
+    contents: &[u8],
+) -> Result<DiskState, VaultError> {
+    let changed_on_disk = storage.timestamps(path)?.modified_at != disk_state.modified_at
+        || content_hash(&storage.read(path)?) != disk_state.content_hash;
+    if changed_on_disk {
+        return Err(VaultError::ChangedOnDisk {
+            path: path.to_path_buf(),
+        });
+    }
+
+    storage.write(path, contents)?;
+
+    Ok(DiskState {
+        modified_at: storage.timestamps(path)?.modified_at,
+        content_hash: content_hash(contents),
+    })
+}
+
+/// Reads every file in parallel. Has an error instead of a file for every
+/// entry that couldn't be read.
+pub fn files_in_vault(
+    storage: &dyn VaultStorage,
+    vault_path: &str,
+    config: &VaultConfig,
+) -> Vec<Result<File, VaultError>> {
+    // The vault root itself is never hidden or ignored, even if it's called `.`.
+    let skip = |path: &Path| is_hidden(path) || is_ignored(vault_path, config, path);
+
+    storage
+        .files_in_folder(Path::new(vault_path), config.follow_symlinks, &skip)
+        .into_par_iter()
+        .map(|path_or_error| {
+            path_or_error.and_then(|path| File::load(storage, vault_path, path, config))
+        })
+        .collect()
+}
+
+/// Paths of the files inside `folder`, skipping hidden ones. Unlike
+/// `files_in_vault`, this doesn't read them or check the config's ignore list.
+pub fn file_paths_in_folder(
+    storage: &dyn VaultStorage,
+    folder: &Path,
+    config: &VaultConfig,
+) -> Vec<PathBuf> {
+    storage
+        .files_in_folder(folder, config.follow_symlinks, &is_hidden)
         .into_iter()
-        .filter_entry(|entry| !is_hidden(entry))
-        .filter_map(|entry| entry.ok())
-        .filter(|entry| entry.file_type().is_file())
-        .map(|entry| File::from_dir_entry(vault_path, &entry))
+        .filter_map(Result::ok)
+        .collect()
 }
 
-fn is_hidden(entry: &DirEntry) -> bool {
-    entry
-        .file_name()
-        .to_str()
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(OsStr::to_str)
         .map(|s| s.starts_with('.'))
         .unwrap_or(false)
 }
 
-impl File {
-    pub fn create(vault_path: &str, absolute_file_path: PathBuf, contents: String) -> File {
-        File::write_file_including_intermediate_folders(&absolute_file_path, &contents)
-            .expect("Error saving file to disk.");
+fn is_ignored(vault_path: &str, config: &VaultConfig, path: &Path) -> bool {
+    path.strip_prefix(vault_path)
+        .ok()
+        .and_then(|path_from_vault_root| path_from_vault_root.to_str())
+        .map(|path_from_vault_root| config.is_ignored(path_from_vault_root))
+        .unwrap_or(false)
+}
 
-        let created_at = fs::metadata(&absolute_file_path)
-            .expect("Couldn't get file metadata.")
-            .created()
-            .expect("Couldn't get file creation time.");
+impl File {
+    pub fn create(
+        storage: &dyn VaultStorage,
+        vault_path: &str,
+        absolute_file_path: PathBuf,
+        contents: String,
+    ) -> Result<File, VaultError> {
+        storage.write(&absolute_file_path, contents.as_bytes())?;
+        let timestamps = storage.timestamps(&absolute_file_path)?;
 
         File::new(
             vault_path,
             absolute_file_path,
             GetContents::FromMarkdown(contents),
-            created_at,
+            timestamps.created_at,
+            timestamps.modified_at,
         )
     }
 
-    fn write_file_including_intermediate_folders(
-        path: &PathBuf,
-        contents: &String,
-    ) -> io::Result<()> {
-        let path_to_parent_folder = path.parent().expect("Couldn't get parent from path.");
-        fs::create_dir_all(path_to_parent_folder)?;
-        fs::write(path, contents)
-    }
-
-    fn from_dir_entry(vault_path: &str, entry: &DirEntry) -> File {
-        let absolute_path = entry.path().to_path_buf();
-
-        let created_at = entry
-            .metadata()
-            .expect("Error reading file metadata.")
-            .created()
-            .expect("Couldn't get created_at timestamp for file.");
+    /// Reads the file at `absolute_path` from storage.
+    pub fn load(
+        storage: &dyn VaultStorage,
+        vault_path: &str,
+        absolute_path: PathBuf,
+        config: &VaultConfig,
+    ) -> Result<File, VaultError> {
+        let timestamps = storage.timestamps(&absolute_path)?;
+        let contents = Contents::load(storage, &absolute_path, config)?;
 
         File::new(
             vault_path,
             absolute_path,
-            GetContents::FromFileSystem,
-            created_at,
+            GetContents::PassedInDirectly(contents),
+            timestamps.created_at,
+            timestamps.modified_at,
         )
     }
 
@@ -91,37 +152,33 @@ impl File {
         absolute_path: PathBuf,
         how_to_get_contents: GetContents,
         created_at: SystemTime,
-    ) -> File {
+        modified_at: SystemTime,
+    ) -> Result<File, VaultError> {
         let file_name_without_extension = absolute_path
             .file_stem()
-            .expect("Error getting file file name without extension.")
+            .ok_or_else(|| VaultError::MissingFileName {
+                path: absolute_path.clone(),
+            })?
             .to_str()
-            .expect("Error converting file name without extension to string.")
+            .ok_or_else(|| VaultError::non_utf8_path(&absolute_path))?
             .to_owned();
 
+        // We strip the extension as written, since extensions we don't
+        // know all parse to `FileExtension::Unknown`.
         let extension = absolute_path
             .extension()
             .unwrap_or(std::ffi::OsStr::new("")) // If there's no extension, just use an empty string.
             .to_str()
-            .expect("Error converting file extension to string.")
-            .parse::<FileExtension>()
-            .expect("Error parsing file extension.");
-
-        let path_from_vault_root = {
-            let path_from_vault_root = absolute_path
-                .to_str()
-                .expect("Error converting path to unicode.")
-                .replace(vault_path, "");
-
-            if path_from_vault_root.starts_with('/') {
-                path_from_vault_root
-                    .strip_prefix('/')
-                    .expect("Error stripping prefix.")
-                    .to_owned()
-            } else {
-                path_from_vault_root
-            }
-        };
+            .ok_or_else(|| VaultError::non_utf8_path(&absolute_path))?;
+
+        let path_from_vault_root = absolute_path
+            .strip_prefix(vault_path)
+            .map_err(|_| VaultError::OutsideVault {
+                path: absolute_path.clone(),
+            })?
+            .to_str()
+            .ok_or_else(|| VaultError::non_utf8_path(&absolute_path))?
+            .to_owned();
 
         let path_from_vault_root_without_extension = path_from_vault_root
             .strip_suffix(&format!(".{}", extension))
@@ -130,18 +187,19 @@ impl File {
 
         let contents = match how_to_get_contents {
             GetContents::PassedInDirectly(contents) => contents,
-            GetContents::FromFileSystem => Contents::from_extension(&extension, &absolute_path),
             GetContents::FromMarkdown(text) => Contents::Markdown { text },
         };
 
         let file_name = absolute_path
             .file_name()
-            .expect("Error getting file name.")
+            .ok_or_else(|| VaultError::MissingFileName {
+                path: absolute_path.clone(),
+            })?
             .to_str()
-            .expect("Error converting file name to string.")
+            .ok_or_else(|| VaultError::non_utf8_path(&absolute_path))?
             .to_owned();
 
-        File {
+        Ok(File {
             vault_path: vault_path.to_string(),
             file_name_without_extension,
             file_name,
@@ -149,39 +207,86 @@ impl File {
             path_from_vault_root_without_extension,
             absolute_path,
             created_at,
+            modified_at,
             contents,
-        }
+        })
     }
 
     pub fn is_image(&self) -> bool {
         matches!(self.contents, Contents::Image {})
     }
 
-    pub fn move_file(&mut self, new_path_from_vault_root: &str) {
+    pub fn move_file(
+        &mut self,
+        storage: &dyn VaultStorage,
+        new_path_from_vault_root: &str,
+    ) -> Result<(), VaultError> {
         let new_absolute_path = Path::new(&self.vault_path).join(new_path_from_vault_root);
-
-        fs::rename(&self.absolute_path, &new_absolute_path).expect("Error moving file.");
+        storage.rename(&self.absolute_path, &new_absolute_path)?;
 
         let new_file = File::new(
             &self.vault_path,
             new_absolute_path,
             GetContents::PassedInDirectly(self.contents.clone()),
             self.created_at,
-        );
+            self.modified_at,
+        )?;
 
         *self = new_file;
+        Ok(())
     }
 }
 
+/// Writes to a temporary file next to `path`, then renames it over `path`,
+/// so a crash halfway through never leaves a half-written page behind.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), VaultError> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| VaultError::MissingFileName {
+            path: path.to_path_buf(),
+        })?
+        .to_string_lossy();
+    // Hidden, so we skip it if we load the vault before it's cleaned up.
+    let temporary_path = path.with_file_name(format!(".{file_name}.{}.tmp", std::process::id()));
+
+    let write_and_rename = || -> io::Result<()> {
+        let mut temporary_file = fs::File::create(&temporary_path)?;
+        io::Write::write_all(&mut temporary_file, contents)?;
+        temporary_file.sync_all()?;
+        fs::rename(&temporary_path, path)
+    };
+
+    write_and_rename().map_err(|error| {
+        let _ = fs::remove_file(&temporary_path);
+        VaultError::io(path, error)
+    })
+}
+
+/// A 64-bit FNV-1a hash. Unlike `DefaultHasher`, it's the same on every
+/// run, so it's safe to store.
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    const offset_basis: u64 = 0xcbf29ce484222325;
+    const prime: u64 = 0x100000001b3;
+
+    bytes.iter().fold(offset_basis, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(prime)
+    })
+}
+
 pub enum GetContents {
-    FromFileSystem,
     PassedInDirectly(Contents),
     FromMarkdown(String),
 }
 
 #[derive(Debug, Clone)]
 pub enum Contents {
-    Markdown { text: String },
+    Markdown {
+        text: String,
+    },
+    /// The JSON in a `.canvas` file.
+    Canvas {
+        text: String,
+    },
     Image {},
     Audio {},
     Video {},
@@ -190,12 +295,38 @@ pub enum Contents {
 }
 
 impl Contents {
-    fn from_extension(extension: &FileExtension, absolute_path: &Path) -> Contents {
-        match extension.content_type() {
-            ContentType::Markdown => {
-                let text = fs::read_to_string(absolute_path)
-                    .expect("Error reading markdown file contents.");
-                Contents::Markdown { text }
+    fn load(
+        storage: &dyn VaultStorage,
+        absolute_path: &Path,
+        config: &VaultConfig,
+    ) -> Result<Contents, VaultError> {
+        let raw_extension = absolute_path
+            .extension()
+            .and_then(OsStr::to_str)
+            .unwrap_or(""); // If there's no extension, just use an empty string.
+
+        if config.is_page_extension(raw_extension) {
+            let bytes = storage.read(absolute_path)?;
+            let text = String::from_utf8(bytes).map_err(|_| VaultError::NonUtf8Contents {
+                path: absolute_path.to_path_buf(),
+            })?;
+            return Ok(Contents::Markdown { text });
+        }
+
+        let extension = raw_extension
+            .parse::<FileExtension>()
+            .unwrap_or(FileExtension::Unknown);
+
+        let contents = match extension.content_type() {
+            // Text files the config doesn't count as pages.
+            ContentType::Markdown => Contents::Unknown {},
+
+            ContentType::Canvas => {
+                let bytes = storage.read(absolute_path)?;
+                let text = String::from_utf8(bytes).map_err(|_| VaultError::NonUtf8Contents {
+                    path: absolute_path.to_path_buf(),
+                })?;
+                Contents::Canvas { text }
             }
 
             ContentType::Image => Contents::Image {},
@@ -203,7 +334,9 @@ impl Contents {
             ContentType::Video => Contents::Video {},
             ContentType::Pdf => Contents::Pdf {},
             ContentType::Unknown => Contents::Unknown {},
-        }
+        };
+
+        Ok(contents)
     }
 }
 
@@ -234,6 +367,7 @@ enum FileExtension {
     // Text
     Md,
     Txt,
+    Canvas,
 
     // Code
     Html,
@@ -274,6 +408,7 @@ impl FileExtension {
             | FileExtension::Mkv => ContentType::Video,
 
             FileExtension::Md | FileExtension::Txt => ContentType::Markdown,
+            FileExtension::Canvas => ContentType::Canvas,
 
             FileExtension::Html
             | FileExtension::Css
@@ -317,6 +452,7 @@ impl std::str::FromStr for FileExtension {
 
             "md" => Ok(FileExtension::Md),
             "txt" => Ok(FileExtension::Txt),
+            "canvas" => Ok(FileExtension::Canvas),
 
             "html" => Ok(FileExtension::Html),
             "css" => Ok(FileExtension::Css),
@@ -358,6 +494,7 @@ impl std::fmt::Display for FileExtension {
 
             FileExtension::Md => "md",
             FileExtension::Txt => "txt",
+            FileExtension::Canvas => "canvas",
 
             FileExtension::Html => "html",
             FileExtension::Css => "css",
@@ -378,6 +515,7 @@ impl std::fmt::Display for FileExtension {
 
 enum ContentType {
     Markdown,
+    Canvas,
     Image,
     Audio,
     Video,
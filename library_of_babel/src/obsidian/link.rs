@@ -1,13 +1,32 @@
+use super::reference_match::{find_reference_matches, ReferenceMatch, ReferenceMatchKind};
 use super::*;
 use chrono::NaiveDate;
-use lazy_static::lazy_static;
-use regex::Regex;
-
-#[derive(Debug, Clone)]
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+
+/// What we percent-encode in Markdown link destinations, like the space in
+/// `richard%20feynman.md`.
+const markdown_destination_characters: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'#')
+    .add(b'%')
+    .add(b'(')
+    .add(b')')
+    .add(b'<')
+    .add(b'>')
+    .add(b'^');
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Link {
     pub is_embed: bool,
+    pub syntax: LinkSyntax,
+    /// For wiki links, the text between the double brackets. For Markdown
+    /// links, the percent-decoded destination, like `people/richard feynman.md#Early life`.
     pub link_text: LinkText,
+    /// The target, alias, heading path, and block id from `link_text`.
+    pub parts: LinkParts,
     pub text: String,
+    /// Not cached, since it depends on the rest of the vault.
+    #[serde(skip)]
     pub vault_item_id: Option<VaultItemId>,
 }
 
@@ -30,78 +49,154 @@ pub type LinkTextStr = str;
 /// A wiki_link is a string formatted like this: `[[Richard Feynman]]`.
 pub type WikiLinkStr = str;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum LinkSyntax {
+    /// `[[Richard Feynman]]`
+    Wiki,
+    /// `[Feynman](people/richard%20feynman.md)`. The destination is
+    /// resolved relative to the folder of the page the link is in.
+    Markdown,
+}
+
 impl Link {
-    /// `string` is often the contents of a page.
-    /// `files` is necessary because we try and resolve which file the
+    /// `string` is often the contents of a page, and `source_id` is that page.
+    /// `link_resolver` is necessary because we try and resolve which file the
     /// reference is pointing to.
-    pub fn parse_references(string: &str, files: &[&File]) -> Vec<Link> {
-        lazy_static! {
-            static ref match_references: Regex =
-                Regex::new(r"!?\[\[.+?\]\]").expect("Error compiling regex.");
-        }
-
-        match_references
-            .find_iter(string)
-            .map(|current_match| {
-                let matched_text = current_match.as_str();
-                Link::new(matched_text, files)
+    ///
+    /// External links, like `[Obsidian](https://obsidian.md)`, are skipped.
+    pub fn parse_references(
+        string: &str,
+        source_id: &VaultItemId,
+        link_resolver: &LinkResolver,
+    ) -> Vec<Link> {
+        let code_ranges = BlockTree::parse(string).code_ranges(string);
+        find_reference_matches(string, &code_ranges)
+            .iter()
+            .filter_map(|reference_match| {
+                Link::from_reference_match(reference_match, source_id, link_resolver)
             })
             .collect()
     }
 
-    pub fn new(matched_text: &str, files: &[&File]) -> Link {
+    /// Returns `None` for external links.
+    pub(super) fn from_reference_match(
+        reference_match: &ReferenceMatch,
+        source_id: &VaultItemId,
+        link_resolver: &LinkResolver,
+    ) -> Option<Link> {
+        match &reference_match.kind {
+            ReferenceMatchKind::Wiki => {
+                let link = Link::new(reference_match.text, source_id, link_resolver);
+                Some(link)
+            }
+
+            ReferenceMatchKind::Markdown {
+                is_embed,
+                label,
+                destination,
+                parts,
+            } => {
+                let alias = Some(label.trim().to_string()).filter(|label| !label.is_empty());
+                let parts = LinkParts {
+                    alias,
+                    ..parts.clone()
+                };
+
+                let mut link = Link {
+                    is_embed: *is_embed,
+                    syntax: LinkSyntax::Markdown,
+                    link_text: destination.clone(),
+                    parts,
+                    text: reference_match.text.to_string(),
+                    vault_item_id: None,
+                };
+                link.resolve(source_id, link_resolver);
+                Some(link)
+            }
+
+            ReferenceMatchKind::External { .. } => None,
+        }
+    }
+
+    /// `matched_text` is a wiki link, like `[[Richard Feynman]]`.
+    pub fn new(matched_text: &str, source_id: &VaultItemId, link_resolver: &LinkResolver) -> Link {
         let is_embed = matched_text.starts_with("![[");
 
         // This is the text between [[ and ]].
-        let link_text: LinkText = matched_text.replace(['!', '[', ']'], "");
-
-        let vault_item_id = Link::find_vault_item_id(&link_text, files);
+        let link_text = Link::extract_link_text(matched_text);
+        let parts = LinkParts::parse(&link_text);
 
-        Link {
+        let mut link = Link {
             is_embed,
+            syntax: LinkSyntax::Wiki,
             text: matched_text.to_string(),
             link_text,
-            vault_item_id,
+            parts,
+            vault_item_id: None,
+        };
+        link.resolve(source_id, link_resolver);
+        link
+    }
+
+    /// Only the target says which file a link points at. Headings,
+    /// block ids, and aliases don't.
+    pub fn resolve(&mut self, source_id: &VaultItemId, link_resolver: &LinkResolver) {
+        self.vault_item_id = link_resolver.resolve_from(&self.parts.target, self.syntax, source_id);
+    }
+
+    /// The text of this link pointed at `target` instead, keeping its alias,
+    /// headings, block id and embed marker. For Markdown links, `target` is a
+    /// path, like `../people/richard feynman.md`.
+    pub fn text_with_target(&self, target: &str) -> String {
+        let embed_marker = if self.is_embed { "!" } else { "" };
+        let parts = LinkParts {
+            target: target.to_string(),
+            ..self.parts.clone()
+        };
+
+        match self.syntax {
+            LinkSyntax::Wiki => format!("{embed_marker}[[{}]]", parts.to_link_text()),
+            LinkSyntax::Markdown => {
+                let label = parts.alias.as_deref().unwrap_or("");
+                let mut destination =
+                    utf8_percent_encode(target, markdown_destination_characters).to_string();
+                for heading in &parts.heading_path {
+                    destination.push('#');
+                    destination.extend(utf8_percent_encode(
+                        heading,
+                        markdown_destination_characters,
+                    ));
+                }
+                if let Some(block_id) = &parts.block_id {
+                    destination.push_str("#^");
+                    destination.push_str(block_id);
+                }
+                format!("{embed_marker}[{label}]({destination})")
+            }
         }
     }
 
     pub fn extract_link_text(wiki_link: &WikiLinkStr) -> LinkText {
-        wiki_link.replace(['!', '[', ']'], "")
+        let link_text = wiki_link.strip_prefix('!').unwrap_or(wiki_link);
+        let link_text = link_text.strip_prefix("[[").unwrap_or(link_text);
+        let link_text = link_text.strip_suffix("]]").unwrap_or(link_text);
+        link_text.to_string()
     }
 
-    /// This function expects that files has been sorted so that
-    /// oldest files are first.
-    ///
-    /// We need a list of files because we need to resolve the reference
-    /// to a specific file.
-    pub fn find_vault_item_id(link_text: &LinkTextStr, files: &[&File]) -> Option<VaultItemId> {
-        Link::find_closest_matching_file(link_text, files).map(VaultItemId::from_file)
+    pub fn target(&self) -> &str {
+        &self.parts.target
     }
 
-    /// This function expects that files has been sorted so that
-    /// oldest files are first.
-    fn find_closest_matching_file<'f>(
-        link_text: &LinkTextStr,
-        files: &'f [&File],
-    ) -> Option<&'f File> {
-        let most_specific_to_least_specific = [
-            |link_text: &LinkTextStr, file: &File| file.path_from_vault_root == link_text,
-            |link_text: &LinkTextStr, file: &File| {
-                file.path_from_vault_root_without_extension == link_text
-            },
-            |link_text: &LinkTextStr, file: &File| file.file_name == link_text,
-            |link_text: &LinkTextStr, file: &File| file.file_name_without_extension == link_text,
-        ];
-
-        for does_file_match in most_specific_to_least_specific {
-            let maybe_match = files.iter().find(|&&file| does_file_match(link_text, file));
-
-            if let Some(matching_file) = maybe_match {
-                return Some(matching_file);
-            }
-        }
+    pub fn alias(&self) -> Option<&str> {
+        self.parts.alias.as_deref()
+    }
 
-        None
+    pub fn heading_path(&self) -> &[String] {
+        &self.parts.heading_path
+    }
+
+    pub fn block_id(&self) -> Option<&str> {
+        self.parts.block_id.as_deref()
     }
 
     pub fn refers_to(&self, target_id: &VaultItemId) -> bool {
@@ -111,9 +206,9 @@ impl Link {
         }
     }
 
-    /// Tries to parse `self.link_text` as a YYYY.MM.DD date.
+    /// Tries to parse the link's target as a YYYY.MM.DD date.
     pub fn try_as_date(&self) -> Option<NaiveDate> {
-        let substrings: Vec<_> = self.link_text.split('.').collect();
+        let substrings: Vec<_> = self.target().split('.').collect();
         let [year_string, month_string, day_string] = if substrings.len() == 3 {
             [substrings[0], substrings[1], substrings[2]]
         } else {
@@ -133,8 +228,3 @@ impl Link {
         NaiveDate::from_ymd_opt(year_number, month_number as u32, day_number as u32)
     }
 }
-
-lazy_static! {
-    pub static ref match_references: Regex =
-        Regex::new(r"!?\[\[.+?\]\]").expect("Error compiling regex.");
-}
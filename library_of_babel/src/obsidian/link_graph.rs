@@ -0,0 +1,220 @@
+use super::{Canvas, Page, VaultItemId};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Which pages and canvases link to which items, built from each page's
+/// `reference_spans` and each canvas's `links`.
+#[derive(Debug, Clone, Default)]
+pub struct LinkGraph {
+    outgoing: HashMap<VaultItemId, Vec<LinkEdge>>,
+    incoming: HashMap<VaultItemId, Vec<LinkEdge>>,
+    /// Keyed by the link's target, like `Richard Feynman`.
+    unresolved: HashMap<String, Vec<LinkEdge>>,
+}
+
+/// A single link or embed from one page or canvas to another item.
+#[derive(Debug, Clone)]
+pub struct LinkEdge {
+    pub source: VaultItemId,
+    /// `None` if the link points at something that isn't in the vault.
+    pub target: Option<VaultItemId>,
+    /// The target as written in the link, like `Richard Feynman`.
+    pub target_text: String,
+    pub is_embed: bool,
+    /// Where the link is in the source page's contents. For canvases,
+    /// where it is in its text node's text, or empty for file nodes.
+    pub range: Range<usize>,
+    /// For canvases, the node the link is in.
+    pub canvas_node_id: Option<String>,
+}
+
+impl LinkEdge {
+    /// True for links like `[[#Early life]]` that point at the page they're in.
+    pub fn is_self_link(&self) -> bool {
+        self.target.as_ref() == Some(&self.source)
+    }
+}
+
+impl LinkGraph {
+    pub fn new<'p>(pages: impl IntoIterator<Item = &'p Page>) -> LinkGraph {
+        let mut graph = LinkGraph::default();
+        for page in pages {
+            graph.add_page(page);
+        }
+        graph
+    }
+
+    /// Replaces everything we know about `page`'s links with its current `reference_spans`.
+    pub fn update_page(&mut self, page: &Page) {
+        self.remove_page(&page.id);
+        self.add_page(page);
+    }
+
+    /// Replaces everything we know about `canvas`'s links with its current `links`.
+    pub fn update_canvas(&mut self, canvas: &Canvas) {
+        self.remove_page(&canvas.id);
+        let edges = canvas
+            .links
+            .iter()
+            .map(|canvas_link| LinkEdge {
+                source: canvas.id.clone(),
+                target: canvas_link.link.vault_item_id.clone(),
+                target_text: canvas_link.link.target().to_string(),
+                is_embed: canvas_link.link.is_embed,
+                range: canvas_link.range.clone().unwrap_or(0..0),
+                canvas_node_id: Some(canvas_link.node_id.clone()),
+            })
+            .collect();
+        self.add_edges(&canvas.id, edges);
+    }
+
+    /// Forgets every link in the page or canvas `id`.
+    pub fn remove_page(&mut self, id: &VaultItemId) {
+        let Some(edges) = self.outgoing.remove(id) else {
+            return;
+        };
+
+        for edge in edges {
+            match &edge.target {
+                Some(target) => remove_edges_from(&mut self.incoming, target, id),
+                None => remove_edges_from(&mut self.unresolved, &edge.target_text, id),
+            }
+        }
+    }
+
+    fn add_page(&mut self, page: &Page) {
+        let edges = page
+            .reference_spans
+            .iter()
+            .map(|reference_span| LinkEdge {
+                source: page.id.clone(),
+                target: reference_span.link.vault_item_id.clone(),
+                target_text: reference_span.link.target().to_string(),
+                is_embed: reference_span.link.is_embed,
+                range: reference_span.range().clone(),
+                canvas_node_id: None,
+            })
+            .collect();
+        self.add_edges(&page.id, edges);
+    }
+
+    fn add_edges(&mut self, source: &VaultItemId, edges: Vec<LinkEdge>) {
+        for edge in &edges {
+            match &edge.target {
+                // A page doesn't count as one of its own backlinks.
+                Some(_) if edge.is_self_link() => {}
+                Some(target) => {
+                    let incoming = self.incoming.entry(target.clone()).or_default();
+                    incoming.push(edge.clone());
+                }
+                None => {
+                    let unresolved = self.unresolved.entry(edge.target_text.clone()).or_default();
+                    unresolved.push(edge.clone());
+                }
+            }
+        }
+
+        if !edges.is_empty() {
+            self.outgoing.insert(source.clone(), edges);
+        }
+    }
+
+    /// Links and embeds from other pages that point at `id`.
+    pub fn backlinks(&self, id: &VaultItemId) -> &[LinkEdge] {
+        self.incoming.get(id).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Every link and embed in the page or canvas `id`, including unresolved ones.
+    pub fn outgoing_links(&self, id: &VaultItemId) -> &[LinkEdge] {
+        self.outgoing.get(id).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn has_backlinks(&self, id: &VaultItemId) -> bool {
+        !self.backlinks(id).is_empty()
+    }
+
+    /// Links to items that aren't in the vault, grouped by the missing target.
+    pub fn unresolved_links(&self) -> &HashMap<String, Vec<LinkEdge>> {
+        &self.unresolved
+    }
+}
+
+fn remove_edges_from<Key>(index: &mut HashMap<Key, Vec<LinkEdge>>, key: &Key, source: &VaultItemId)
+where
+    Key: std::hash::Hash + Eq,
+{
+    if let Some(edges) = index.get_mut(key) {
+        edges.retain(|edge| &edge.source != source);
+        if edges.is_empty() {
+            index.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::obsidian::*;
+    use std::time::SystemTime;
+
+    fn orphans(vault: &Vault) -> Vec<&str> {
+        let mut orphans: Vec<_> = vault
+            .orphans()
+            .map(|item| item.id().path_from_vault_root())
+            .collect();
+        orphans.sort();
+        orphans
+    }
+
+    #[test]
+    fn test_link_graph() {
+        let created_at = SystemTime::UNIX_EPOCH;
+        let mut vault = Vault::from_files([
+            (
+                "Richard Feynman.md",
+                "Physicist, see [[#Early life]] and [[Richard Feynman]].\n\n# Early life",
+                created_at,
+            ),
+            (
+                "Reading.md",
+                "I'm reading [[Richard Feynman]] and [[Surely You're Joking]].",
+                created_at,
+            ),
+            ("Lonely.md", "", created_at),
+        ])
+        .unwrap();
+        let feynman = VaultItemId::from("Richard Feynman.md");
+        let reading = VaultItemId::from("Reading.md");
+        let lonely = VaultItemId::from("Lonely.md");
+
+        assert_eq!(orphans(&vault), ["Lonely.md", "Reading.md"]);
+        // Links from a page to itself aren't backlinks, but they're still outgoing links.
+        assert_eq!(vault.backlinks(&feynman).len(), 1);
+        assert_eq!(vault.backlinks(&feynman)[0].source, reading);
+        assert_eq!(vault.outgoing_links(&feynman).len(), 2);
+        let unresolved = &vault.unresolved_links()["Surely You're Joking"];
+        assert_eq!(unresolved.len(), 1);
+        assert_eq!(unresolved[0].source, reading);
+
+        vault
+            .edit_page(&lonely, "See [[Reading]].".to_string())
+            .unwrap();
+        assert_eq!(orphans(&vault), ["Lonely.md"]);
+        assert_eq!(vault.backlinks(&reading)[0].source, lonely);
+
+        let reading = vault.rename_item(&reading, "notes/Reading.md").unwrap();
+        assert!(vault.backlinks(&VaultItemId::from("Reading.md")).is_empty());
+        assert_eq!(vault.backlinks(&reading)[0].source, lonely);
+        assert_eq!(vault.backlinks(&feynman)[0].source, reading);
+        assert_eq!(
+            vault.unresolved_links()["Surely You're Joking"][0].source,
+            reading
+        );
+
+        vault
+            .edit_page(&reading, "Nothing to read.".to_string())
+            .unwrap();
+        assert!(vault.backlinks(&feynman).is_empty());
+        assert!(vault.unresolved_links().is_empty());
+        assert_eq!(orphans(&vault), ["Lonely.md", "Richard Feynman.md"]);
+    }
+}
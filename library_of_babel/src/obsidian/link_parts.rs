@@ -0,0 +1,157 @@
+use super::{LinkText, LinkTextStr};
+
+/// The pieces of a link's text. In this example:
+///
+/// ```text
+/// [[people/Richard Feynman#Early life#School|Feynman]]
+/// ```
+///
+/// The target is `people/Richard Feynman`, the heading path is
+/// `["Early life", "School"]`, and the alias is `Feynman`. In
+/// `[[Richard Feynman#^favorite-quote]]`, the block id is `favorite-quote`.
+#[derive(Debug, Clone, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub struct LinkParts {
+    /// The file the link points at. Empty when a link points at a heading
+    /// or block in the same page, like `[[#Early life]]`.
+    pub target: String,
+    /// The text Obsidian displays instead of the target.
+    pub alias: Option<String>,
+    /// Empty if the link doesn't point at a heading.
+    pub heading_path: Vec<String>,
+    pub block_id: Option<String>,
+    /// Inside tables, the pipe before the alias has to be escaped as `\|`,
+    /// so we keep the escape when we write the link back out.
+    #[serde(default)]
+    pub escaped_alias_separator: bool,
+}
+
+impl LinkParts {
+    /// `link_text` is the text between the double brackets.
+    pub fn parse(link_text: &LinkTextStr) -> LinkParts {
+        match link_text.split_once('|') {
+            Some((destination, alias)) => {
+                let escaped_destination = destination.strip_suffix('\\');
+                LinkParts {
+                    alias: Some(alias.trim().to_string()),
+                    escaped_alias_separator: escaped_destination.is_some(),
+                    ..LinkParts::from_destination(escaped_destination.unwrap_or(destination))
+                }
+            }
+            None => LinkParts::from_destination(link_text),
+        }
+    }
+
+    /// `destination` is everything but the alias, like `Richard Feynman#Early life`.
+    pub fn from_destination(destination: &str) -> LinkParts {
+        let mut segments = destination.split('#');
+        let target = segments.next().unwrap_or("").trim().to_string();
+
+        let mut heading_path = vec![];
+        let mut block_id = None;
+        for segment in segments
+            .map(str::trim)
+            .filter(|segment| !segment.is_empty())
+        {
+            match segment.strip_prefix('^') {
+                Some(id) => block_id = Some(id.to_string()),
+                None => heading_path.push(segment.to_string()),
+            }
+        }
+
+        LinkParts {
+            target,
+            alias: None,
+            heading_path,
+            block_id,
+            escaped_alias_separator: false,
+        }
+    }
+
+    /// The innermost heading the link points at.
+    pub fn heading(&self) -> Option<&str> {
+        self.heading_path.last().map(String::as_str)
+    }
+
+    /// Reassembles the parts into link text, like `Richard Feynman#Early life|Feynman`.
+    pub fn to_link_text(&self) -> LinkText {
+        let mut link_text = self.target.clone();
+
+        for heading in &self.heading_path {
+            link_text.push('#');
+            link_text.push_str(heading);
+        }
+
+        if let Some(block_id) = &self.block_id {
+            link_text.push_str("#^");
+            link_text.push_str(block_id);
+        }
+
+        if let Some(alias) = &self.alias {
+            if self.escaped_alias_separator {
+                link_text.push('\\');
+            }
+            link_text.push('|');
+            link_text.push_str(alias);
+        }
+
+        link_text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let parts = LinkParts::parse("people/Richard Feynman#Early life#School|Feynman");
+        assert_eq!(parts.target, "people/Richard Feynman");
+        assert_eq!(parts.heading_path, vec!["Early life", "School"]);
+        assert_eq!(parts.alias.as_deref(), Some("Feynman"));
+        assert_eq!(parts.block_id, None);
+
+        let parts = LinkParts::parse("Richard Feynman#^favorite-quote");
+        assert_eq!(parts.target, "Richard Feynman");
+        assert!(parts.heading_path.is_empty());
+        assert_eq!(parts.block_id.as_deref(), Some("favorite-quote"));
+
+        let parts = LinkParts::parse("#Early life");
+        assert_eq!(parts.target, "");
+        assert_eq!(parts.heading(), Some("Early life"));
+
+        let parts = LinkParts::parse(r"Richard Feynman\|Feynman");
+        assert_eq!(parts.target, "Richard Feynman");
+        assert_eq!(parts.alias.as_deref(), Some("Feynman"));
+    }
+
+    #[test]
+    fn test_to_link_text_round_trips() {
+        for link_text in [
+            "Richard Feynman",
+            "people/Richard Feynman#Early life#School|Feynman",
+            "Richard Feynman#^favorite-quote",
+            "photo.jpg|300",
+            r"Richard Feynman\|Feynman",
+        ] {
+            assert_eq!(LinkParts::parse(link_text).to_link_text(), link_text);
+        }
+    }
+
+    #[test]
+    fn test_escaped_alias_separator_round_trips() {
+        let mut parts = LinkParts::parse(r"Richard Feynman#Early life\|Feynman");
+        assert!(parts.escaped_alias_separator);
+        assert_eq!(parts.target, "Richard Feynman");
+        assert_eq!(parts.heading_path, vec!["Early life"]);
+
+        parts.target = "people/Dick Feynman".to_string();
+        assert_eq!(
+            parts.to_link_text(),
+            r"people/Dick Feynman#Early life\|Feynman"
+        );
+
+        let parts = LinkParts::parse("Richard Feynman|Feynman");
+        assert!(!parts.escaped_alias_separator);
+        assert_eq!(parts.to_link_text(), "Richard Feynman|Feynman");
+    }
+}
@@ -0,0 +1,458 @@
+use super::{Contents, File, LinkSyntax, LinkTextStr, VaultItem, VaultItemId};
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+/// Resolves link text to the file it points at, without scanning every file
+/// in the vault.
+///
+/// Link text can match a file in four ways. From most specific to least specific:
+///
+/// 1. `people/richard feynman.md`, the path from the vault root.
+/// 2. `people/richard feynman`, the path without the extension.
+/// 3. `richard feynman.md`, the file name.
+/// 4. `richard feynman`, the file name without the extension.
+///
+/// Least specific of all, link text can match one of a page's aliases, which
+/// come from its `aliases` property.
+///
+/// The most specific match wins. If several files match equally well, the
+/// oldest one wins.
+#[derive(Debug, Clone, Default)]
+pub struct LinkResolver {
+    by_path: HashMap<String, Vec<Candidate>>,
+    by_path_without_extension: HashMap<String, Vec<Candidate>>,
+    by_file_name: HashMap<String, Vec<Candidate>>,
+    by_file_name_without_extension: HashMap<String, Vec<Candidate>>,
+    by_alias: HashMap<String, Vec<Candidate>>,
+    aliases_by_id: HashMap<VaultItemId, Vec<String>>,
+}
+
+#[derive(Debug, Clone)]
+struct Candidate {
+    created_at: SystemTime,
+    id: VaultItemId,
+}
+
+impl Candidate {
+    fn new(file: &File, id: &VaultItemId) -> Candidate {
+        Candidate {
+            created_at: file.created_at,
+            id: id.clone(),
+        }
+    }
+
+    /// Oldest first. Ties are broken by path so resolution doesn't depend on load order.
+    fn sort_key(&self) -> (SystemTime, &str) {
+        (self.created_at, self.id.path_from_vault_root())
+    }
+}
+
+impl LinkResolver {
+    pub fn new<'f>(files: impl IntoIterator<Item = &'f File>) -> LinkResolver {
+        let mut resolver = LinkResolver::default();
+        for file in files {
+            resolver.insert(file);
+        }
+        resolver
+    }
+
+    pub fn insert(&mut self, file: &File) {
+        let id = VaultItemId::from_file(file);
+
+        for (index, key) in self.indexes_mut().into_iter().zip(LinkResolver::keys(file)) {
+            insert_candidate(index, key, Candidate::new(file, &id));
+        }
+    }
+
+    /// Inserts the item's file and, if it's a page, its aliases.
+    pub fn insert_item(&mut self, item: &VaultItem) {
+        self.insert(item.file());
+        if let Some(page) = item.try_into_page() {
+            self.insert_aliases(&page.file, &page.aliases());
+        }
+    }
+
+    /// Replaces any aliases we already had for `file`.
+    pub fn insert_aliases(&mut self, file: &File, aliases: &[String]) {
+        let id = VaultItemId::from_file(file);
+        self.remove_aliases(&id);
+
+        for alias in aliases {
+            insert_candidate(&mut self.by_alias, alias, Candidate::new(file, &id));
+        }
+
+        if !aliases.is_empty() {
+            self.aliases_by_id.insert(id, aliases.to_vec());
+        }
+    }
+
+    /// Removes the file and any aliases it had.
+    pub fn remove(&mut self, file: &File) {
+        let id = VaultItemId::from_file(file);
+
+        for (index, key) in self.indexes_mut().into_iter().zip(LinkResolver::keys(file)) {
+            remove_candidate(index, key, &id);
+        }
+
+        self.remove_aliases(&id);
+    }
+
+    fn remove_aliases(&mut self, id: &VaultItemId) {
+        let aliases = self.aliases_by_id.remove(id).unwrap_or_default();
+        for alias in aliases {
+            remove_candidate(&mut self.by_alias, &alias, id);
+        }
+    }
+
+    pub fn resolve(&self, link_text: &LinkTextStr) -> Option<&VaultItemId> {
+        self.candidates(link_text)
+            .first()
+            .map(|candidate| &candidate.id)
+    }
+
+    /// Every file `link_text` matches as well as the one `resolve` picks,
+    /// oldest first. More than one means the link is ambiguous.
+    pub fn matches(&self, link_text: &LinkTextStr) -> Vec<&VaultItemId> {
+        self.candidates(link_text)
+            .iter()
+            .map(|candidate| &candidate.id)
+            .collect()
+    }
+
+    /// The candidates from the most specific index `link_text` is in.
+    fn candidates(&self, link_text: &LinkTextStr) -> &[Candidate] {
+        self.indexes()
+            .into_iter()
+            .chain([&self.by_alias])
+            .find_map(|index| index.get(link_text))
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// Like `resolve`, but also handles links whose meaning depends on where
+    /// they are: same-page links like `[[#Early life]]`, Markdown links, which
+    /// are relative to the folder of the page they're in, and wiki links that
+    /// start with `./` or `../`.
+    pub fn resolve_from(
+        &self,
+        target: &str,
+        syntax: LinkSyntax,
+        source_id: &VaultItemId,
+    ) -> Option<VaultItemId> {
+        if target.is_empty() {
+            return Some(source_id.clone());
+        }
+        if let Some(id) = self.resolve_relative(target, syntax, source_id) {
+            return Some(id.clone());
+        }
+
+        // Markdown links can start with a slash to mean the vault root.
+        let target = target.strip_prefix('/').unwrap_or(target);
+        self.resolve(target).cloned()
+    }
+
+    /// Like `matches`, but for a link in `source_id`, like `resolve_from`.
+    pub fn matches_from<'a>(
+        &'a self,
+        target: &str,
+        syntax: LinkSyntax,
+        source_id: &'a VaultItemId,
+    ) -> Vec<&'a VaultItemId> {
+        if target.is_empty() {
+            return vec![source_id];
+        }
+        if let Some(id) = self.resolve_relative(target, syntax, source_id) {
+            return vec![id];
+        }
+
+        let target = target.strip_prefix('/').unwrap_or(target);
+        self.matches(target)
+    }
+
+    /// Paths relative to the source's folder only ever match one file.
+    fn resolve_relative(
+        &self,
+        target: &str,
+        syntax: LinkSyntax,
+        source_id: &VaultItemId,
+    ) -> Option<&VaultItemId> {
+        let is_explicitly_relative = target.starts_with("./") || target.starts_with("../");
+        if syntax == LinkSyntax::Markdown || is_explicitly_relative {
+            let path_relative_to_source = join_path(source_id.folder(), target)?;
+            self.resolve_path(&path_relative_to_source)
+        } else {
+            None
+        }
+    }
+
+    /// The shortest link text that resolves to `file` and nothing else: its
+    /// name if that's unique, otherwise its path. Like Obsidian, we leave
+    /// off the extension for pages.
+    pub fn shortest_link_text(&self, file: &File) -> String {
+        let id = VaultItemId::from_file(file);
+        let is_page = matches!(file.contents, Contents::Markdown { .. });
+        let candidates = if is_page {
+            [
+                &file.file_name_without_extension,
+                &file.path_from_vault_root_without_extension,
+            ]
+        } else {
+            [&file.file_name, &file.path_from_vault_root]
+        };
+
+        candidates
+            .into_iter()
+            .find(|link_text| self.is_unambiguous(link_text, &id))
+            .unwrap_or(&file.path_from_vault_root)
+            .clone()
+    }
+
+    /// True if `link_text` resolves to `id`, and no other file matches it
+    /// equally well.
+    pub fn is_unambiguous(&self, link_text: &LinkTextStr, id: &VaultItemId) -> bool {
+        match self.candidates(link_text) {
+            [only_candidate] => &only_candidate.id == id,
+            _ => false,
+        }
+    }
+
+    /// Only matches full paths from the vault root, with or without the extension.
+    fn resolve_path(&self, path: &str) -> Option<&VaultItemId> {
+        [&self.by_path, &self.by_path_without_extension]
+            .into_iter()
+            .find_map(|index| index.get(path))
+            .and_then(|candidates| candidates.first())
+            .map(|candidate| &candidate.id)
+    }
+
+    /// Every link text that could resolve to `file`, from most specific
+    /// to least specific.
+    pub fn keys(file: &File) -> [&str; 4] {
+        [
+            &file.path_from_vault_root,
+            &file.path_from_vault_root_without_extension,
+            &file.file_name,
+            &file.file_name_without_extension,
+        ]
+    }
+
+    fn indexes(&self) -> [&HashMap<String, Vec<Candidate>>; 4] {
+        [
+            &self.by_path,
+            &self.by_path_without_extension,
+            &self.by_file_name,
+            &self.by_file_name_without_extension,
+        ]
+    }
+
+    fn indexes_mut(&mut self) -> [&mut HashMap<String, Vec<Candidate>>; 4] {
+        [
+            &mut self.by_path,
+            &mut self.by_path_without_extension,
+            &mut self.by_file_name,
+            &mut self.by_file_name_without_extension,
+        ]
+    }
+}
+
+fn insert_candidate(index: &mut HashMap<String, Vec<Candidate>>, key: &str, candidate: Candidate) {
+    let candidates = index.entry(key.to_string()).or_default();
+    let position =
+        candidates.partition_point(|existing| existing.sort_key() <= candidate.sort_key());
+    candidates.insert(position, candidate);
+}
+
+fn remove_candidate(index: &mut HashMap<String, Vec<Candidate>>, key: &str, id: &VaultItemId) {
+    if let Some(candidates) = index.get_mut(key) {
+        candidates.retain(|candidate| &candidate.id != id);
+        if candidates.is_empty() {
+            index.remove(key);
+        }
+    }
+}
+
+/// The path to `path_from_vault_root` from inside `folder`, like
+/// `../people/richard feynman.md`. Markdown links are written this way.
+pub fn relative_path(folder: &str, path_from_vault_root: &str) -> String {
+    let folder_segments: Vec<&str> = folder
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    let path_segments: Vec<&str> = path_from_vault_root.split('/').collect();
+
+    let shared_segment_count = folder_segments
+        .iter()
+        .zip(&path_segments)
+        .take_while(|(folder_segment, path_segment)| folder_segment == path_segment)
+        .count();
+
+    let mut segments = vec![".."; folder_segments.len() - shared_segment_count];
+    segments.extend(&path_segments[shared_segment_count..]);
+    segments.join("/")
+}
+
+/// Joins a relative path like `../people/richard feynman.md` onto a folder.
+/// Returns `None` if the path climbs out of the vault.
+fn join_path(folder: &str, relative_path: &str) -> Option<String> {
+    let mut segments: Vec<&str> = folder
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+
+    for segment in relative_path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    Some(segments.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::obsidian::*;
+    use std::time::{Duration, SystemTime};
+
+    fn created_at(seconds: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    fn resolved<'r>(link_resolver: &'r LinkResolver, link_text: &str) -> Option<&'r str> {
+        link_resolver
+            .resolve(link_text)
+            .map(VaultItemId::path_from_vault_root)
+    }
+
+    #[test]
+    fn test_most_specific_match_wins() {
+        let vault = Vault::from_files([
+            (
+                "people/Richard Feynman.md",
+                "---\naliases: [Dick]\n---\n",
+                created_at(0),
+            ),
+            ("people/Feynman.md", "", created_at(1)),
+            ("Feynman.md", "", created_at(2)),
+            ("notes/Dick.md", "", created_at(3)),
+        ])
+        .unwrap();
+        let link_resolver = vault.link_resolver();
+
+        assert_eq!(
+            resolved(link_resolver, "people/Feynman.md"),
+            Some("people/Feynman.md")
+        );
+        assert_eq!(
+            resolved(link_resolver, "people/Feynman"),
+            Some("people/Feynman.md")
+        );
+        // A path beats an older file with the same name.
+        assert_eq!(resolved(link_resolver, "Feynman.md"), Some("Feynman.md"));
+        assert_eq!(resolved(link_resolver, "Feynman"), Some("Feynman.md"));
+        assert_eq!(
+            resolved(link_resolver, "Richard Feynman.md"),
+            Some("people/Richard Feynman.md")
+        );
+        // A file name beats an older page's alias.
+        assert_eq!(resolved(link_resolver, "Dick"), Some("notes/Dick.md"));
+        assert_eq!(resolved(link_resolver, "Nobody"), None);
+    }
+
+    #[test]
+    fn test_oldest_match_wins() {
+        let vault = Vault::from_files([
+            ("a/Note.md", "", created_at(2)),
+            ("b/Note.md", "", created_at(1)),
+        ])
+        .unwrap();
+        let link_resolver = vault.link_resolver();
+
+        assert_eq!(resolved(link_resolver, "Note"), Some("b/Note.md"));
+        assert_eq!(
+            link_resolver.matches("Note"),
+            [
+                &VaultItemId::from("b/Note.md"),
+                &VaultItemId::from("a/Note.md")
+            ]
+        );
+        let newer_note = vault.item_at_path("a/Note.md").unwrap().file();
+        assert_eq!(link_resolver.shortest_link_text(newer_note), "a/Note");
+    }
+
+    #[test]
+    fn test_resolve_from() {
+        let vault = Vault::from_files([
+            ("people/Richard Feynman.md", "", created_at(0)),
+            ("journal/2024/Physics.md", "", created_at(0)),
+        ])
+        .unwrap();
+        let link_resolver = vault.link_resolver();
+        let physics = VaultItemId::from("journal/2024/Physics.md");
+        let feynman = VaultItemId::from("people/Richard Feynman.md");
+        let resolve_from =
+            |target: &str, syntax: LinkSyntax| link_resolver.resolve_from(target, syntax, &physics);
+
+        assert_eq!(resolve_from("", LinkSyntax::Wiki), Some(physics.clone()));
+        assert_eq!(
+            resolve_from("../../people/Richard Feynman.md", LinkSyntax::Markdown),
+            Some(feynman.clone())
+        );
+        assert_eq!(
+            resolve_from("Physics.md", LinkSyntax::Markdown),
+            Some(physics.clone())
+        );
+        assert_eq!(
+            resolve_from("./Physics", LinkSyntax::Wiki),
+            Some(physics.clone())
+        );
+        assert_eq!(
+            resolve_from("/people/Richard Feynman.md", LinkSyntax::Markdown),
+            Some(feynman.clone())
+        );
+        // Markdown links that don't work relative to the page fall back to
+        // matching anywhere in the vault, like in Obsidian.
+        assert_eq!(
+            resolve_from("Richard Feynman.md", LinkSyntax::Markdown),
+            Some(feynman)
+        );
+        assert_eq!(
+            resolve_from("../../../Richard Feynman.md", LinkSyntax::Markdown),
+            None
+        );
+    }
+
+    #[test]
+    fn test_insert_and_remove() {
+        let vault = Vault::from_files([
+            ("a/Note.md", "", created_at(2)),
+            ("b/Note.md", "", created_at(1)),
+        ])
+        .unwrap();
+        let mut link_resolver = vault.link_resolver().clone();
+        let older_note = vault.item_at_path("b/Note.md").unwrap().file();
+        let newer_note = vault.item_at_path("a/Note.md").unwrap().file();
+
+        link_resolver.remove(older_note);
+        assert_eq!(resolved(&link_resolver, "Note"), Some("a/Note.md"));
+        assert_eq!(resolved(&link_resolver, "b/Note"), None);
+        assert_eq!(link_resolver.shortest_link_text(newer_note), "Note");
+
+        link_resolver.insert(older_note);
+        assert_eq!(resolved(&link_resolver, "Note"), Some("b/Note.md"));
+
+        link_resolver.insert_aliases(newer_note, &["Memo".to_string()]);
+        assert_eq!(resolved(&link_resolver, "Memo"), Some("a/Note.md"));
+        link_resolver.insert_aliases(newer_note, &["Reminder".to_string()]);
+        assert_eq!(resolved(&link_resolver, "Memo"), None);
+        link_resolver.remove(newer_note);
+        assert_eq!(resolved(&link_resolver, "Reminder"), None);
+        assert_eq!(
+            link_resolver.matches("Note"),
+            [&VaultItemId::from("b/Note.md")]
+        );
+    }
+}
@@ -1,33 +1,33 @@
-use super::match_references;
-use super::{File, Link, LinkTextStr, Span, VaultItemId};
+use super::reference_match::find_reference_matches;
+use super::{Link, LinkParts, LinkResolver, LinkTextStr, Span, VaultItemId};
 use std::ops::Range;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct LinkSpan {
     pub link: Link,
     pub span: Span,
 }
 
 impl LinkSpan {
-    pub fn parse_reference_spans(string: &str, files: &[&File]) -> Vec<LinkSpan> {
-        match_references
-            .find_iter(string)
-            .map(|current_match| {
-                let matched_text = current_match.as_str();
-                let matched_range = current_match.range();
-
-                LinkSpan::new(matched_text, matched_range, files)
+    /// Finds wiki links and Markdown links. External links, like
+    /// `[Obsidian](https://obsidian.md)`, are skipped, and so are links in
+    /// `code_ranges`, which comes from `BlockTree::code_ranges`.
+    pub fn parse_reference_spans(
+        string: &str,
+        code_ranges: &[Range<usize>],
+        source_id: &VaultItemId,
+        link_resolver: &LinkResolver,
+    ) -> Vec<LinkSpan> {
+        find_reference_matches(string, code_ranges)
+            .iter()
+            .filter_map(|reference_match| {
+                let link = Link::from_reference_match(reference_match, source_id, link_resolver)?;
+                let span = Span::new(reference_match.text, reference_match.range.clone());
+                Some(LinkSpan { link, span })
             })
             .collect()
     }
 
-    fn new(matched_text: &str, matched_range: Range<usize>, files: &[&File]) -> LinkSpan {
-        let link = Link::new(matched_text, files);
-        let span = Span::new(matched_text, matched_range);
-
-        LinkSpan { link, span }
-    }
-
     pub fn shift_range(&mut self, cumulative_range_shift: i64) {
         self.span.shift_range(cumulative_range_shift);
     }
@@ -44,6 +44,10 @@ impl LinkSpan {
         &self.link.link_text
     }
 
+    pub fn parts(&self) -> &LinkParts {
+        &self.link.parts
+    }
+
     pub fn refers_to(&self, target_id: &VaultItemId) -> bool {
         self.link.refers_to(target_id)
     }
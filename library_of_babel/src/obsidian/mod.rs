@@ -1,18 +1,62 @@
+mod ambiguous_link;
+pub use ambiguous_link::*;
+mod attachment;
+pub use attachment::*;
+mod attachment_report;
+pub use attachment_report::*;
+mod block;
+pub use block::*;
+mod canvas;
+pub use canvas::*;
+mod change_set;
+pub use change_set::*;
+mod diff;
+pub use diff::*;
+mod embed;
+pub use embed::{Embed, EmbedError};
+mod external_link;
+pub use external_link::*;
 mod file;
 pub use file::*;
 mod page;
 pub use page::*;
 mod link;
 pub use link::*;
+mod link_graph;
+pub use link_graph::*;
+mod link_parts;
+pub use link_parts::*;
+mod link_resolver;
+pub use link_resolver::*;
 mod link_span;
 pub use link_span::*;
+mod parse_cache;
+mod periodic_notes;
+pub use periodic_notes::*;
+mod properties;
+pub use properties::*;
+mod query;
+pub use query::*;
+mod reference_match;
+mod search;
+pub use search::*;
 mod span;
 pub use span::*;
+mod storage;
+pub use storage::*;
 mod tag;
 pub use tag::*;
+mod template;
+pub use template::*;
 mod vault;
 pub use vault::Vault;
+mod vault_config;
+pub use vault_config::*;
+mod vault_error;
+pub use vault_error::*;
 mod vault_item;
 pub use vault_item::*;
+mod watch;
+pub use watch::{VaultEvent, VaultWatcher};
 mod wiki_link_string;
 pub use wiki_link_string::*;
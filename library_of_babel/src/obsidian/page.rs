@@ -1,26 +1,148 @@
 use super::*;
+use std::ops::Range;
 
 #[derive(Debug, Clone)]
 pub struct Page {
     pub id: VaultItemId,
     pub file: File,
     pub contents: String,
+    /// What the file looked like when we loaded or last saved it. The page
+    /// is dirty if `contents` no longer matches.
+    pub disk_state: DiskState,
+    /// The properties from the page's frontmatter. Empty if it doesn't have any.
+    pub properties: Properties,
+    /// Where the body starts in `contents`, right after the frontmatter.
+    /// Zero if the page doesn't have frontmatter.
+    pub body_offset: usize,
+    /// Headings, lists, code blocks and so on, with ranges into `contents`.
+    pub blocks: BlockTree,
+    /// Wiki links and Markdown links to items in the vault.
     pub reference_spans: Vec<LinkSpan>,
-    pub tags: Vec<String>,
+    /// Markdown links to things outside the vault, like `[Obsidian](https://obsidian.md)`.
+    pub external_links: Vec<ExternalLink>,
+    /// Tags from the body and from the `tags` property.
+    pub tags: Vec<Tag>,
 }
 
 impl Page {
-    pub fn parse(files: &[&File], file: File, contents: String) -> Page {
-        let parsed_page_contents = parse_page_contents(&contents, files);
+    pub fn parse(link_resolver: &LinkResolver, file: File, contents: String) -> Page {
+        let id = VaultItemId::from_file(&file);
+        let parsed_page_contents = parse_page_contents(&contents, &id, link_resolver);
+        Page::from_parsed_contents(id, file, contents, parsed_page_contents)
+    }
+
+    /// Like `parse`, but reuses what an earlier `parse` found in the same
+    /// contents. Only the links need resolving again, since other files
+    /// might have come or gone.
+    pub(super) fn from_cache(
+        link_resolver: &LinkResolver,
+        file: File,
+        contents: String,
+        mut parsed_page_contents: ParsedPageContents,
+    ) -> Page {
+        let id = VaultItemId::from_file(&file);
+        for reference_span in &mut parsed_page_contents.reference_spans {
+            reference_span.link.resolve(&id, link_resolver);
+        }
+        Page::from_parsed_contents(id, file, contents, parsed_page_contents)
+    }
+
+    fn from_parsed_contents(
+        id: VaultItemId,
+        file: File,
+        contents: String,
+        parsed_page_contents: ParsedPageContents,
+    ) -> Page {
+        let disk_state = DiskState {
+            modified_at: file.modified_at,
+            content_hash: content_hash(contents.as_bytes()),
+        };
         Page {
-            id: VaultItemId::from_file(&file),
+            id,
             file,
             contents,
+            disk_state,
+            properties: parsed_page_contents.properties,
+            body_offset: parsed_page_contents.body_offset,
+            blocks: parsed_page_contents.blocks,
             reference_spans: parsed_page_contents.reference_spans,
+            external_links: parsed_page_contents.external_links,
             tags: parsed_page_contents.tags,
         }
     }
 
+    /// Replaces the page's contents and re-parses everything that depends
+    /// on them. Doesn't touch the file on disk.
+    pub fn set_contents(&mut self, contents: String, link_resolver: &LinkResolver) {
+        self.file.contents = Contents::Markdown {
+            text: contents.clone(),
+        };
+        let disk_state = self.disk_state;
+        *self = Page::parse(link_resolver, self.file.clone(), contents);
+        self.disk_state = disk_state;
+    }
+
+    /// Replaces each range of `contents` with its new text. The ranges
+    /// can't overlap.
+    pub fn replace_ranges(
+        &mut self,
+        replacements: Vec<(Range<usize>, String)>,
+        link_resolver: &LinkResolver,
+    ) {
+        let contents = replace_ranges_in(&self.contents, replacements);
+        self.set_contents(contents, link_resolver);
+    }
+
+    /// True if `contents` has changed since the page was loaded or last saved.
+    pub fn is_dirty(&self) -> bool {
+        content_hash(self.contents.as_bytes()) != self.disk_state.content_hash
+    }
+
+    /// Writes the page to its file if it's dirty. Refuses with
+    /// `VaultError::ChangedOnDisk` if the file was modified since we loaded
+    /// it, because Obsidian or sync might have edited it in the meantime.
+    pub fn save(&mut self, storage: &dyn VaultStorage) -> Result<(), VaultError> {
+        if !self.is_dirty() {
+            return Ok(());
+        }
+
+        self.disk_state = save_unless_changed_on_disk(
+            storage,
+            &self.file.absolute_path,
+            &self.disk_state,
+            self.contents.as_bytes(),
+        )?;
+        self.file.modified_at = self.disk_state.modified_at;
+        Ok(())
+    }
+
+    pub(super) fn parsed_contents(&self) -> ParsedPageContents {
+        ParsedPageContents {
+            properties: self.properties.clone(),
+            body_offset: self.body_offset,
+            blocks: self.blocks.clone(),
+            reference_spans: self.reference_spans.clone(),
+            external_links: self.external_links.clone(),
+            tags: self.tags.clone(),
+        }
+    }
+
+    /// Everything after the frontmatter.
+    pub fn body(&self) -> &str {
+        &self.contents[self.body_offset..]
+    }
+
+    /// The text under a heading, including the heading itself. See
+    /// `BlockTree::section`.
+    pub fn section(&self, heading_path: &[impl AsRef<str>]) -> Option<&str> {
+        let section = self.blocks.section(heading_path)?;
+        Some(&self.contents[section.range])
+    }
+
+    pub fn aliases(&self) -> Vec<String> {
+        self.properties.aliases()
+    }
+
     pub fn find_and_replace_text_for_references<GetNewReferenceText>(
         &mut self,
         get_new_reference_text: GetNewReferenceText,
@@ -60,6 +182,13 @@ impl Page {
             .find(|reference_span| reference_span.link_text() == link_text)
     }
 
+    /// See `Tag::matches`.
+    pub fn has_tag(&self, tag_name: &str, include_children: bool) -> bool {
+        self.tags
+            .iter()
+            .any(|tag| tag.matches(tag_name, include_children))
+    }
+
     pub fn has_a_reference_to(&self, target_id: &VaultItemId) -> bool {
         self.reference_spans
             .iter()
@@ -73,7 +202,7 @@ impl<'a> TryFrom<&'a VaultItem> for &'a Page {
     fn try_from(vault_item: &'a VaultItem) -> Result<Self, Self::Error> {
         match vault_item {
             VaultItem::Page(page) => Ok(page),
-            VaultItem::NonPage { .. } => Err(()),
+            _ => Err(()),
         }
     }
 }
@@ -84,21 +213,79 @@ impl<'a> TryFrom<&'a mut VaultItem> for &'a mut Page {
     fn try_from(vault_item: &'a mut VaultItem) -> Result<Self, Self::Error> {
         match vault_item {
             VaultItem::Page(page) => Ok(page),
-            VaultItem::NonPage { .. } => Err(()),
+            _ => Err(()),
         }
     }
 }
 
-fn parse_page_contents(page_contents: &str, files: &[&File]) -> ParsedPageContents {
-    let reference_spans = LinkSpan::parse_reference_spans(page_contents, files);
-    let tags = Tag::parse_tags(page_contents);
+/// `text` with each range replaced by its new text. The ranges can't overlap.
+pub(super) fn replace_ranges_in(
+    text: &str,
+    mut replacements: Vec<(Range<usize>, String)>,
+) -> String {
+    replacements.sort_by_key(|(range, _)| range.start);
+
+    let mut new_text = String::with_capacity(text.len());
+    let mut end_of_last_replacement = 0;
+    for (range, replacement) in replacements {
+        new_text.push_str(&text[end_of_last_replacement..range.start]);
+        new_text.push_str(&replacement);
+        end_of_last_replacement = range.end;
+    }
+    new_text.push_str(&text[end_of_last_replacement..]);
+    new_text
+}
+
+fn parse_page_contents(
+    page_contents: &str,
+    page_id: &VaultItemId,
+    link_resolver: &LinkResolver,
+) -> ParsedPageContents {
+    let (properties, body_offset) = match Frontmatter::parse(page_contents) {
+        Some(frontmatter) => {
+            let body_offset = frontmatter.body_offset();
+            (frontmatter.properties, body_offset)
+        }
+        None => (Properties::default(), 0),
+    };
+
+    // Parsing blocks is the slow part, so we only do it once.
+    let blocks = BlockTree::parse(page_contents);
+    let code_ranges = blocks.code_ranges(page_contents);
+
+    // Links in properties count, so we look for references in the whole page.
+    let reference_spans =
+        LinkSpan::parse_reference_spans(page_contents, &code_ranges, page_id, link_resolver);
+    let external_links = ExternalLink::parse_external_links(page_contents, &code_ranges);
+
+    // In frontmatter, `#` starts a YAML comment rather than a tag.
+    let mut tags: Vec<Tag> = Tag::parse_tags(page_contents, &code_ranges)
+        .into_iter()
+        .filter(|tag| {
+            tag.span
+                .as_ref()
+                .is_some_and(|span| span.range.start >= body_offset)
+        })
+        .collect();
+    tags.extend(properties.tags().iter().map(|name| Tag::new(name)));
+
     ParsedPageContents {
+        properties,
+        body_offset,
+        blocks,
         reference_spans,
+        external_links,
         tags,
     }
 }
 
-struct ParsedPageContents {
-    reference_spans: Vec<LinkSpan>,
-    tags: Vec<String>,
+/// Everything `Page::parse` works out from a page's contents.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub(super) struct ParsedPageContents {
+    pub properties: Properties,
+    pub body_offset: usize,
+    pub blocks: BlockTree,
+    pub reference_spans: Vec<LinkSpan>,
+    pub external_links: Vec<ExternalLink>,
+    pub tags: Vec<Tag>,
 }
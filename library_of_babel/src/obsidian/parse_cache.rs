@@ -0,0 +1,217 @@
+use super::{Contents, File, Page, ParsedPageContents, VaultError, VaultStorage};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Where the cache lives, relative to the vault root. It's hidden, so the
+/// vault never loads it as an attachment.
+const parse_cache_path: &str = ".library_of_babel/parse-cache.json";
+
+/// Bump this whenever parsing changes, so caches from older versions get
+/// thrown away instead of handing back stale results.
+const parse_cache_version: u32 = 3;
+
+/// What we found in each page the last time the vault was opened, so
+/// opening it again only parses the pages that changed since.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub(super) struct ParseCache {
+    version: u32,
+    /// By path from the vault root.
+    entries: HashMap<String, CacheEntry>,
+    /// Pages that weren't in the cache, or had changed since.
+    #[serde(skip)]
+    misses: usize,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct CacheEntry {
+    modified_at: SystemTime,
+    /// In bytes. Together with `modified_at`, this tells us whether the
+    /// page changed, without having to hash it.
+    len: usize,
+    parsed_page_contents: ParsedPageContents,
+}
+
+impl ParseCache {
+    /// An empty cache if there isn't one yet, or if it can't be read.
+    pub(super) fn load(storage: &dyn VaultStorage, vault_path: &Path) -> ParseCache {
+        let empty_cache = ParseCache {
+            version: parse_cache_version,
+            ..ParseCache::default()
+        };
+
+        let Ok(bytes) = storage.read(&vault_path.join(parse_cache_path)) else {
+            return empty_cache;
+        };
+        match serde_json::from_slice::<ParseCache>(&bytes) {
+            Ok(cache) if cache.version == parse_cache_version => cache,
+            _ => empty_cache,
+        }
+    }
+
+    /// What the cache has for the page in `file`, if the file hasn't
+    /// changed since it was cached. Each entry can only be taken once.
+    pub(super) fn take(&mut self, file: &File) -> Option<ParsedPageContents> {
+        let Contents::Markdown { text } = &file.contents else {
+            return None;
+        };
+
+        match self.entries.remove(&file.path_from_vault_root) {
+            Some(entry) if entry.modified_at == file.modified_at && entry.len == text.len() => {
+                Some(entry.parsed_page_contents)
+            }
+            _ => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Whether the cache on disk is out of date, once every page has been
+    /// taken from it. Leftover entries are for pages that are gone.
+    pub(super) fn is_stale(&self) -> bool {
+        self.misses > 0 || !self.entries.is_empty()
+    }
+
+    /// Replaces the cache on disk with what's in `pages`.
+    pub(super) fn save<'p>(
+        storage: &dyn VaultStorage,
+        vault_path: &Path,
+        pages: impl IntoIterator<Item = &'p Page>,
+    ) -> Result<(), VaultError> {
+        let entries = pages
+            .into_iter()
+            .map(|page| {
+                let entry = CacheEntry {
+                    modified_at: page.file.modified_at,
+                    len: page.contents.len(),
+                    parsed_page_contents: page.parsed_contents(),
+                };
+                (page.file.path_from_vault_root.clone(), entry)
+            })
+            .collect();
+        let cache = ParseCache {
+            version: parse_cache_version,
+            entries,
+            misses: 0,
+        };
+
+        let path = vault_path.join(parse_cache_path);
+        let json = serde_json::to_vec(&cache)
+            .map_err(|error| VaultError::io(&path, std::io::Error::other(error)))?;
+        storage.write(&path, &json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obsidian::vault_item::parse_files;
+    use crate::obsidian::{files_in_vault, Vault, VaultItem, VaultItemId};
+
+    fn vault() -> Vault {
+        let created_at = SystemTime::UNIX_EPOCH;
+        Vault::from_files([
+            ("Reading.md", "I'm reading [[Richard Feynman]].", created_at),
+            ("Richard Feynman.md", "Physicist.", created_at),
+            ("Julian Schwinger.md", "Physicist.", created_at),
+        ])
+        .unwrap()
+    }
+
+    fn saved_cache(vault: &Vault) -> ParseCache {
+        ParseCache::save(vault.storage(), vault.path(), vault.pages()).unwrap();
+        ParseCache::load(vault.storage(), vault.path())
+    }
+
+    /// Loads the vault's files from storage again and parses them with
+    /// `cache`, like opening the vault again does.
+    fn reparse(vault: &Vault, cache: &mut ParseCache) -> Vec<VaultItem> {
+        let files = files_in_vault(vault.storage(), vault.path_str(), vault.config())
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        parse_files(files, cache).0
+    }
+
+    fn reading_links(items: &[VaultItem]) -> Vec<Option<VaultItemId>> {
+        let reading = items
+            .iter()
+            .filter_map(VaultItem::try_into_page)
+            .find(|page| page.id == VaultItemId::from("Reading.md"))
+            .unwrap();
+        reading
+            .reference_spans
+            .iter()
+            .map(|span| span.link.vault_item_id.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_unchanged_pages_come_from_the_cache() {
+        let vault = vault();
+        let mut cache = saved_cache(&vault);
+        assert_eq!(cache.entries.len(), 3);
+
+        let items = reparse(&vault, &mut cache);
+        assert_eq!(cache.misses, 0);
+        assert!(!cache.is_stale());
+        assert_eq!(
+            reading_links(&items),
+            [Some(VaultItemId::from("Richard Feynman.md"))]
+        );
+    }
+
+    #[test]
+    fn test_changed_pages_are_parsed_again() {
+        let vault = vault();
+        let mut cache = saved_cache(&vault);
+        let reading_path = vault.absolute_path_to_item(&VaultItemId::from("Reading.md"));
+        vault
+            .storage()
+            .write(&reading_path, b"I'm reading [[Julian Schwinger]].")
+            .unwrap();
+
+        let items = reparse(&vault, &mut cache);
+        assert_eq!(cache.misses, 1);
+        assert!(cache.is_stale());
+        assert_eq!(
+            reading_links(&items),
+            [Some(VaultItemId::from("Julian Schwinger.md"))]
+        );
+    }
+
+    #[test]
+    fn test_deleted_pages_are_dropped_from_the_cache() {
+        let vault = vault();
+        let mut cache = saved_cache(&vault);
+        let feynman_path = vault.absolute_path_to_item(&VaultItemId::from("Richard Feynman.md"));
+        vault.storage().remove_file(&feynman_path).unwrap();
+
+        let items = reparse(&vault, &mut cache);
+        assert_eq!(cache.misses, 0);
+        assert!(cache.is_stale());
+
+        let pages = items.iter().filter_map(VaultItem::try_into_page);
+        ParseCache::save(vault.storage(), vault.path(), pages).unwrap();
+        let cache = ParseCache::load(vault.storage(), vault.path());
+        assert_eq!(cache.entries.len(), 2);
+        assert!(!cache.entries.contains_key("Richard Feynman.md"));
+    }
+
+    #[test]
+    fn test_caches_from_other_versions_are_ignored() {
+        let vault = vault();
+        let mut cache = saved_cache(&vault);
+        cache.version = parse_cache_version - 1;
+        let json = serde_json::to_vec(&cache).unwrap();
+        vault
+            .storage()
+            .write(&vault.path().join(parse_cache_path), &json)
+            .unwrap();
+
+        let cache = ParseCache::load(vault.storage(), vault.path());
+        assert_eq!(cache.version, parse_cache_version);
+        assert!(cache.entries.is_empty());
+    }
+}
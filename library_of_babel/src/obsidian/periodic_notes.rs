@@ -0,0 +1,482 @@
+use super::{
+    render_template, ChangeSetError, TemplateVars, Vault, VaultChangeSet, VaultError, VaultItemId,
+};
+use chrono::{Datelike, Days, Local, Months, NaiveDate};
+use std::collections::HashMap;
+use std::io;
+
+/// Where the Periodic Notes plugin keeps its settings, relative to the vault root.
+const plugin_settings_path: &str = ".obsidian/plugins/periodic-notes/data.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Periodicity {
+    Day,
+    /// ISO weeks, which start on Monday and belong to the year their
+    /// Thursday is in.
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+/// One day, week, month, quarter or year.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Period {
+    pub periodicity: Periodicity,
+    /// The first day of the period.
+    pub start: NaiveDate,
+}
+
+/// Which notes to create for each kind of period, and where. Mirrors the
+/// settings of Obsidian's Periodic Notes plugin.
+#[derive(Debug, Clone)]
+pub struct PeriodicNotes {
+    /// Periodicities without settings don't get notes.
+    pub settings: Vec<PeriodicNoteSettings>,
+    /// Notes for periods outside the current year go under this folder,
+    /// like `years/2023/2023.01.md`. `None` keeps every year in the same place.
+    pub other_years_folder: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PeriodicNoteSettings {
+    pub periodicity: Periodicity,
+    /// Relative to the vault root. Empty for the root.
+    pub folder: String,
+    /// A Moment.js format, like Obsidian uses. See `format_date`. It can
+    /// include slashes to put notes in subfolders, like `YYYY/YYYY-MM-DD`.
+    pub format: String,
+    /// What new notes start out with. Without a template, they get links
+    /// to the previous, parent and next periods.
+    pub template: Option<NoteTemplate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NoteTemplate {
+    Text(String),
+    /// A template's path, relative to the templates folder or the vault
+    /// root. See `Vault::template`. Notes start out empty if it doesn't exist.
+    Page(String),
+}
+
+impl Period {
+    /// The period of this kind that `date` falls in.
+    pub fn containing(periodicity: Periodicity, date: NaiveDate) -> Period {
+        let start = match periodicity {
+            Periodicity::Day => date,
+            Periodicity::Week => date - Days::new(date.weekday().num_days_from_monday() as u64),
+            Periodicity::Month => date.with_day(1).expect("Every month has a first day."),
+            Periodicity::Quarter => {
+                let first_month = (date.month0() / 3) * 3 + 1;
+                NaiveDate::from_ymd_opt(date.year(), first_month, 1)
+                    .expect("Every quarter has a first day.")
+            }
+            Periodicity::Year => {
+                NaiveDate::from_ymd_opt(date.year(), 1, 1).expect("Every year has a first day.")
+            }
+        };
+        Period { periodicity, start }
+    }
+
+    pub fn next(&self) -> Period {
+        let start = match self.periodicity {
+            Periodicity::Day => self.start + Days::new(1),
+            Periodicity::Week => self.start + Days::new(7),
+            Periodicity::Month => self.start + Months::new(1),
+            Periodicity::Quarter => self.start + Months::new(3),
+            Periodicity::Year => self.start + Months::new(12),
+        };
+        Period { start, ..*self }
+    }
+
+    pub fn previous(&self) -> Period {
+        let start = match self.periodicity {
+            Periodicity::Day => self.start - Days::new(1),
+            Periodicity::Week => self.start - Days::new(7),
+            Periodicity::Month => self.start - Months::new(1),
+            Periodicity::Quarter => self.start - Months::new(3),
+            Periodicity::Year => self.start - Months::new(12),
+        };
+        Period { start, ..*self }
+    }
+
+    /// The last day of the period.
+    pub fn end(&self) -> NaiveDate {
+        self.next().start - Days::new(1)
+    }
+
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.start <= date && date <= self.end()
+    }
+
+    /// The day that decides which bigger periods this one belongs to. For
+    /// weeks, that's Thursday, so week 1 of 2026 is part of 2026 even
+    /// though it starts in 2025.
+    fn anchor(&self) -> NaiveDate {
+        match self.periodicity {
+            Periodicity::Week => self.start + Days::new(3),
+            _ => self.start,
+        }
+    }
+}
+
+impl PeriodicNoteSettings {
+    /// The plugin's defaults: notes in the vault root, named like
+    /// `2024-01-31`, `2024-W05`, `2024-01`, `2024-Q1` and `2024`.
+    pub fn new(periodicity: Periodicity) -> PeriodicNoteSettings {
+        let format = match periodicity {
+            Periodicity::Day => "YYYY-MM-DD",
+            Periodicity::Week => "gggg-[W]ww",
+            Periodicity::Month => "YYYY-MM",
+            Periodicity::Quarter => "YYYY-[Q]Q",
+            Periodicity::Year => "YYYY",
+        };
+        PeriodicNoteSettings {
+            periodicity,
+            folder: String::new(),
+            format: format.to_string(),
+            template: None,
+        }
+    }
+}
+
+impl PeriodicNotes {
+    pub fn new(settings: Vec<PeriodicNoteSettings>) -> PeriodicNotes {
+        PeriodicNotes {
+            settings,
+            other_years_folder: None,
+        }
+    }
+
+    /// Reads the Periodic Notes plugin's settings from the vault. Falls
+    /// back to daily notes with the plugin's defaults if there aren't any.
+    pub fn read(vault: &Vault) -> Result<PeriodicNotes, VaultError> {
+        let path = vault.path().join(plugin_settings_path);
+        if !vault.storage().is_file(&path) {
+            return Ok(PeriodicNotes::new(vec![PeriodicNoteSettings::new(
+                Periodicity::Day,
+            )]));
+        }
+
+        let bytes = vault.storage().read(&path)?;
+        let plugin_settings: serde_json::Value =
+            serde_json::from_slice(&bytes).map_err(|error| {
+                VaultError::io(&path, io::Error::new(io::ErrorKind::InvalidData, error))
+            })?;
+
+        let keys = [
+            (Periodicity::Day, "daily"),
+            (Periodicity::Week, "weekly"),
+            (Periodicity::Month, "monthly"),
+            (Periodicity::Quarter, "quarterly"),
+            (Periodicity::Year, "yearly"),
+        ];
+        let settings = keys
+            .into_iter()
+            .filter_map(|(periodicity, key)| {
+                let settings = plugin_settings.get(key)?;
+                if settings.get("enabled")?.as_bool() != Some(true) {
+                    return None;
+                }
+
+                let string_setting = |name: &str| {
+                    settings
+                        .get(name)
+                        .and_then(serde_json::Value::as_str)
+                        .map(str::trim)
+                        .filter(|value| !value.is_empty())
+                };
+                let defaults = PeriodicNoteSettings::new(periodicity);
+                Some(PeriodicNoteSettings {
+                    periodicity,
+                    folder: string_setting("folder")
+                        .map(|folder| folder.trim_matches('/').to_string())
+                        .unwrap_or(defaults.folder),
+                    format: string_setting("format")
+                        .map(str::to_string)
+                        .unwrap_or(defaults.format),
+                    template: string_setting("templatePath")
+                        .map(|path| NoteTemplate::Page(path.to_string())),
+                })
+            })
+            .collect();
+
+        Ok(PeriodicNotes::new(settings))
+    }
+
+    pub fn settings_for(&self, periodicity: Periodicity) -> Option<&PeriodicNoteSettings> {
+        self.settings
+            .iter()
+            .find(|settings| settings.periodicity == periodicity)
+    }
+
+    /// Where the period's note goes. `None` if this kind of period doesn't
+    /// get notes.
+    pub fn id_for(&self, period: &Period) -> Option<VaultItemId> {
+        let settings = self.settings_for(period.periodicity)?;
+        let mut path = format_date(period.start, &settings.format);
+        if !settings.folder.is_empty() {
+            path = format!("{}/{path}", settings.folder);
+        }
+
+        if let Some(other_years_folder) = &self.other_years_folder {
+            if period.anchor().year() != Local::now().year() {
+                path = format!("{other_years_folder}/{path}");
+            }
+        }
+
+        Some(VaultItemId::from_path_from_vault_root(format!("{path}.md")))
+    }
+
+    /// The smallest bigger period that gets notes. Days belong to weeks,
+    /// then months, quarters and years. Weeks belong to the month their
+    /// Thursday is in.
+    pub fn parent(&self, period: &Period) -> Option<Period> {
+        let bigger_periodicities: &[Periodicity] = match period.periodicity {
+            Periodicity::Day => &[
+                Periodicity::Week,
+                Periodicity::Month,
+                Periodicity::Quarter,
+                Periodicity::Year,
+            ],
+            Periodicity::Week => &[Periodicity::Month, Periodicity::Quarter, Periodicity::Year],
+            Periodicity::Month => &[Periodicity::Quarter, Periodicity::Year],
+            Periodicity::Quarter => &[Periodicity::Year],
+            Periodicity::Year => &[],
+        };
+
+        bigger_periodicities
+            .iter()
+            .find(|periodicity| self.settings_for(**periodicity).is_some())
+            .map(|periodicity| Period::containing(*periodicity, period.anchor()))
+    }
+
+    /// A wiki link to the period's note, like `[[2024-01-31]]`. Empty if
+    /// the period doesn't get notes.
+    pub fn link_to(&self, period: &Period) -> String {
+        match self.id_for(period) {
+            Some(id) => format!("[[{}]]", id.file_stem()),
+            None => String::new(),
+        }
+    }
+
+    /// What a new note for the period starts out with: its template,
+    /// rendered with `render_template`. The period's title is its note's
+    /// name, and its date is its first day.
+    pub fn contents_for(&self, vault: &Vault, period: &Period) -> String {
+        let template = self
+            .settings_for(period.periodicity)
+            .and_then(|settings| settings.template.as_ref());
+        let title = self
+            .id_for(period)
+            .map(|id| id.file_stem())
+            .unwrap_or_default();
+        let vars = TemplateVars {
+            values: HashMap::new(),
+            date: Some(period.start),
+            period: Some((*period, self.clone())),
+        };
+
+        match template {
+            Some(NoteTemplate::Text(text)) => render_template(vault, text, &title, &vars),
+            Some(NoteTemplate::Page(name)) => match vault.template(name) {
+                Some(page) => render_template(vault, &page.contents, &title, &vars),
+                None => String::new(),
+            },
+            None => {
+                let links: Vec<String> = [
+                    self.link_to(&period.previous()),
+                    self.parent(period)
+                        .map(|parent| self.link_to(&parent))
+                        .unwrap_or_default(),
+                    self.link_to(&period.next()),
+                ]
+                .into_iter()
+                .filter(|link| !link.is_empty())
+                .collect();
+                format!("{}\n", links.join(" · "))
+            }
+        }
+    }
+
+    /// Every period that gets notes and overlaps `start..=end`, biggest
+    /// first, so parents come before their children.
+    pub fn periods_in_range(&self, start: NaiveDate, end: NaiveDate) -> Vec<Period> {
+        let mut periodicities: Vec<Periodicity> = self
+            .settings
+            .iter()
+            .map(|settings| settings.periodicity)
+            .collect();
+        periodicities.sort_by(|a, b| b.cmp(a));
+        periodicities.dedup();
+
+        let mut periods = vec![];
+        for periodicity in periodicities {
+            let mut period = Period::containing(periodicity, start);
+            while period.start <= end {
+                periods.push(period);
+                period = period.next();
+            }
+        }
+        periods
+    }
+
+    /// Notes for every period in `start..=end` that doesn't have one yet.
+    pub fn plan_range(&self, vault: &Vault, start: NaiveDate, end: NaiveDate) -> VaultChangeSet {
+        let mut change_set = VaultChangeSet::new();
+        for period in self.periods_in_range(start, end) {
+            let Some(id) = self.id_for(&period) else {
+                continue;
+            };
+            if vault.item(&id).is_none() && !change_set.creates(&id) {
+                let contents = self.contents_for(vault, &period);
+                change_set.create_page(id, contents);
+            }
+        }
+        change_set
+    }
+
+    /// Creates the missing notes for every period in `start..=end`, and
+    /// returns their ids. If one can't be created, none are.
+    pub fn ensure_range(
+        &self,
+        vault: &mut Vault,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<VaultItemId>, ChangeSetError> {
+        let change_set = self.plan_range(vault, start, end);
+        change_set.apply(vault)?;
+        Ok(change_set.created_ids())
+    }
+}
+
+/// Longer tokens come first, so `YYYY` isn't read as two `YY`s.
+const date_tokens: [&str; 22] = [
+    "YYYY", "YY", "GGGG", "GG", "gggg", "gg", "Q", "MMMM", "MMM", "MM", "M", "DDDD", "DDD", "DD",
+    "D", "dddd", "ddd", "d", "WW", "W", "ww", "w",
+];
+
+/// Formats `date` with a Moment.js format, which is what Obsidian uses
+/// for note names. Supports years (`YYYY`, `YY`), ISO week years (`GGGG`,
+/// `gggg`), quarters (`Q`), months (`MMMM`, `MMM`, `MM`, `M`), days of the
+/// month (`DD`, `D`) and year (`DDDD`, `DDD`), weekdays (`dddd`, `ddd`,
+/// `d`) and ISO weeks (`WW`, `ww`, `W`, `w`). Text in square brackets is
+/// copied as is, like `[W]`.
+pub fn format_date(date: NaiveDate, format: &str) -> String {
+    let mut formatted = String::new();
+    let mut rest = format;
+
+    while let Some(first) = rest.chars().next() {
+        if first == '[' {
+            if let Some(end) = rest.find(']') {
+                formatted.push_str(&rest[1..end]);
+                rest = &rest[end + 1..];
+                continue;
+            }
+        }
+
+        match date_tokens.iter().find(|token| rest.starts_with(**token)) {
+            Some(token) => {
+                formatted.push_str(&format_date_token(date, token));
+                rest = &rest[token.len()..];
+            }
+            None => {
+                formatted.push(first);
+                rest = &rest[first.len_utf8()..];
+            }
+        }
+    }
+
+    formatted
+}
+
+fn format_date_token(date: NaiveDate, token: &str) -> String {
+    let week = date.iso_week();
+    match token {
+        "YYYY" => format!("{:04}", date.year()),
+        "YY" => format!("{:02}", date.year().rem_euclid(100)),
+        "GGGG" | "gggg" => format!("{:04}", week.year()),
+        "GG" | "gg" => format!("{:02}", week.year().rem_euclid(100)),
+        "Q" => (date.month0() / 3 + 1).to_string(),
+        "MMMM" => date.format("%B").to_string(),
+        "MMM" => date.format("%b").to_string(),
+        "MM" => format!("{:02}", date.month()),
+        "M" => date.month().to_string(),
+        "DDDD" => format!("{:03}", date.ordinal()),
+        "DDD" => date.ordinal().to_string(),
+        "DD" => format!("{:02}", date.day()),
+        "D" => date.day().to_string(),
+        "dddd" => date.format("%A").to_string(),
+        "ddd" => date.format("%a").to_string(),
+        "d" => date.weekday().num_days_from_sunday().to_string(),
+        "WW" | "ww" => format!("{:02}", week.week()),
+        "W" | "w" => week.week().to_string(),
+        _ => token.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(
+            format_date(date(2024, 1, 5), "YYYY/YYYY.MM.DD"),
+            "2024/2024.01.05"
+        );
+        assert_eq!(format_date(date(2024, 12, 30), "gggg-[W]ww"), "2025-W01");
+        assert_eq!(
+            format_date(date(2024, 8, 1), "YYYY-[Q]Q, MMMM D"),
+            "2024-Q3, August 1"
+        );
+    }
+
+    #[test]
+    fn test_ensure_range() {
+        let mut vault = Vault::from_files([(
+            "journal/2024-01-01.md",
+            "Already here.",
+            SystemTime::UNIX_EPOCH,
+        )])
+        .unwrap();
+
+        let mut days = PeriodicNoteSettings::new(Periodicity::Day);
+        days.folder = "journal".to_string();
+        days.template = Some(NoteTemplate::Text(
+            "# {{title}}\n{{period.previous}} · {{period.parent}} · {{period.next}}\n".to_string(),
+        ));
+        let weeks = PeriodicNoteSettings::new(Periodicity::Week);
+        let periodic_notes = PeriodicNotes::new(vec![days, weeks]);
+
+        let created = periodic_notes
+            .ensure_range(&mut vault, date(2024, 1, 1), date(2024, 1, 2))
+            .unwrap();
+        let created: Vec<&str> = created.iter().map(|id| id.path_from_vault_root()).collect();
+        assert_eq!(created, vec!["2024-W01.md", "journal/2024-01-02.md"]);
+
+        let contents = |path: &str| {
+            vault
+                .item_at_path(path)
+                .and_then(|item| item.try_into_page())
+                .map(|page| page.contents.clone())
+        };
+        assert_eq!(
+            contents("journal/2024-01-02.md").as_deref(),
+            Some("# 2024-01-02\n[[2024-01-01]] · [[2024-W01]] · [[2024-01-03]]\n")
+        );
+        assert_eq!(
+            contents("2024-W01.md").as_deref(),
+            Some("[[2023-W52]] · [[2024-W02]]\n")
+        );
+        assert_eq!(
+            contents("journal/2024-01-01.md").as_deref(),
+            Some("Already here.")
+        );
+    }
+}
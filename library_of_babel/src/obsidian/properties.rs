@@ -0,0 +1,411 @@
+use super::WikiLinkString;
+use chrono::{NaiveDate, NaiveDateTime};
+use std::ops::Range;
+
+/// The YAML block at the top of a page, between two `---` lines.
+/// Obsidian calls the fields in it properties.
+#[derive(Debug, Clone, Default)]
+pub struct Frontmatter {
+    pub properties: Properties,
+    /// Where the frontmatter is in the page's contents, including both `---` lines.
+    pub range: Range<usize>,
+}
+
+impl Frontmatter {
+    /// Returns `None` if `page_contents` doesn't start with a frontmatter block.
+    pub fn parse(page_contents: &str) -> Option<Frontmatter> {
+        let first_line_end = page_contents.find('\n')?;
+        if page_contents[..first_line_end].trim_end() != "---" {
+            return None;
+        }
+
+        let yaml_start = first_line_end + 1;
+        let mut line_start = yaml_start;
+        for line in page_contents[yaml_start..].split_inclusive('\n') {
+            let line_end = line_start + line.len();
+            let trimmed_line = line.trim_end();
+
+            if trimmed_line == "---" || trimmed_line == "..." {
+                let yaml = &page_contents[yaml_start..line_start];
+                return Some(Frontmatter {
+                    properties: Properties::parse_yaml(yaml),
+                    range: 0..line_end,
+                });
+            }
+
+            line_start = line_end;
+        }
+
+        // There was an opening `---` but no closing one, so this is a thematic break.
+        None
+    }
+
+    /// Where the body of the page starts.
+    pub fn body_offset(&self) -> usize {
+        self.range.end
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum PropertyValue {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+    List(Vec<PropertyValue>),
+    /// A quoted wiki link, like `"[[Richard Feynman]]"`.
+    Link(WikiLinkString),
+    /// An empty value, `null`, or `~`.
+    Null,
+}
+
+impl PropertyValue {
+    fn parse_scalar(text: &str) -> PropertyValue {
+        let text = text.trim();
+
+        let (unquoted, was_quoted) = match unquote(text) {
+            Some(unquoted) => (unquoted, true),
+            None => (text, false),
+        };
+
+        if let Ok(wiki_link) = unquoted.parse::<WikiLinkString>() {
+            return PropertyValue::Link(wiki_link);
+        }
+
+        if was_quoted {
+            return PropertyValue::String(unquoted.to_string());
+        }
+
+        match text {
+            "" | "~" | "null" | "Null" | "NULL" => return PropertyValue::Null,
+            "true" | "True" | "TRUE" => return PropertyValue::Bool(true),
+            "false" | "False" | "FALSE" => return PropertyValue::Bool(false),
+            _ => {}
+        }
+
+        if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+            return PropertyValue::Date(date);
+        }
+
+        for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S"] {
+            if let Ok(date_time) = NaiveDateTime::parse_from_str(text, format) {
+                return PropertyValue::DateTime(date_time);
+            }
+        }
+
+        if let Ok(number) = text.parse::<f64>() {
+            if number.is_finite() {
+                return PropertyValue::Number(number);
+            }
+        }
+
+        PropertyValue::String(text.to_string())
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            PropertyValue::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// Lists become their items, `Null` becomes nothing, and everything else
+    /// becomes a list of one.
+    pub fn as_list(&self) -> Vec<&PropertyValue> {
+        match self {
+            PropertyValue::List(items) => items.iter().collect(),
+            PropertyValue::Null => vec![],
+            value => vec![value],
+        }
+    }
+}
+
+/// The properties from a page's frontmatter, in the order they were written.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Properties {
+    entries: Vec<(String, PropertyValue)>,
+}
+
+impl Properties {
+    /// Handles the subset of YAML that Obsidian writes for properties:
+    /// `key: value` pairs, block lists (`- item`), flow lists (`[a, b]`),
+    /// and block strings (`|` and `>`). Nested mappings aren't supported
+    /// and are kept as strings.
+    pub fn parse_yaml(yaml: &str) -> Properties {
+        let lines: Vec<&str> = yaml.lines().collect();
+        let mut entries = vec![];
+        let mut line_index = 0;
+
+        while line_index < lines.len() {
+            let line = lines[line_index];
+            line_index += 1;
+
+            if is_blank_or_comment(line) || line.starts_with([' ', '\t', '-']) {
+                continue;
+            }
+
+            let Some((key, value)) = split_key_and_value(line) else {
+                continue;
+            };
+
+            // Indented lines after the key belong to it.
+            let indented_lines_start = line_index;
+            while line_index < lines.len()
+                && (lines[line_index].starts_with([' ', '\t', '-'])
+                    || lines[line_index].trim().is_empty())
+            {
+                line_index += 1;
+            }
+            let indented_lines = &lines[indented_lines_start..line_index];
+
+            let value = parse_value(value, indented_lines);
+            entries.push((key, value));
+        }
+
+        Properties { entries }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&PropertyValue> {
+        self.entries
+            .iter()
+            .find(|(entry_key, _)| entry_key == key)
+            .map(|(_, value)| value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &PropertyValue)> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (key.as_str(), value))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The `tags` property, without leading `#`s. Accepts a list or a
+    /// comma- or space-separated string.
+    pub fn tags(&self) -> Vec<String> {
+        self.string_list(&["tags", "tag"])
+            .iter()
+            .flat_map(|tags| tags.split([',', ' ']))
+            .map(|tag| tag.trim().trim_start_matches('#'))
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// The `aliases` property. Obsidian resolves links to an alias to the page.
+    pub fn aliases(&self) -> Vec<String> {
+        self.string_list(&["aliases", "alias"])
+            .into_iter()
+            .filter(|alias| !alias.is_empty())
+            .collect()
+    }
+
+    fn string_list(&self, keys: &[&str]) -> Vec<String> {
+        keys.iter()
+            .filter_map(|key| self.get(key))
+            .flat_map(|value| value.as_list())
+            .filter_map(|value| match value {
+                PropertyValue::String(string) => Some(string.trim().to_string()),
+                PropertyValue::Number(number) => Some(number.to_string()),
+                PropertyValue::Link(wiki_link) => Some(wiki_link.parts.target.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+fn parse_value(value: &str, indented_lines: &[&str]) -> PropertyValue {
+    let value = strip_comment(value).trim();
+    let non_blank_lines: Vec<&str> = indented_lines
+        .iter()
+        .copied()
+        .filter(|line| !is_blank_or_comment(line))
+        .collect();
+
+    if value == "|" || value == ">" || value.starts_with("|-") || value.starts_with(">-") {
+        let separator = if value.starts_with('|') { "\n" } else { " " };
+        let text = non_blank_lines
+            .iter()
+            .map(|line| line.trim())
+            .collect::<Vec<_>>()
+            .join(separator);
+        return PropertyValue::String(text);
+    }
+
+    if value.is_empty() {
+        let is_block_list = !non_blank_lines.is_empty()
+            && non_blank_lines
+                .iter()
+                .all(|line| line.trim_start().starts_with('-'));
+
+        if is_block_list {
+            let items = non_blank_lines
+                .iter()
+                .map(|line| line.trim_start().trim_start_matches('-'))
+                .map(|item| PropertyValue::parse_scalar(strip_comment(item)))
+                .collect();
+            return PropertyValue::List(items);
+        }
+
+        if non_blank_lines.is_empty() {
+            return PropertyValue::Null;
+        }
+
+        // A nested mapping. We keep it as text.
+        let text = non_blank_lines
+            .iter()
+            .map(|line| line.trim())
+            .collect::<Vec<_>>()
+            .join("\n");
+        return PropertyValue::String(text);
+    }
+
+    // Unquoted wiki links look like nested flow lists to YAML, but
+    // people write them all the time, so we treat them as links.
+    let is_flow_list = value.starts_with('[') && value.ends_with(']') && !value.starts_with("[[");
+    if is_flow_list {
+        let items = split_flow_list(&value[1..value.len() - 1])
+            .into_iter()
+            .filter(|item| !item.trim().is_empty())
+            .map(PropertyValue::parse_scalar)
+            .collect();
+        return PropertyValue::List(items);
+    }
+
+    PropertyValue::parse_scalar(value)
+}
+
+fn split_key_and_value(line: &str) -> Option<(String, &str)> {
+    let (key, value) = if let Some(quote) = line.chars().next().filter(|c| *c == '"' || *c == '\'')
+    {
+        let closing_quote = line[1..].find(quote)? + 1;
+        let key = &line[1..closing_quote];
+        let rest = line[closing_quote + 1..].trim_start().strip_prefix(':')?;
+        (key, rest)
+    } else {
+        // The separator is a colon followed by whitespace or the end of the line,
+        // so URLs and times in values don't get split.
+        let separator = line
+            .match_indices(':')
+            .map(|(index, _)| index)
+            .find(|&index| {
+                line[index + 1..]
+                    .chars()
+                    .next()
+                    .is_none_or(char::is_whitespace)
+            })?;
+        (&line[..separator], &line[separator + 1..])
+    };
+
+    Some((key.trim().to_string(), value))
+}
+
+fn split_flow_list(list: &str) -> Vec<&str> {
+    let mut items = vec![];
+    let mut quote = None;
+    let mut bracket_depth = 0;
+    let mut item_start = 0;
+
+    for (index, char) in list.char_indices() {
+        match (char, quote) {
+            ('"' | '\'', None) => quote = Some(char),
+            (c, Some(open)) if c == open => quote = None,
+            ('[', None) => bracket_depth += 1,
+            (']', None) => bracket_depth -= 1,
+            (',', None) if bracket_depth == 0 => {
+                items.push(&list[item_start..index]);
+                item_start = index + 1;
+            }
+            _ => {}
+        }
+    }
+
+    items.push(&list[item_start..]);
+    items
+}
+
+fn unquote(text: &str) -> Option<&str> {
+    ['"', '\'']
+        .into_iter()
+        .find_map(|quote| text.strip_prefix(quote)?.strip_suffix(quote))
+}
+
+/// YAML comments start with a `#` that follows whitespace. Quoted `#`s aren't comments.
+fn strip_comment(value: &str) -> &str {
+    let mut quote = None;
+    let mut previous_char = ' ';
+
+    for (index, char) in value.char_indices() {
+        match (char, quote) {
+            ('"' | '\'', None) => quote = Some(char),
+            (c, Some(open)) if c == open => quote = None,
+            ('#', None) if previous_char.is_whitespace() => return &value[..index],
+            _ => {}
+        }
+        previous_char = char;
+    }
+
+    value
+}
+
+fn is_blank_or_comment(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frontmatter() {
+        let contents = "---\n\
+            title: \"Richard Feynman\"\n\
+            born: 1918-05-11\n\
+            nobel: true\n\
+            age: 69\n\
+            tags: [physics, \"#people\"]\n\
+            aliases:\n  - Feynman\n  - Dick  # Only his friends\n\
+            mentor: \"[[John Wheeler]]\"\n\
+            ---\n\
+            Richard Feynman was an American physicist.";
+
+        let frontmatter = Frontmatter::parse(contents).unwrap();
+        assert_eq!(
+            &contents[frontmatter.body_offset()..],
+            "Richard Feynman was an American physicist."
+        );
+
+        let properties = &frontmatter.properties;
+        assert_eq!(
+            properties.get("title").and_then(PropertyValue::as_str),
+            Some("Richard Feynman")
+        );
+        assert_eq!(
+            properties.get("born"),
+            Some(&PropertyValue::Date(
+                NaiveDate::from_ymd_opt(1918, 5, 11).unwrap()
+            ))
+        );
+        assert_eq!(properties.get("nobel"), Some(&PropertyValue::Bool(true)));
+        assert_eq!(properties.get("age"), Some(&PropertyValue::Number(69.0)));
+        assert_eq!(properties.tags(), vec!["physics", "people"]);
+        assert_eq!(properties.aliases(), vec!["Feynman", "Dick"]);
+
+        match properties.get("mentor") {
+            Some(PropertyValue::Link(wiki_link)) => {
+                assert_eq!(wiki_link.parts.target, "John Wheeler")
+            }
+            other => panic!("Expected a link, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_no_frontmatter() {
+        assert!(Frontmatter::parse("Just a page.\n---\n").is_none());
+        assert!(Frontmatter::parse("---\nA thematic break with no end.").is_none());
+    }
+}
@@ -0,0 +1,1185 @@
+use super::{Page, PropertyValue, Vault, VaultItemId, WikiLinkString};
+use crate::leaflet;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use serde::ser::{Serialize, SerializeSeq, Serializer};
+use std::cell::OnceCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::time::SystemTime;
+
+/// A Dataview-style query, like
+/// `TABLE file.ctime AS "Created" FROM "people" WHERE links_to([[People]]) AND created > 2024-01-01 SORT file.name`.
+///
+/// Every clause is optional and keywords aren't case-sensitive:
+///
+/// - `TABLE expression [AS "name"], ...` picks the columns. Every row
+///   also has the page it came from.
+/// - `FROM` picks pages by folder (`"people"`), tag (`#physics`) or what
+///   they link to (`[[People]]`), combined with `AND`, `OR` and `-`.
+/// - `WHERE expression` keeps pages where the expression is truthy.
+/// - `SORT expression [ASC|DESC], ...` orders the rows. Without it, rows
+///   are sorted by path.
+/// - `LIMIT n` keeps the first `n` rows.
+///
+/// Expressions can compare (`=`, `!=`, `<`, `<=`, `>`, `>=`), combine
+/// (`AND`, `OR`, `NOT` or `!`) and call `links_to(link)`,
+/// `linked_from(link)`, `contains(list or text, value)`, `length(value)`
+/// and `lower(text)`. Literals are numbers, `"strings"`, dates like
+/// `2024-01-01` or `2024-01-01T09:30`, `[[links]]`, `#tags`, `true`,
+/// `false` and `null`.
+///
+/// Fields are `file.name`, `file.path`, `file.folder`, `file.link`,
+/// `file.ctime`, `file.mtime`, `file.tags`, `file.aliases`,
+/// `file.outlinks` and `file.inlinks`, leaflet fields like
+/// `leaflet.date`, and frontmatter properties by name. `created` and
+/// `modified` fall back to `file.ctime` and `file.mtime` for pages
+/// without those properties. Comparing a list to a value is true if any
+/// item in the list matches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub columns: Vec<(Expression, String)>,
+    pub from: Option<Source>,
+    pub filter: Option<Expression>,
+    pub sort: Vec<(Expression, SortOrder)>,
+    pub limit: Option<usize>,
+}
+
+/// Which pages a query starts from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Folder(String),
+    Tag(String),
+    /// Pages that link to this link's target.
+    LinksTo(String),
+    And(Box<Source>, Box<Source>),
+    Or(Box<Source>, Box<Source>),
+    Not(Box<Source>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Literal(QueryValue),
+    Field(String),
+    /// A function's name, its arguments, and where its name starts in the
+    /// query, for errors.
+    Call(String, Vec<Expression>, usize),
+    Compare(Box<Expression>, Comparison, Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+    /// `target` is the link's target as written, like `Richard Feynman`.
+    /// `id` is `None` if it doesn't resolve.
+    Link {
+        target: String,
+        id: Option<VaultItemId>,
+    },
+    List(Vec<QueryValue>),
+}
+
+/// The result of running a query: one row per page, with a value for
+/// each column. Prints as a Markdown table and serializes with serde.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct QueryTable {
+    pub columns: Vec<String>,
+    pub rows: Vec<QueryRow>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct QueryRow {
+    pub id: VaultItemId,
+    pub values: Vec<QueryValue>,
+}
+
+/// The query couldn't be parsed or run. `position` is a byte offset
+/// into the query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryError {
+    pub message: String,
+    pub position: usize,
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (at position {})", self.message, self.position)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl QueryError {
+    fn new(message: impl Into<String>, position: usize) -> QueryError {
+        QueryError {
+            message: message.into(),
+            position,
+        }
+    }
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Query, QueryError> {
+        let tokens = tokenize(query)?;
+        Parser {
+            tokens,
+            next: 0,
+            query,
+        }
+        .parse_query()
+    }
+
+    pub fn run(&self, vault: &Vault) -> Result<QueryTable, QueryError> {
+        let mut rows = vec![];
+        for page in vault.pages() {
+            let context = PageContext::new(vault, page);
+
+            if let Some(from) = &self.from {
+                if !context.is_in(from) {
+                    continue;
+                }
+            }
+            if let Some(filter) = &self.filter {
+                if !context.evaluate(filter)?.is_truthy() {
+                    continue;
+                }
+            }
+
+            let values = self
+                .columns
+                .iter()
+                .map(|(expression, _)| context.evaluate(expression))
+                .collect::<Result<Vec<_>, _>>()?;
+            let sort_keys = self
+                .sort
+                .iter()
+                .map(|(expression, _)| context.evaluate(expression))
+                .collect::<Result<Vec<_>, _>>()?;
+            rows.push((
+                sort_keys,
+                QueryRow {
+                    id: page.id.clone(),
+                    values,
+                },
+            ));
+        }
+
+        rows.sort_by(|(a_keys, a_row), (b_keys, b_row)| {
+            a_keys
+                .iter()
+                .zip(b_keys)
+                .zip(&self.sort)
+                .map(|((a, b), (_, order))| {
+                    let ordering = a.sort_cmp(b);
+                    match order {
+                        SortOrder::Ascending => ordering,
+                        SortOrder::Descending => ordering.reverse(),
+                    }
+                })
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or_else(|| {
+                    a_row
+                        .id
+                        .path_from_vault_root()
+                        .cmp(b_row.id.path_from_vault_root())
+                })
+        });
+
+        let mut rows: Vec<QueryRow> = rows.into_iter().map(|(_, row)| row).collect();
+        if let Some(limit) = self.limit {
+            rows.truncate(limit);
+        }
+
+        Ok(QueryTable {
+            columns: self.columns.iter().map(|(_, name)| name.clone()).collect(),
+            rows,
+        })
+    }
+}
+
+impl QueryValue {
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            QueryValue::Null => false,
+            QueryValue::Bool(bool) => *bool,
+            QueryValue::Number(number) => *number != 0.0,
+            QueryValue::String(string) => !string.is_empty(),
+            QueryValue::List(list) => !list.is_empty(),
+            QueryValue::Date(_) | QueryValue::DateTime(_) | QueryValue::Link { .. } => true,
+        }
+    }
+
+    fn link(vault: &Vault, target: &str) -> QueryValue {
+        QueryValue::Link {
+            target: target.to_string(),
+            id: vault.vault_item_id_by_link_text(target),
+        }
+    }
+
+    fn from_property(vault: &Vault, value: &PropertyValue) -> QueryValue {
+        match value {
+            PropertyValue::String(string) => QueryValue::String(string.clone()),
+            PropertyValue::Number(number) => QueryValue::Number(*number),
+            PropertyValue::Bool(bool) => QueryValue::Bool(*bool),
+            PropertyValue::Date(date) => QueryValue::Date(*date),
+            PropertyValue::DateTime(date_time) => QueryValue::DateTime(*date_time),
+            PropertyValue::List(list) => QueryValue::List(
+                list.iter()
+                    .map(|value| QueryValue::from_property(vault, value))
+                    .collect(),
+            ),
+            PropertyValue::Link(wiki_link) => QueryValue::link(vault, &wiki_link.parts.target),
+            PropertyValue::Null => QueryValue::Null,
+        }
+    }
+
+    fn from_leaflet_field(field_value: &leaflet::FieldValue) -> QueryValue {
+        match field_value {
+            leaflet::FieldValue::YyyyMmDd(date) => QueryValue::Date(*date),
+            leaflet::FieldValue::String(string) => QueryValue::String(string.clone()),
+            leaflet::FieldValue::U64(number) => QueryValue::Number(*number as f64),
+            leaflet::FieldValue::Link {
+                wiki_link_text,
+                vault_item_id,
+            } => QueryValue::Link {
+                target: wiki_link_text.parts.target.clone(),
+                id: vault_item_id.clone(),
+            },
+        }
+    }
+
+    fn from_system_time(time: SystemTime) -> QueryValue {
+        QueryValue::DateTime(DateTime::<Local>::from(time).naive_local())
+    }
+
+    /// `None` if the values can't be compared, like a number and a date.
+    fn compare(&self, other: &QueryValue) -> Option<Ordering> {
+        match (self, other) {
+            (QueryValue::Null, QueryValue::Null) => Some(Ordering::Equal),
+            (QueryValue::Bool(a), QueryValue::Bool(b)) => Some(a.cmp(b)),
+            (QueryValue::Number(a), QueryValue::Number(b)) => a.partial_cmp(b),
+            (QueryValue::String(a), QueryValue::String(b)) => Some(a.cmp(b)),
+            (QueryValue::Date(a), QueryValue::Date(b)) => Some(a.cmp(b)),
+            (QueryValue::DateTime(a), QueryValue::DateTime(b)) => Some(a.cmp(b)),
+            (QueryValue::Date(a), QueryValue::DateTime(b)) => Some(a.and_hms_opt(0, 0, 0)?.cmp(b)),
+            (QueryValue::DateTime(a), QueryValue::Date(b)) => Some(a.cmp(&b.and_hms_opt(0, 0, 0)?)),
+            (
+                QueryValue::Link {
+                    target: a_target,
+                    id: a_id,
+                },
+                QueryValue::Link {
+                    target: b_target,
+                    id: b_id,
+                },
+            ) => match (a_id, b_id) {
+                (Some(a_id), Some(b_id)) if a_id == b_id => Some(Ordering::Equal),
+                _ => Some(a_target.to_lowercase().cmp(&b_target.to_lowercase())),
+            },
+            (QueryValue::Link { target, .. }, QueryValue::String(string))
+            | (QueryValue::String(string), QueryValue::Link { target, .. }) => {
+                let ordering = target.to_lowercase().cmp(&string.to_lowercase());
+                Some(if matches!(self, QueryValue::String(_)) {
+                    ordering.reverse()
+                } else {
+                    ordering
+                })
+            }
+            (QueryValue::List(a), QueryValue::List(b)) => {
+                for (a, b) in a.iter().zip(b) {
+                    match a.compare(b)? {
+                        Ordering::Equal => continue,
+                        ordering => return Some(ordering),
+                    }
+                }
+                Some(a.len().cmp(&b.len()))
+            }
+            _ => None,
+        }
+    }
+
+    fn satisfies(&self, comparison: Comparison, other: &QueryValue) -> bool {
+        // A list matches if any of its items do, so `file.tags = "#physics"` works.
+        if let (QueryValue::List(list), false) = (self, matches!(other, QueryValue::List(_))) {
+            return match comparison {
+                Comparison::NotEqual => !list
+                    .iter()
+                    .any(|item| item.satisfies(Comparison::Equal, other)),
+                _ => list.iter().any(|item| item.satisfies(comparison, other)),
+            };
+        }
+
+        let Some(ordering) = self.compare(other) else {
+            return comparison == Comparison::NotEqual;
+        };
+        match comparison {
+            Comparison::Equal => ordering.is_eq(),
+            Comparison::NotEqual => ordering.is_ne(),
+            Comparison::Less => ordering.is_lt(),
+            Comparison::LessOrEqual => ordering.is_le(),
+            Comparison::Greater => ordering.is_gt(),
+            Comparison::GreaterOrEqual => ordering.is_ge(),
+        }
+    }
+
+    /// Values that can't be compared sort by kind, with nulls last.
+    fn sort_cmp(&self, other: &QueryValue) -> Ordering {
+        self.compare(other)
+            .unwrap_or_else(|| self.sort_rank().cmp(&other.sort_rank()))
+    }
+
+    fn sort_rank(&self) -> u8 {
+        match self {
+            QueryValue::Bool(_) => 0,
+            QueryValue::Number(_) => 1,
+            QueryValue::Date(_) | QueryValue::DateTime(_) => 2,
+            QueryValue::String(_) | QueryValue::Link { .. } => 3,
+            QueryValue::List(_) => 4,
+            QueryValue::Null => 5,
+        }
+    }
+}
+
+impl Display for QueryValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryValue::Null => write!(f, "-"),
+            QueryValue::Bool(bool) => write!(f, "{bool}"),
+            QueryValue::Number(number) => write!(f, "{number}"),
+            QueryValue::String(string) => write!(f, "{string}"),
+            QueryValue::Date(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+            QueryValue::DateTime(date_time) => write!(f, "{}", date_time.format("%Y-%m-%d %H:%M")),
+            QueryValue::Link { target, .. } => write!(f, "[[{target}]]"),
+            QueryValue::List(list) => {
+                for (index, item) in list.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Serialize for QueryValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            QueryValue::Null => serializer.serialize_none(),
+            QueryValue::Bool(bool) => serializer.serialize_bool(*bool),
+            QueryValue::Number(number) => serializer.serialize_f64(*number),
+            QueryValue::List(list) => {
+                let mut sequence = serializer.serialize_seq(Some(list.len()))?;
+                for item in list {
+                    sequence.serialize_element(item)?;
+                }
+                sequence.end()
+            }
+            QueryValue::String(_)
+            | QueryValue::Date(_)
+            | QueryValue::DateTime(_)
+            | QueryValue::Link { .. } => serializer.collect_str(self),
+        }
+    }
+}
+
+/// A Markdown table with the page in the first column.
+impl Display for QueryTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let escape = |text: String| text.replace('|', "\\|");
+
+        write!(f, "| File |")?;
+        for column in &self.columns {
+            write!(f, " {} |", escape(column.clone()))?;
+        }
+        write!(f, "\n| --- |")?;
+        for _ in &self.columns {
+            write!(f, " --- |")?;
+        }
+        writeln!(f)?;
+
+        for row in &self.rows {
+            write!(f, "| [[{}]] |", escape(row.id.file_stem()))?;
+            for value in &row.values {
+                write!(f, " {} |", escape(value.to_string()))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// What a query can look at while it's evaluating one page.
+struct PageContext<'v> {
+    vault: &'v Vault,
+    page: &'v Page,
+    /// Leaflet fields are only parsed for queries that use them.
+    leaflet_fields: OnceCell<HashMap<String, Vec<QueryValue>>>,
+}
+
+impl<'v> PageContext<'v> {
+    fn new(vault: &'v Vault, page: &'v Page) -> PageContext<'v> {
+        PageContext {
+            vault,
+            page,
+            leaflet_fields: OnceCell::new(),
+        }
+    }
+
+    fn is_in(&self, source: &Source) -> bool {
+        match source {
+            Source::Folder(folder) => {
+                let folder = folder.trim_matches('/');
+                let path = self.page.id.path_from_vault_root();
+                folder.is_empty()
+                    || path == folder
+                    || self.page.file.path_from_vault_root_without_extension == folder
+                    || path
+                        .strip_prefix(folder)
+                        .is_some_and(|rest| rest.starts_with('/'))
+            }
+            Source::Tag(tag) => self.page.has_tag(tag, true),
+            Source::LinksTo(target) => self.links_to(&QueryValue::link(self.vault, target)),
+            Source::And(a, b) => self.is_in(a) && self.is_in(b),
+            Source::Or(a, b) => self.is_in(a) || self.is_in(b),
+            Source::Not(source) => !self.is_in(source),
+        }
+    }
+
+    fn evaluate(&self, expression: &Expression) -> Result<QueryValue, QueryError> {
+        let value = match expression {
+            Expression::Literal(QueryValue::Link { target, .. }) => {
+                QueryValue::link(self.vault, target)
+            }
+            Expression::Literal(value) => value.clone(),
+            Expression::Field(name) => self.field(name),
+            Expression::Call(name, arguments, position) => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.evaluate(argument))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.call(name, &arguments, *position)?
+            }
+            Expression::Compare(a, comparison, b) => {
+                QueryValue::Bool(self.evaluate(a)?.satisfies(*comparison, &self.evaluate(b)?))
+            }
+            Expression::And(a, b) => {
+                QueryValue::Bool(self.evaluate(a)?.is_truthy() && self.evaluate(b)?.is_truthy())
+            }
+            Expression::Or(a, b) => {
+                QueryValue::Bool(self.evaluate(a)?.is_truthy() || self.evaluate(b)?.is_truthy())
+            }
+            Expression::Not(expression) => {
+                QueryValue::Bool(!self.evaluate(expression)?.is_truthy())
+            }
+        };
+        Ok(value)
+    }
+
+    fn field(&self, name: &str) -> QueryValue {
+        let page = self.page;
+        let file = &page.file;
+
+        match name {
+            "file.name" => QueryValue::String(file.file_name_without_extension.clone()),
+            "file.path" => QueryValue::String(file.path_from_vault_root.clone()),
+            "file.folder" => QueryValue::String(page.id.folder().to_string()),
+            "file.link" => QueryValue::Link {
+                target: file.path_from_vault_root_without_extension.clone(),
+                id: Some(page.id.clone()),
+            },
+            "file.ctime" => QueryValue::from_system_time(file.created_at),
+            "file.mtime" => QueryValue::from_system_time(file.modified_at),
+            "file.tags" => QueryValue::List(
+                page.tags
+                    .iter()
+                    .map(|tag| QueryValue::String(format!("#{}", tag.name)))
+                    .collect(),
+            ),
+            "file.aliases" => {
+                QueryValue::List(page.aliases().into_iter().map(QueryValue::String).collect())
+            }
+            "file.outlinks" => QueryValue::List(
+                self.vault
+                    .outgoing_links(&page.id)
+                    .iter()
+                    .map(|edge| QueryValue::Link {
+                        target: edge.target_text.clone(),
+                        id: edge.target.clone(),
+                    })
+                    .collect(),
+            ),
+            "file.inlinks" => {
+                let mut sources: Vec<&VaultItemId> = self
+                    .vault
+                    .backlinks(&page.id)
+                    .iter()
+                    .map(|edge| &edge.source)
+                    .collect();
+                sources.sort_by_key(|id| id.path_from_vault_root());
+                sources.dedup();
+                QueryValue::List(
+                    sources
+                        .into_iter()
+                        .map(|id| self.link_to_item(id))
+                        .collect(),
+                )
+            }
+            _ => {
+                if let Some(leaflet_field) = name.strip_prefix("leaflet.") {
+                    return self.leaflet_field(leaflet_field);
+                }
+
+                let property = page.properties.get(name).or_else(|| {
+                    page.properties
+                        .iter()
+                        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                        .map(|(_, value)| value)
+                });
+                match (property, name) {
+                    (Some(value), _) => QueryValue::from_property(self.vault, value),
+                    (None, "created") => self.field("file.ctime"),
+                    (None, "modified") => self.field("file.mtime"),
+                    (None, _) => QueryValue::Null,
+                }
+            }
+        }
+    }
+
+    fn link_to_item(&self, id: &VaultItemId) -> QueryValue {
+        let target = match self.vault.item(id) {
+            Some(item) => item.file().path_from_vault_root_without_extension.clone(),
+            None => id.path_from_vault_root().to_string(),
+        };
+        QueryValue::Link {
+            target,
+            id: Some(id.clone()),
+        }
+    }
+
+    /// Every value the field has in the page's leaflet paragraphs.
+    fn leaflet_field(&self, name: &str) -> QueryValue {
+        let fields = self.leaflet_fields.get_or_init(|| {
+            let Ok(document) =
+                leaflet::Document::from_str(self.vault, self.page.body().to_string())
+            else {
+                return HashMap::new();
+            };
+
+            let mut fields: HashMap<String, Vec<QueryValue>> = HashMap::new();
+            for (name, value) in document.field_values() {
+                let values = fields.entry(name.to_string()).or_default();
+                let value = QueryValue::from_leaflet_field(value);
+                // Paragraphs inherit the fields before them, so most values repeat.
+                if !values.contains(&value) {
+                    values.push(value);
+                }
+            }
+            fields
+        });
+
+        match fields.get(&name.trim().to_lowercase()) {
+            Some(values) => QueryValue::List(values.clone()),
+            None => QueryValue::Null,
+        }
+    }
+
+    /// `position` is where the call starts in the query.
+    fn call(
+        &self,
+        name: &str,
+        arguments: &[QueryValue],
+        position: usize,
+    ) -> Result<QueryValue, QueryError> {
+        let value = match (name.to_lowercase().as_str(), arguments) {
+            ("links_to", [target]) => QueryValue::Bool(self.links_to(target)),
+            ("linked_from", [source]) => QueryValue::Bool(self.is_linked_from(source)),
+            ("contains", [QueryValue::List(list), needle]) => QueryValue::Bool(
+                list.iter()
+                    .any(|item| item.satisfies(Comparison::Equal, needle)),
+            ),
+            ("contains", [QueryValue::String(haystack), QueryValue::String(needle)]) => {
+                QueryValue::Bool(haystack.contains(needle.as_str()))
+            }
+            ("contains", [_, _]) => QueryValue::Bool(false),
+            ("length", [QueryValue::List(list)]) => QueryValue::Number(list.len() as f64),
+            ("length", [QueryValue::String(string)]) => {
+                QueryValue::Number(string.chars().count() as f64)
+            }
+            ("length", [QueryValue::Null]) => QueryValue::Number(0.0),
+            ("lower", [QueryValue::String(string)]) => QueryValue::String(string.to_lowercase()),
+            ("lower", [value]) => value.clone(),
+            _ => {
+                return Err(QueryError::new(
+                    format!(
+                        "{name} doesn't take {} argument(s) of those types",
+                        arguments.len()
+                    ),
+                    position,
+                ))
+            }
+        };
+        Ok(value)
+    }
+
+    /// `target` is a link or link text. Unresolved links match by target text.
+    fn links_to(&self, target: &QueryValue) -> bool {
+        let (target_text, target_id) = match target {
+            QueryValue::Link { target, id } => (target.clone(), id.clone()),
+            QueryValue::String(text) => (text.clone(), self.vault.vault_item_id_by_link_text(text)),
+            _ => return false,
+        };
+
+        self.page.reference_spans.iter().any(|reference_span| {
+            let link = &reference_span.link;
+            match (&target_id, &link.vault_item_id) {
+                (Some(target_id), Some(id)) => id == target_id,
+                (None, None) => link.target().eq_ignore_ascii_case(&target_text),
+                _ => false,
+            }
+        })
+    }
+
+    fn is_linked_from(&self, source: &QueryValue) -> bool {
+        let source_id = match source {
+            QueryValue::Link { id: Some(id), .. } => Some(id.clone()),
+            QueryValue::String(text) => self.vault.vault_item_id_by_link_text(text),
+            _ => None,
+        };
+
+        source_id.is_some_and(|source_id| {
+            self.vault
+                .outgoing_links(&source_id)
+                .iter()
+                .any(|edge| edge.target.as_ref() == Some(&self.page.id))
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Word(String),
+    String(String),
+    Number(f64),
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+    Link(String),
+    Tag(String),
+    Symbol(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    kind: TokenKind,
+    start: usize,
+    end: usize,
+}
+
+const symbols: [&str; 10] = ["!=", "<=", ">=", "=", "<", ">", "(", ")", ",", "!"];
+
+fn tokenize(query: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = vec![];
+    let mut start = 0;
+
+    while start < query.len() {
+        let rest = &query[start..];
+        let first = rest.chars().next().expect("rest isn't empty");
+
+        if first.is_whitespace() {
+            start += first.len_utf8();
+            continue;
+        }
+
+        let (kind, length) =
+            if let Some(symbol) = symbols.iter().find(|symbol| rest.starts_with(**symbol)) {
+                (TokenKind::Symbol(symbol), symbol.len())
+            } else if first == '-' {
+                (TokenKind::Symbol("-"), 1)
+            } else if first == '"' {
+                let (string, length) = string_literal(rest).ok_or_else(|| {
+                    QueryError::new("This string is missing its closing quote.", start)
+                })?;
+                (TokenKind::String(string), length)
+            } else if let Some(after_brackets) = rest.strip_prefix("[[") {
+                let end = after_brackets.find("]]").ok_or_else(|| {
+                    QueryError::new("This link is missing its closing brackets.", start)
+                })?;
+                let link = WikiLinkString::new(rest[..end + 4].to_string());
+                (TokenKind::Link(link.parts.target), end + 4)
+            } else if first == '#' {
+                let length = 1 + rest[1..]
+                    .find(|char: char| !(char.is_alphanumeric() || "_-/".contains(char)))
+                    .unwrap_or(rest.len() - 1);
+                (TokenKind::Tag(rest[1..length].to_string()), length)
+            } else if first.is_ascii_digit() {
+                let length = rest
+                    .find(|char: char| !(char.is_ascii_alphanumeric() || ".:-".contains(char)))
+                    .unwrap_or(rest.len());
+                (number_or_date(&rest[..length], start)?, length)
+            } else if first.is_alphabetic() || first == '_' {
+                let length = rest
+                    .find(|char: char| !(char.is_alphanumeric() || "_.-".contains(char)))
+                    .unwrap_or(rest.len());
+                (TokenKind::Word(rest[..length].to_string()), length)
+            } else {
+                return Err(QueryError::new(
+                    format!("Unexpected character `{first}`."),
+                    start,
+                ));
+            };
+
+        tokens.push(Token {
+            kind,
+            start,
+            end: start + length,
+        });
+        start += length;
+    }
+
+    Ok(tokens)
+}
+
+/// The string without quotes and how many bytes it took up, quotes included.
+fn string_literal(rest: &str) -> Option<(String, usize)> {
+    let mut string = String::new();
+    let mut characters = rest.char_indices().skip(1);
+    while let Some((index, character)) = characters.next() {
+        match character {
+            '"' => return Some((string, index + 1)),
+            '\\' => string.push(characters.next()?.1),
+            _ => string.push(character),
+        }
+    }
+    None
+}
+
+fn number_or_date(text: &str, start: usize) -> Result<TokenKind, QueryError> {
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return Ok(TokenKind::Date(date));
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"] {
+        if let Ok(date_time) = NaiveDateTime::parse_from_str(text, format) {
+            return Ok(TokenKind::DateTime(date_time));
+        }
+    }
+    text.parse::<f64>()
+        .map(TokenKind::Number)
+        .map_err(|_| QueryError::new(format!("`{text}` isn't a number or a date."), start))
+}
+
+const keywords: [&str; 15] = [
+    "table",
+    "from",
+    "where",
+    "sort",
+    "limit",
+    "as",
+    "and",
+    "or",
+    "not",
+    "asc",
+    "desc",
+    "ascending",
+    "descending",
+    "true",
+    "false",
+];
+
+struct Parser<'q> {
+    tokens: Vec<Token>,
+    next: usize,
+    query: &'q str,
+}
+
+impl Parser<'_> {
+    fn parse_query(mut self) -> Result<Query, QueryError> {
+        let mut query = Query {
+            columns: vec![],
+            from: None,
+            filter: None,
+            sort: vec![],
+            limit: None,
+        };
+
+        if self.take_keyword("table") && !self.at_clause_keyword() && self.peek().is_some() {
+            loop {
+                let start = self.position();
+                let expression = self.parse_expression()?;
+                let name = if self.take_keyword("as") {
+                    match self.take() {
+                        Some(Token {
+                            kind: TokenKind::String(name) | TokenKind::Word(name),
+                            ..
+                        }) => name,
+                        _ => return Err(self.error("Expected a column name after AS.")),
+                    }
+                } else {
+                    self.query[start..self.previous_end()].trim().to_string()
+                };
+                query.columns.push((expression, name));
+
+                if !self.take_symbol(",") {
+                    break;
+                }
+            }
+        }
+
+        if self.take_keyword("from") {
+            query.from = Some(self.parse_source_or()?);
+        }
+
+        if self.take_keyword("where") {
+            query.filter = Some(self.parse_expression()?);
+        }
+
+        if self.take_keyword("sort") {
+            loop {
+                let expression = self.parse_expression()?;
+                let order = if self.take_keyword("desc") || self.take_keyword("descending") {
+                    SortOrder::Descending
+                } else {
+                    let _ = self.take_keyword("asc") || self.take_keyword("ascending");
+                    SortOrder::Ascending
+                };
+                query.sort.push((expression, order));
+
+                if !self.take_symbol(",") {
+                    break;
+                }
+            }
+        }
+
+        if self.take_keyword("limit") {
+            query.limit = match self.take() {
+                Some(Token {
+                    kind: TokenKind::Number(number),
+                    ..
+                }) if number >= 0.0 && number.fract() == 0.0 => Some(number as usize),
+                _ => return Err(self.error("Expected a whole number after LIMIT.")),
+            };
+        }
+
+        match self.peek() {
+            None => Ok(query),
+            Some(_) => {
+                Err(self.error("Expected TABLE, FROM, WHERE, SORT or LIMIT, in that order."))
+            }
+        }
+    }
+
+    fn parse_source_or(&mut self) -> Result<Source, QueryError> {
+        let mut source = self.parse_source_and()?;
+        while self.take_keyword("or") {
+            source = Source::Or(Box::new(source), Box::new(self.parse_source_and()?));
+        }
+        Ok(source)
+    }
+
+    fn parse_source_and(&mut self) -> Result<Source, QueryError> {
+        let mut source = self.parse_source()?;
+        while self.take_keyword("and") {
+            source = Source::And(Box::new(source), Box::new(self.parse_source()?));
+        }
+        Ok(source)
+    }
+
+    fn parse_source(&mut self) -> Result<Source, QueryError> {
+        if self.take_symbol("-") || self.take_keyword("not") {
+            return Ok(Source::Not(Box::new(self.parse_source()?)));
+        }
+        if self.take_symbol("(") {
+            let source = self.parse_source_or()?;
+            return self.expect_symbol(")").map(|_| source);
+        }
+
+        match self.take().map(|token| token.kind) {
+            Some(TokenKind::String(folder)) => Ok(Source::Folder(folder)),
+            Some(TokenKind::Tag(tag)) => Ok(Source::Tag(tag)),
+            Some(TokenKind::Link(target)) => Ok(Source::LinksTo(target)),
+            _ => Err(self.error_at_previous("Expected a \"folder\", #tag or [[link]].")),
+        }
+    }
+
+    fn parse_expression(&mut self) -> Result<Expression, QueryError> {
+        let mut expression = self.parse_and()?;
+        while self.take_keyword("or") {
+            expression = Expression::Or(Box::new(expression), Box::new(self.parse_and()?));
+        }
+        Ok(expression)
+    }
+
+    fn parse_and(&mut self) -> Result<Expression, QueryError> {
+        let mut expression = self.parse_not()?;
+        while self.take_keyword("and") {
+            expression = Expression::And(Box::new(expression), Box::new(self.parse_not()?));
+        }
+        Ok(expression)
+    }
+
+    fn parse_not(&mut self) -> Result<Expression, QueryError> {
+        if self.take_keyword("not") || self.take_symbol("!") {
+            return Ok(Expression::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expression, QueryError> {
+        let left = self.parse_primary()?;
+        let comparison = match self.peek().map(|token| &token.kind) {
+            Some(TokenKind::Symbol("=")) => Comparison::Equal,
+            Some(TokenKind::Symbol("!=")) => Comparison::NotEqual,
+            Some(TokenKind::Symbol("<")) => Comparison::Less,
+            Some(TokenKind::Symbol("<=")) => Comparison::LessOrEqual,
+            Some(TokenKind::Symbol(">")) => Comparison::Greater,
+            Some(TokenKind::Symbol(">=")) => Comparison::GreaterOrEqual,
+            _ => return Ok(left),
+        };
+        self.next += 1;
+        let right = self.parse_primary()?;
+        Ok(Expression::Compare(
+            Box::new(left),
+            comparison,
+            Box::new(right),
+        ))
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, QueryError> {
+        if self.take_symbol("(") {
+            let expression = self.parse_expression()?;
+            return self.expect_symbol(")").map(|_| expression);
+        }
+
+        let start = self.position();
+        let literal = match self.take().map(|token| token.kind) {
+            Some(TokenKind::String(string)) => QueryValue::String(string),
+            Some(TokenKind::Number(number)) => QueryValue::Number(number),
+            Some(TokenKind::Date(date)) => QueryValue::Date(date),
+            Some(TokenKind::DateTime(date_time)) => QueryValue::DateTime(date_time),
+            Some(TokenKind::Tag(tag)) => QueryValue::String(format!("#{tag}")),
+            Some(TokenKind::Link(target)) => QueryValue::Link { target, id: None },
+            Some(TokenKind::Word(word)) => match word.to_lowercase().as_str() {
+                "true" => QueryValue::Bool(true),
+                "false" => QueryValue::Bool(false),
+                "null" => QueryValue::Null,
+                lowercase if keywords.contains(&lowercase) => {
+                    return Err(self.error_at_previous(format!("Expected a value, not {word}.")))
+                }
+                _ if self.take_symbol("(") => {
+                    let mut arguments = vec![];
+                    if !self.take_symbol(")") {
+                        loop {
+                            arguments.push(self.parse_expression()?);
+                            if self.take_symbol(")") {
+                                break;
+                            }
+                            self.expect_symbol(",")?;
+                        }
+                    }
+                    return Ok(Expression::Call(word, arguments, start));
+                }
+                _ => return Ok(Expression::Field(word)),
+            },
+            _ => return Err(self.error_at_previous("Expected a value.")),
+        };
+        Ok(Expression::Literal(literal))
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn take(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    fn take_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token {
+                kind: TokenKind::Word(word),
+                ..
+            }) if word.eq_ignore_ascii_case(keyword) => {
+                self.next += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn at_clause_keyword(&self) -> bool {
+        matches!(
+            self.peek(),
+            Some(Token { kind: TokenKind::Word(word), .. })
+                if ["from", "where", "sort", "limit"].contains(&word.to_lowercase().as_str())
+        )
+    }
+
+    fn take_symbol(&mut self, symbol: &str) -> bool {
+        match self.peek() {
+            Some(Token {
+                kind: TokenKind::Symbol(found),
+                ..
+            }) if *found == symbol => {
+                self.next += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), QueryError> {
+        if self.take_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(format!("Expected `{symbol}`.")))
+        }
+    }
+
+    fn position(&self) -> usize {
+        self.peek()
+            .map(|token| token.start)
+            .unwrap_or(self.query.len())
+    }
+
+    fn previous_end(&self) -> usize {
+        self.tokens[self.next - 1].end
+    }
+
+    fn error(&self, message: impl Into<String>) -> QueryError {
+        QueryError::new(message, self.position())
+    }
+
+    /// For errors about a token we already took.
+    fn error_at_previous(&self, message: impl Into<String>) -> QueryError {
+        let position = self
+            .tokens
+            .get(self.next - 1)
+            .map(|token| token.start)
+            .unwrap_or(self.query.len());
+        QueryError::new(message, position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_parse() {
+        let query =
+            Query::parse(r#"FROM "people" WHERE links_to([[People]]) AND created > 2024-01-01 SORT file.name DESC"#)
+                .unwrap();
+
+        assert_eq!(query.from, Some(Source::Folder("people".to_string())));
+        assert_eq!(
+            query.filter,
+            Some(Expression::And(
+                Box::new(Expression::Call(
+                    "links_to".to_string(),
+                    vec![Expression::Literal(QueryValue::Link {
+                        target: "People".to_string(),
+                        id: None
+                    })],
+                    20
+                )),
+                Box::new(Expression::Compare(
+                    Box::new(Expression::Field("created".to_string())),
+                    Comparison::Greater,
+                    Box::new(Expression::Literal(QueryValue::Date(
+                        NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()
+                    )))
+                ))
+            ))
+        );
+        assert_eq!(
+            query.sort,
+            vec![(
+                Expression::Field("file.name".to_string()),
+                SortOrder::Descending
+            )]
+        );
+
+        let error = Query::parse("WHERE file.name = ").unwrap_err();
+        assert_eq!(error.position, 18);
+    }
+
+    #[test]
+    fn test_run() {
+        let before_2024 = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let in_2024 = SystemTime::UNIX_EPOCH + Duration::from_secs(1_720_000_000);
+        let vault = Vault::from_files([
+            ("topics/People.md", "", before_2024),
+            (
+                "people/Ada Lovelace.md",
+                "---\nborn: 1815\n---\n[[People]]",
+                before_2024,
+            ),
+            (
+                "people/Richard Feynman.md",
+                "---\nborn: 1918\n---\n[[People]] #physics",
+                in_2024,
+            ),
+            (
+                "people/Grace Hopper.md",
+                "---\nborn: 1906\n---\n[[People]]",
+                in_2024,
+            ),
+            ("Reading.md", "[[People]]", in_2024),
+        ])
+        .unwrap();
+
+        let table = vault
+            .query(r#"TABLE born AS "Born", file.tags FROM "people" WHERE links_to([[People]]) AND created > 2024-01-01 SORT file.name"#)
+            .unwrap();
+        assert_eq!(
+            table.to_string(),
+            "| File | Born | file.tags |
+| --- | --- | --- |
+| [[Grace Hopper]] | 1906 |  |
+| [[Richard Feynman]] | 1918 | #physics |
+"
+        );
+
+        let table = vault
+            .query("FROM [[People]] AND -#physics SORT born DESC LIMIT 1")
+            .unwrap();
+        let ids: Vec<_> = table
+            .rows
+            .iter()
+            .map(|row| row.id.path_from_vault_root())
+            .collect();
+        assert_eq!(ids, vec!["Reading.md"]);
+
+        let error = vault
+            .query("WHERE born > 1900 AND lower(born, 2)")
+            .unwrap_err();
+        assert_eq!(error.position, 22);
+    }
+}
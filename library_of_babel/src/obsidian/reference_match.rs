@@ -0,0 +1,213 @@
+use super::{BlockTree, LinkParts};
+use lazy_static::lazy_static;
+use percent_encoding::percent_decode_str;
+use regex::Regex;
+use std::ops::Range;
+
+/// A link or embed found in a string, before we've worked out what it points at.
+pub struct ReferenceMatch<'s> {
+    pub range: Range<usize>,
+    /// The whole match, like `[[Richard Feynman]]` or `![](photo.jpg)`.
+    pub text: &'s str,
+    pub kind: ReferenceMatchKind<'s>,
+}
+
+pub enum ReferenceMatchKind<'s> {
+    /// `[[Richard Feynman]]` or `![[photo.jpg]]`.
+    Wiki,
+    /// `[Feynman](people/richard%20feynman.md)` or `![](photo.jpg)`.
+    Markdown {
+        is_embed: bool,
+        label: &'s str,
+        /// Percent-decoded, like `people/richard feynman.md`.
+        destination: String,
+        /// Split from the destination before decoding it, so an encoded `#`,
+        /// like in `C%23%20notes.md`, stays part of the target.
+        parts: LinkParts,
+    },
+    /// `[Obsidian](https://obsidian.md)`, or any other destination with a URL scheme.
+    External {
+        is_embed: bool,
+        label: &'s str,
+        url: &'s str,
+    },
+}
+
+lazy_static! {
+    pub static ref match_references: Regex =
+        Regex::new(r"!?\[\[.+?\]\]").expect("Error compiling regex.");
+
+    /// Matches `[label](destination)` and `[label](<destination with spaces> "title")`,
+    /// with an optional leading `!` for embeds. Like in CommonMark, bare
+    /// destinations can have balanced parentheses, like `Feynman%20(1948).md`,
+    /// though we only allow them one level deep.
+    static ref match_markdown_links: Regex = Regex::new(
+        r#"(!?)\[([^\[\]\n]*)\]\((?:<([^>\n]*)>|((?:[^\s()]|\([^\s()]*\))+))(?:\s+"[^"\n]*")?\)"#
+    )
+    .expect("Error compiling regex.");
+
+    static ref match_url_scheme: Regex =
+        Regex::new(r"^[a-zA-Z][a-zA-Z0-9+.\-]*:").expect("Error compiling regex.");
+}
+
+/// Finds wiki links, Markdown links, and external links, in the order they
+/// appear in `string`. Links in code don't count. `code_ranges` comes from
+/// `BlockTree::code_ranges`, so callers that already parsed `string` into
+/// blocks don't have to do it again.
+pub fn find_reference_matches<'s>(
+    string: &'s str,
+    code_ranges: &[Range<usize>],
+) -> Vec<ReferenceMatch<'s>> {
+    let wiki_matches = match_references
+        .find_iter(string)
+        .map(|current_match| ReferenceMatch {
+            range: current_match.range(),
+            text: current_match.as_str(),
+            kind: ReferenceMatchKind::Wiki,
+        });
+
+    let markdown_matches = match_markdown_links
+        .captures_iter(string)
+        .filter_map(markdown_reference_match);
+
+    let mut matches: Vec<_> = wiki_matches
+        .chain(markdown_matches)
+        .filter(|reference_match| !BlockTree::is_in_code(code_ranges, reference_match.range.start))
+        .collect();
+    matches.sort_by_key(|reference_match| reference_match.range.start);
+
+    // Drop matches that overlap an earlier one, like a Markdown link inside a wiki link's alias.
+    let mut end_of_last_match = 0;
+    matches.retain(|reference_match| {
+        if reference_match.range.start < end_of_last_match {
+            return false;
+        }
+        end_of_last_match = reference_match.range.end;
+        true
+    });
+
+    matches
+}
+
+fn markdown_reference_match(captures: regex::Captures<'_>) -> Option<ReferenceMatch<'_>> {
+    let whole_match = captures.get(0)?;
+    let is_embed = !captures[1].is_empty();
+    let label = captures.get(2).map_or("", |label| label.as_str());
+    let raw_destination = captures.get(3).or_else(|| captures.get(4))?.as_str();
+
+    if raw_destination.is_empty() {
+        return None;
+    }
+
+    let kind = if match_url_scheme.is_match(raw_destination) {
+        ReferenceMatchKind::External {
+            is_embed,
+            label,
+            url: raw_destination,
+        }
+    } else {
+        let decode = |part: &str| percent_decode_str(part).decode_utf8_lossy().into_owned();
+        let encoded_parts = LinkParts::from_destination(raw_destination);
+        let parts = LinkParts {
+            target: decode(&encoded_parts.target),
+            heading_path: encoded_parts
+                .heading_path
+                .iter()
+                .map(|heading| decode(heading))
+                .collect(),
+            block_id: encoded_parts.block_id.as_deref().map(decode),
+            ..encoded_parts
+        };
+        ReferenceMatchKind::Markdown {
+            is_embed,
+            label,
+            destination: decode(raw_destination),
+            parts,
+        }
+    };
+
+    Some(ReferenceMatch {
+        range: whole_match.range(),
+        text: whole_match.as_str(),
+        kind,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_reference_matches() {
+        let string =
+            "[[Richard Feynman]] and [Feynman](people/richard%20feynman.md#Early%20life), \
+            ![](<attachments/a photo.jpg> \"A photo\") and [Obsidian](https://obsidian.md)";
+
+        let code_ranges = BlockTree::parse(string).code_ranges(string);
+        let matches = find_reference_matches(string, &code_ranges);
+        assert_eq!(matches.len(), 4);
+
+        assert!(matches!(matches[0].kind, ReferenceMatchKind::Wiki));
+        assert_eq!(matches[0].text, "[[Richard Feynman]]");
+
+        match &matches[1].kind {
+            ReferenceMatchKind::Markdown {
+                is_embed,
+                label,
+                destination,
+                ..
+            } => {
+                assert!(!is_embed);
+                assert_eq!(*label, "Feynman");
+                assert_eq!(destination, "people/richard feynman.md#Early life");
+            }
+            _ => panic!("Expected a Markdown link."),
+        }
+
+        match &matches[2].kind {
+            ReferenceMatchKind::Markdown {
+                is_embed,
+                destination,
+                ..
+            } => {
+                assert!(is_embed);
+                assert_eq!(destination, "attachments/a photo.jpg");
+            }
+            _ => panic!("Expected a Markdown embed."),
+        }
+
+        match &matches[3].kind {
+            ReferenceMatchKind::External { url, .. } => assert_eq!(*url, "https://obsidian.md"),
+            _ => panic!("Expected an external link."),
+        }
+    }
+
+    #[test]
+    fn test_markdown_destinations_with_parentheses() {
+        let string = "[Paper](papers/Feynman%20(1948).md) (see [Reading](Reading.md))";
+        let matches = find_reference_matches(string, &[]);
+        assert_eq!(matches.len(), 2);
+
+        assert_eq!(matches[0].text, "[Paper](papers/Feynman%20(1948).md)");
+        match &matches[0].kind {
+            ReferenceMatchKind::Markdown { destination, .. } => {
+                assert_eq!(destination, "papers/Feynman (1948).md")
+            }
+            _ => panic!("Expected a Markdown link."),
+        }
+        assert_eq!(matches[1].text, "[Reading](Reading.md)");
+    }
+
+    #[test]
+    fn test_encoded_hashes_stay_in_the_target() {
+        let string = "[Notes](C%23%20notes.md#Early%20life)";
+        let matches = find_reference_matches(string, &[]);
+        match &matches[0].kind {
+            ReferenceMatchKind::Markdown { parts, .. } => {
+                assert_eq!(parts.target, "C# notes.md");
+                assert_eq!(parts.heading_path, vec!["Early life"]);
+            }
+            _ => panic!("Expected a Markdown link."),
+        }
+    }
+}
@@ -0,0 +1,592 @@
+use super::{Page, Tag, VaultItemId};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::{Bound, Range};
+
+/// Roughly how many characters of context to show on each side of the
+/// first match in a snippet.
+const snippet_context_length: usize = 60;
+
+/// An inverted index over page titles, aliases, tags and bodies, from each
+/// lowercased word to where it shows up.
+#[derive(Debug, Clone, Default)]
+pub struct SearchIndex {
+    postings: BTreeMap<String, HashMap<VaultItemId, Vec<Occurrence>>>,
+    /// Every indexed page, with the words it contains, so we can take a
+    /// page back out without scanning every posting.
+    terms_by_page: HashMap<VaultItemId, HashSet<String>>,
+}
+
+/// The part of a page a word was found in. Matches in titles count for
+/// more than matches in aliases, and so on down to the body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SearchField {
+    Title,
+    Alias,
+    Tag,
+    Body,
+}
+
+impl SearchField {
+    fn weight(&self) -> f64 {
+        match self {
+            SearchField::Title => 4.0,
+            SearchField::Alias => 3.0,
+            SearchField::Tag => 2.0,
+            SearchField::Body => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Occurrence {
+    field: SearchField,
+    /// Which word in the field this is, so phrases can check that words
+    /// come one after another. Aliases and tags are numbered as if they
+    /// were one long field, with gaps so phrases can't span two of them.
+    position: usize,
+    /// Where the word is. For the body, that's a range into the page's
+    /// contents. Otherwise it's a range into the title, alias or tag.
+    range: Range<usize>,
+}
+
+/// A parsed search, like `"quantum electrodynamics" feyn* tag:#physics path:people`.
+///
+/// - Plain words have to appear somewhere in the page.
+/// - Quoted phrases have to appear in that order, one after another.
+/// - Words ending in `*` match any word that starts with them.
+/// - `tag:` keeps pages with that tag or one of its children.
+/// - `path:` or `folder:` keeps pages inside that folder. With more than
+///   one, a page can be in any of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchQuery {
+    pub terms: Vec<SearchTerm>,
+    pub tags: Vec<Tag>,
+    pub folders: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchTerm {
+    Word(String),
+    Prefix(String),
+    Phrase(Vec<String>),
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub id: VaultItemId,
+    pub score: f64,
+    pub snippet: Snippet,
+}
+
+/// A bit of a page's body around the best match.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snippet {
+    pub text: String,
+    /// The matches, as ranges into `text`.
+    pub highlights: Vec<Range<usize>>,
+}
+
+impl Snippet {
+    /// The snippet's text with each match wrapped in `before` and `after`,
+    /// like `**` and `**` for Markdown bold.
+    pub fn highlighted(&self, before: &str, after: &str) -> String {
+        let mut highlighted = String::with_capacity(self.text.len());
+        let mut end_of_last_highlight = 0;
+        for highlight in &self.highlights {
+            highlighted.push_str(&self.text[end_of_last_highlight..highlight.start]);
+            highlighted.push_str(before);
+            highlighted.push_str(&self.text[highlight.clone()]);
+            highlighted.push_str(after);
+            end_of_last_highlight = highlight.end;
+        }
+        highlighted.push_str(&self.text[end_of_last_highlight..]);
+        highlighted
+    }
+}
+
+impl SearchQuery {
+    pub fn parse(query: &str) -> SearchQuery {
+        let mut search_query = SearchQuery::default();
+
+        for (part, is_quoted) in split_query(query) {
+            if is_quoted {
+                search_query.add_words(tokenize(part).into_iter().map(|(word, _)| word).collect());
+            } else if let Some(tag_name) = part.strip_prefix("tag:") {
+                search_query.tags.push(Tag::new(tag_name));
+            } else if let Some(folder) = part
+                .strip_prefix("path:")
+                .or_else(|| part.strip_prefix("folder:"))
+            {
+                search_query
+                    .folders
+                    .push(folder.trim_matches('/').to_string());
+            } else if let Some(prefix) = part.strip_suffix('*') {
+                let mut words: Vec<String> =
+                    tokenize(prefix).into_iter().map(|(word, _)| word).collect();
+                // `don't*` is the phrase `don` followed by any word starting with `t`,
+                // but that's rare enough that we search for the words separately.
+                if let Some(last_word) = words.pop() {
+                    search_query.terms.push(SearchTerm::Prefix(last_word));
+                }
+                search_query.add_words(words);
+            } else {
+                search_query.add_words(tokenize(part).into_iter().map(|(word, _)| word).collect());
+            }
+        }
+
+        search_query
+    }
+
+    /// One word is a word. Several, like from `"richard feynman"` or
+    /// `don't`, are a phrase.
+    fn add_words(&mut self, mut words: Vec<String>) {
+        match words.len() {
+            0 => {}
+            1 => self.terms.push(SearchTerm::Word(words.remove(0))),
+            _ => self.terms.push(SearchTerm::Phrase(words)),
+        }
+    }
+
+    fn matches_filters(&self, page: &Page) -> bool {
+        let in_folder = self.folders.is_empty()
+            || self.folders.iter().any(|folder| {
+                folder.is_empty()
+                    || page
+                        .id
+                        .path_from_vault_root()
+                        .strip_prefix(folder.as_str())
+                        .is_some_and(|rest| rest.starts_with('/'))
+            });
+
+        in_folder && self.tags.iter().all(|tag| page.has_tag(&tag.name, true))
+    }
+}
+
+/// Splits on whitespace, keeping quoted phrases together. Returns each
+/// part and whether it was quoted.
+fn split_query(query: &str) -> Vec<(&str, bool)> {
+    let mut parts = vec![];
+    let mut rest = query;
+
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return parts;
+        }
+
+        if let Some(after_quote) = rest.strip_prefix('"') {
+            // An unclosed quote runs to the end of the query.
+            let end = after_quote.find('"').unwrap_or(after_quote.len());
+            parts.push((&after_quote[..end], true));
+            rest = after_quote.get(end + 1..).unwrap_or("");
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            parts.push((&rest[..end], false));
+            rest = &rest[end..];
+        }
+    }
+}
+
+/// Lowercased words and where they are in `text`. A word is a run of
+/// letters and numbers.
+fn tokenize(text: &str) -> Vec<(String, Range<usize>)> {
+    let mut words = vec![];
+    let mut start_of_word = None;
+
+    for (index, char) in text.char_indices().chain([(text.len(), ' ')]) {
+        match (char.is_alphanumeric(), start_of_word) {
+            (true, None) => start_of_word = Some(index),
+            (false, Some(start)) => {
+                words.push((text[start..index].to_lowercase(), start..index));
+                start_of_word = None;
+            }
+            _ => {}
+        }
+    }
+
+    words
+}
+
+impl SearchIndex {
+    pub fn new<'p>(pages: impl IntoIterator<Item = &'p Page>) -> SearchIndex {
+        let mut index = SearchIndex::default();
+        for page in pages {
+            index.add_page(page);
+        }
+        index
+    }
+
+    /// Re-indexes `page` from its current contents.
+    pub fn update_page(&mut self, page: &Page) {
+        self.remove_page(&page.id);
+        self.add_page(page);
+    }
+
+    pub fn remove_page(&mut self, id: &VaultItemId) {
+        let Some(terms) = self.terms_by_page.remove(id) else {
+            return;
+        };
+
+        for term in terms {
+            if let Some(pages) = self.postings.get_mut(&term) {
+                pages.remove(id);
+                if pages.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+    }
+
+    fn add_page(&mut self, page: &Page) {
+        let mut occurrences: Vec<(String, Occurrence)> = vec![];
+        let mut add_field = |field: SearchField, texts: Vec<&str>, offset: usize| {
+            let mut position = 0;
+            for text in texts {
+                for (word, range) in tokenize(text) {
+                    let range = range.start + offset..range.end + offset;
+                    occurrences.push((
+                        word,
+                        Occurrence {
+                            field,
+                            position,
+                            range,
+                        },
+                    ));
+                    position += 1;
+                }
+                position += 1;
+            }
+        };
+
+        let aliases = page.aliases();
+        add_field(
+            SearchField::Title,
+            vec![&page.file.file_name_without_extension],
+            0,
+        );
+        add_field(
+            SearchField::Alias,
+            aliases.iter().map(String::as_str).collect(),
+            0,
+        );
+        add_field(
+            SearchField::Tag,
+            page.tags.iter().map(|tag| tag.name.as_str()).collect(),
+            0,
+        );
+        add_field(SearchField::Body, vec![page.body()], page.body_offset);
+
+        let mut terms = HashSet::new();
+        for (word, occurrence) in occurrences {
+            let pages = self.postings.entry(word.clone()).or_default();
+            pages.entry(page.id.clone()).or_default().push(occurrence);
+            terms.insert(word);
+        }
+        self.terms_by_page.insert(page.id.clone(), terms);
+    }
+
+    /// Pages that match every term and filter in `query`, best first.
+    /// `page` looks up pages by id, for filters and snippets.
+    pub fn search<'p>(
+        &self,
+        query: &SearchQuery,
+        page: impl Fn(&VaultItemId) -> Option<&'p Page>,
+    ) -> Vec<SearchResult> {
+        let page_count = self.terms_by_page.len() as f64;
+
+        // Every page matches an empty query, so filters work on their own.
+        let mut matches: Option<HashMap<&VaultItemId, (f64, Vec<Occurrence>)>> = None;
+        for term in &query.terms {
+            let term_matches = self.matches(term);
+            let inverse_document_frequency =
+                (1.0 + page_count / (1.0 + term_matches.len() as f64)).ln();
+
+            let mut next_matches = HashMap::new();
+            for (id, occurrences) in term_matches {
+                let previous = match &mut matches {
+                    None => Some((0.0, vec![])),
+                    Some(matches) => matches.remove(id),
+                };
+                let Some((score, mut all_occurrences)) = previous else {
+                    continue;
+                };
+
+                let term_score: f64 = occurrences
+                    .iter()
+                    .map(|occurrence| occurrence.field.weight())
+                    .sum::<f64>()
+                    .ln_1p();
+                all_occurrences.extend(occurrences);
+                next_matches.insert(
+                    id,
+                    (
+                        score + term_score * inverse_document_frequency,
+                        all_occurrences,
+                    ),
+                );
+            }
+            matches = Some(next_matches);
+        }
+
+        let matches = matches.unwrap_or_else(|| {
+            self.terms_by_page
+                .keys()
+                .map(|id| (id, (0.0, vec![])))
+                .collect()
+        });
+
+        let mut results: Vec<SearchResult> = matches
+            .into_iter()
+            .filter_map(|(id, (score, occurrences))| {
+                let page = page(id)?;
+                if !query.matches_filters(page) {
+                    return None;
+                }
+
+                Some(SearchResult {
+                    id: id.clone(),
+                    score,
+                    snippet: snippet(page, &occurrences),
+                })
+            })
+            .collect();
+
+        results.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.id.path_from_vault_root().cmp(b.id.path_from_vault_root()))
+        });
+        results
+    }
+
+    /// Where `term` shows up in each page that has it. A phrase's
+    /// occurrences cover the whole phrase.
+    fn matches(&self, term: &SearchTerm) -> HashMap<&VaultItemId, Vec<Occurrence>> {
+        match term {
+            SearchTerm::Word(word) => self
+                .postings
+                .get(word)
+                .map(|pages| {
+                    pages
+                        .iter()
+                        .map(|(id, occurrences)| (id, occurrences.clone()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+
+            SearchTerm::Prefix(prefix) => {
+                let mut matches: HashMap<&VaultItemId, Vec<Occurrence>> = HashMap::new();
+                let words = self
+                    .postings
+                    .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+                    .take_while(|(word, _)| word.starts_with(prefix.as_str()));
+                for (_, pages) in words {
+                    for (id, occurrences) in pages {
+                        matches
+                            .entry(id)
+                            .or_default()
+                            .extend(occurrences.iter().cloned());
+                    }
+                }
+                matches
+            }
+
+            SearchTerm::Phrase(words) => {
+                let Some(first_word_pages) = self.postings.get(&words[0]) else {
+                    return HashMap::new();
+                };
+
+                first_word_pages
+                    .iter()
+                    .filter_map(|(id, first_occurrences)| {
+                        let phrase_occurrences: Vec<Occurrence> = first_occurrences
+                            .iter()
+                            .filter_map(|first| self.phrase_starting_at(id, first, &words[1..]))
+                            .collect();
+                        (!phrase_occurrences.is_empty()).then_some((id, phrase_occurrences))
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// The occurrence of the whole phrase, if `rest` follows `first` word
+    /// for word in the same field.
+    fn phrase_starting_at(
+        &self,
+        id: &VaultItemId,
+        first: &Occurrence,
+        rest: &[String],
+    ) -> Option<Occurrence> {
+        let mut end = first.range.end;
+        for (offset, word) in rest.iter().enumerate() {
+            let next = self
+                .postings
+                .get(word)?
+                .get(id)?
+                .iter()
+                .find(|occurrence| {
+                    occurrence.field == first.field
+                        && occurrence.position == first.position + offset + 1
+                })?;
+            end = next.range.end;
+        }
+
+        Some(Occurrence {
+            field: first.field,
+            position: first.position,
+            range: first.range.start..end,
+        })
+    }
+}
+
+/// The body around the first match, on one line, with every match in it
+/// highlighted. The start of the body if nothing matched there.
+fn snippet(page: &Page, occurrences: &[Occurrence]) -> Snippet {
+    let mut body_ranges: Vec<Range<usize>> = occurrences
+        .iter()
+        .filter(|occurrence| occurrence.field == SearchField::Body)
+        .map(|occurrence| occurrence.range.clone())
+        .collect();
+    body_ranges.sort_by_key(|range| (range.start, std::cmp::Reverse(range.end)));
+
+    let contents = &page.contents;
+    let first_match = body_ranges
+        .first()
+        .cloned()
+        .unwrap_or(page.body_offset..page.body_offset);
+
+    let start = floor_char_boundary(
+        contents,
+        first_match
+            .start
+            .saturating_sub(snippet_context_length)
+            .max(page.body_offset),
+    );
+    let end = floor_char_boundary(
+        contents,
+        (first_match.end + snippet_context_length).min(contents.len()),
+    );
+
+    // Start and end on whole words.
+    let start = match contents[start..first_match.start].find(char::is_whitespace) {
+        Some(index) if start > page.body_offset => start + index + 1,
+        _ => start,
+    };
+    let end = match contents[first_match.end..end].rfind(char::is_whitespace) {
+        Some(index) if end < contents.len() => first_match.end + index,
+        _ => end,
+    };
+
+    let before = if start > page.body_offset { "…" } else { "" };
+    let after = if end < contents.len() { "…" } else { "" };
+    let text = format!(
+        "{before}{}{after}",
+        contents[start..end].trim_end().replace(['\n', '\t'], " ")
+    );
+
+    let mut highlights: Vec<Range<usize>> = vec![];
+    let mut end_of_last_highlight = start;
+    for range in body_ranges {
+        // Skips matches inside a longer one too, like a word inside a phrase.
+        if range.start < end_of_last_highlight || range.end > end {
+            continue;
+        }
+        let shift = before.len();
+        highlights.push(range.start - start + shift..range.end - start + shift);
+        end_of_last_highlight = range.end;
+    }
+
+    Snippet { text, highlights }
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_query() {
+        let query = SearchQuery::parse(
+            r#""Quantum Electrodynamics" feyn* path:people/ tag:#physics lectures"#,
+        );
+        assert_eq!(
+            query.terms,
+            vec![
+                SearchTerm::Phrase(vec!["quantum".to_string(), "electrodynamics".to_string()]),
+                SearchTerm::Prefix("feyn".to_string()),
+                SearchTerm::Word("lectures".to_string()),
+            ]
+        );
+        assert_eq!(query.folders, vec!["people".to_string()]);
+        assert_eq!(query.tags, vec![Tag::new("physics")]);
+    }
+
+    #[test]
+    fn test_search() {
+        let created_at = std::time::SystemTime::UNIX_EPOCH;
+        let mut vault = crate::obsidian::Vault::from_files([
+            (
+                "people/Richard Feynman.md",
+                "---\naliases: [Dick]\n---\nFeynman worked on quantum electrodynamics. #physics",
+                created_at,
+            ),
+            (
+                "Reading.md",
+                "Reading about quantum computing and electrodynamics.",
+                created_at,
+            ),
+        ])
+        .unwrap();
+
+        fn ids(vault: &crate::obsidian::Vault, query: &str) -> Vec<String> {
+            vault
+                .search(query)
+                .into_iter()
+                .map(|result| result.id.path_from_vault_root().to_string())
+                .collect()
+        }
+        let feynman = vec!["people/Richard Feynman.md"];
+        assert_eq!(
+            ids(&vault, "quantum"),
+            vec!["Reading.md", "people/Richard Feynman.md"]
+        );
+        assert_eq!(ids(&vault, "\"quantum electrodynamics\""), feynman);
+        assert_eq!(ids(&vault, "dick"), feynman);
+        assert_eq!(ids(&vault, "comp*"), vec!["Reading.md"]);
+        assert_eq!(ids(&vault, "quantum tag:physics"), feynman);
+        assert_eq!(ids(&vault, "quantum path:people"), feynman);
+
+        let results = vault.search("\"quantum electrodynamics\"");
+        assert_eq!(
+            results[0].snippet.highlighted("[", "]"),
+            "Feynman worked on [quantum electrodynamics]. #physics"
+        );
+
+        vault
+            .edit_page(
+                &VaultItemId::from("Reading.md"),
+                "Nothing here.".to_string(),
+            )
+            .unwrap();
+        assert_eq!(ids(&vault, "quantum"), feynman);
+    }
+
+    #[test]
+    fn test_snippet_highlighted() {
+        let snippet = Snippet {
+            text: "…he won the Nobel Prize in 1965…".to_string(),
+            highlights: vec![Range { start: 14, end: 25 }],
+        };
+        assert_eq!(
+            snippet.highlighted("**", "**"),
+            "…he won the **Nobel Prize** in 1965…"
+        );
+    }
+}
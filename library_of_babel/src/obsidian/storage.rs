@@ -0,0 +1,331 @@
+use super::{write_atomically, VaultError};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Debug;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use walkdir::WalkDir;
+
+/// Where a vault's files live. Every path is absolute, starting with the
+/// vault's path.
+///
+/// `FileSystemStorage` reads and writes real files. `InMemoryStorage`
+/// keeps them in memory, so tests don't need a vault on disk.
+pub trait VaultStorage: Debug + Send + Sync {
+    fn is_file(&self, path: &Path) -> bool;
+
+    fn is_dir(&self, path: &Path) -> bool;
+
+    fn exists(&self, path: &Path) -> bool {
+        self.is_file(path) || self.is_dir(path)
+    }
+
+    fn timestamps(&self, path: &Path) -> Result<Timestamps, VaultError>;
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, VaultError>;
+
+    /// Replaces the file's contents all at once, creating it and any
+    /// missing folders if needed.
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<(), VaultError>;
+
+    /// Moves a file, creating any missing folders in `to`.
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), VaultError>;
+
+    fn remove_file(&self, path: &Path) -> Result<(), VaultError>;
+
+    /// Every file inside `folder`, at any depth. `skip` gets called with
+    /// each file and folder below `folder`. Skipping a folder skips
+    /// everything inside it. Unreadable entries come back as errors.
+    fn files_in_folder(
+        &self,
+        folder: &Path,
+        follow_symlinks: bool,
+        skip: &dyn Fn(&Path) -> bool,
+    ) -> Vec<Result<PathBuf, VaultError>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamps {
+    pub created_at: SystemTime,
+    pub modified_at: SystemTime,
+}
+
+/// Files on disk.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileSystemStorage;
+
+impl VaultStorage for FileSystemStorage {
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn timestamps(&self, path: &Path) -> Result<Timestamps, VaultError> {
+        let metadata = fs::metadata(path).map_err(|error| VaultError::io(path, error))?;
+        let missing_timestamp = |error| VaultError::MissingTimestamp {
+            path: path.to_path_buf(),
+            error,
+        };
+
+        let modified_at = metadata.modified().map_err(missing_timestamp)?;
+        // Not every platform and file system records when a file was created.
+        // When they don't, we fall back to when it was last modified.
+        let created_at = metadata.created().unwrap_or(modified_at);
+
+        Ok(Timestamps {
+            created_at,
+            modified_at,
+        })
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, VaultError> {
+        fs::read(path).map_err(|error| VaultError::io(path, error))
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<(), VaultError> {
+        create_parent_folder(path)?;
+        write_atomically(path, contents)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), VaultError> {
+        create_parent_folder(to)?;
+        fs::rename(from, to).map_err(|error| VaultError::io(from, error))
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<(), VaultError> {
+        fs::remove_file(path).map_err(|error| VaultError::io(path, error))
+    }
+
+    fn files_in_folder(
+        &self,
+        folder: &Path,
+        follow_symlinks: bool,
+        skip: &dyn Fn(&Path) -> bool,
+    ) -> Vec<Result<PathBuf, VaultError>> {
+        WalkDir::new(folder)
+            .follow_links(follow_symlinks)
+            .into_iter()
+            .filter_entry(|entry| entry.depth() == 0 || !skip(entry.path()))
+            .filter_map(|entry| match entry {
+                Ok(entry) if entry.file_type().is_file() => Some(Ok(entry.into_path())),
+                Ok(_) => None,
+                Err(error) => Some(Err(walkdir_error_to_vault_error(folder, error))),
+            })
+            .collect()
+    }
+}
+
+fn create_parent_folder(path: &Path) -> Result<(), VaultError> {
+    match path.parent() {
+        Some(parent_folder) => {
+            fs::create_dir_all(parent_folder).map_err(|error| VaultError::io(parent_folder, error))
+        }
+        None => Ok(()),
+    }
+}
+
+fn walkdir_error_to_vault_error(folder: &Path, error: walkdir::Error) -> VaultError {
+    let path = error
+        .path()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| folder.to_path_buf());
+
+    // Walkdir errors without an underlying io::Error are symlink loops.
+    let io_error = match error.into_io_error() {
+        Some(io_error) => io_error,
+        None => io::Error::other("Found a symlink loop."),
+    };
+
+    VaultError::io(&path, io_error)
+}
+
+/// Files kept in memory, for tests and fixtures. Folders exist once
+/// something's been put in them and stick around after it's gone, like
+/// they do on disk.
+#[derive(Debug, Default)]
+pub struct InMemoryStorage {
+    files: Mutex<BTreeMap<PathBuf, InMemoryFile>>,
+    folders: Mutex<BTreeSet<PathBuf>>,
+}
+
+#[derive(Debug, Clone)]
+struct InMemoryFile {
+    contents: Vec<u8>,
+    timestamps: Timestamps,
+}
+
+impl InMemoryStorage {
+    pub fn new() -> InMemoryStorage {
+        InMemoryStorage::default()
+    }
+
+    pub fn create_folder(&self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        let mut folders = self.folders.lock().unwrap();
+        folders.extend(path.ancestors().map(Path::to_path_buf));
+    }
+
+    /// Adds a file as if it was created at `created_at` and hasn't been
+    /// modified since.
+    pub fn insert_file(
+        &self,
+        path: impl Into<PathBuf>,
+        contents: impl Into<Vec<u8>>,
+        created_at: SystemTime,
+    ) {
+        let path = path.into();
+        if let Some(parent_folder) = path.parent() {
+            self.create_folder(parent_folder);
+        }
+
+        let file = InMemoryFile {
+            contents: contents.into(),
+            timestamps: Timestamps {
+                created_at,
+                modified_at: created_at,
+            },
+        };
+        self.files.lock().unwrap().insert(path, file);
+    }
+
+    fn not_found(path: &Path) -> VaultError {
+        VaultError::io(path, io::ErrorKind::NotFound.into())
+    }
+}
+
+impl VaultStorage for InMemoryStorage {
+    fn is_file(&self, path: &Path) -> bool {
+        self.files.lock().unwrap().contains_key(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.folders.lock().unwrap().contains(path)
+    }
+
+    fn timestamps(&self, path: &Path) -> Result<Timestamps, VaultError> {
+        let files = self.files.lock().unwrap();
+        let file = files.get(path).ok_or_else(|| Self::not_found(path))?;
+        Ok(file.timestamps)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, VaultError> {
+        let files = self.files.lock().unwrap();
+        let file = files.get(path).ok_or_else(|| Self::not_found(path))?;
+        Ok(file.contents.clone())
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<(), VaultError> {
+        if self.is_dir(path) {
+            return Err(VaultError::io(path, io::ErrorKind::IsADirectory.into()));
+        }
+        if let Some(parent_folder) = path.parent() {
+            self.create_folder(parent_folder);
+        }
+
+        let now = SystemTime::now();
+        let mut files = self.files.lock().unwrap();
+        let timestamps = match files.get(path) {
+            // Writes close together can happen within the clock's resolution,
+            // but saving checks modification times, so they need to differ.
+            Some(file) => Timestamps {
+                created_at: file.timestamps.created_at,
+                modified_at: now.max(file.timestamps.modified_at + Duration::from_nanos(1)),
+            },
+            None => Timestamps {
+                created_at: now,
+                modified_at: now,
+            },
+        };
+
+        files.insert(
+            path.to_path_buf(),
+            InMemoryFile {
+                contents: contents.to_vec(),
+                timestamps,
+            },
+        );
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), VaultError> {
+        if let Some(parent_folder) = to.parent() {
+            self.create_folder(parent_folder);
+        }
+
+        let mut files = self.files.lock().unwrap();
+        let file = files.remove(from).ok_or_else(|| Self::not_found(from))?;
+        files.insert(to.to_path_buf(), file);
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<(), VaultError> {
+        let mut files = self.files.lock().unwrap();
+        files.remove(path).ok_or_else(|| Self::not_found(path))?;
+        Ok(())
+    }
+
+    fn files_in_folder(
+        &self,
+        folder: &Path,
+        _follow_symlinks: bool,
+        skip: &dyn Fn(&Path) -> bool,
+    ) -> Vec<Result<PathBuf, VaultError>> {
+        let files = self.files.lock().unwrap();
+        files
+            .keys()
+            .filter(|path| path.starts_with(folder) && *path != folder)
+            .filter(|path| {
+                // The file itself and every folder between it and `folder`.
+                let mut paths_below_folder =
+                    path.ancestors().take_while(|ancestor| *ancestor != folder);
+                !paths_below_folder.any(skip)
+            })
+            .map(|path| Ok(path.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::obsidian::*;
+    use std::time::SystemTime;
+
+    #[test]
+    fn test_in_memory_vault() {
+        let created_at = SystemTime::UNIX_EPOCH;
+        let mut vault = Vault::from_files([
+            ("Reading.md", "I'm reading [[Richard Feynman]].", created_at),
+            (
+                "people/Richard Feynman.md",
+                "Physicist. See [[Reading]].",
+                created_at,
+            ),
+            (".obsidian/app.json", "{}", created_at),
+        ])
+        .unwrap();
+
+        assert_eq!(vault.items().count(), 2);
+        let feynman_id = VaultItemId::from("people/Richard Feynman.md");
+        let backlinks = vault.backlinks(&feynman_id);
+        assert_eq!(backlinks.len(), 1);
+        assert_eq!(backlinks[0].source, VaultItemId::from("Reading.md"));
+
+        vault
+            .rename_item(&VaultItemId::from("Reading.md"), "notes/Reading.md")
+            .unwrap();
+        let reading_path = vault.absolute_path_to_item(&VaultItemId::from("notes/Reading.md"));
+        assert!(vault.storage().is_file(&reading_path));
+        assert_eq!(
+            vault
+                .backlinks(&VaultItemId::from("notes/Reading.md"))
+                .len(),
+            1
+        );
+        assert_eq!(vault.backlinks(&feynman_id).len(), 1);
+    }
+}
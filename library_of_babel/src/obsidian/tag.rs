@@ -1,19 +1,265 @@
+use super::Span;
 use lazy_static::lazy_static;
 use regex::Regex;
+use std::collections::{BTreeMap, HashSet};
+use std::ops::Range;
 
-pub struct Tag {}
+/// A tag like `#project/poetry`. Slashes nest tags, so `project/poetry`
+/// is a child of `project`.
+///
+/// Obsidian compares tags without caring about case, so `#Poetry` and
+/// `#poetry` are the same tag.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Tag {
+    /// The tag without its `#`, like `project/poetry`.
+    pub name: String,
+    /// Where the tag is in the text it was parsed from. `None` for tags
+    /// that came from the `tags` property.
+    pub span: Option<Span>,
+}
+
+lazy_static! {
+    /// A `#` at the start of the string or after whitespace, followed by
+    /// letters, numbers, `_`, `-` or `/`. Trailing punctuation isn't part
+    /// of the tag, so `#poetry.` is `poetry`.
+    static ref match_tags: Regex =
+        Regex::new(r"(?:^|\s)(#[\p{L}\p{N}_\-/]+)").expect("Error compiling regex.");
+
+    static ref match_urls: Regex =
+        Regex::new(r"[a-zA-Z][a-zA-Z0-9+.\-]*://\S+").expect("Error compiling regex.");
+
+    static ref match_hex_colors: Regex =
+        Regex::new(r"^(?:[0-9a-fA-F]{3,4}|[0-9a-fA-F]{6}|[0-9a-fA-F]{8})$")
+            .expect("Error compiling regex.");
+}
 
 impl Tag {
-    pub fn parse_tags(string: &str) -> Vec<String> {
-        lazy_static! {
-            static ref match_tags: Regex = Regex::new(r"#(\S+)").expect("Error compiling regex.");
+    /// Accepts a name with or without its leading `#`.
+    pub fn new(name: &str) -> Tag {
+        Tag {
+            name: Tag::normalize_name(name),
+            span: None,
         }
+    }
+
+    /// Finds tags in Markdown, skipping code, URLs and hex colors like
+    /// `#ff0000`. `code_ranges` comes from `BlockTree::code_ranges`.
+    pub fn parse_tags(string: &str, code_ranges: &[Range<usize>]) -> Vec<Tag> {
+        let ignored_ranges: Vec<Range<usize>> = code_ranges
+            .iter()
+            .cloned()
+            .chain(match_urls.find_iter(string).map(|url| url.range()))
+            .collect();
 
         match_tags
             .captures_iter(string)
-            // capture[0] gives us the entire match, but we want the first
-            // capture group, which is why we do capture[1]
-            .map(|capture| capture[1].to_string())
+            .filter_map(|captures| {
+                let tag_match = captures.get(1)?;
+                let name = tag_match.as_str()[1..].trim_end_matches('/');
+                let range = tag_match.start()..tag_match.start() + 1 + name.len();
+
+                let is_ignored = ignored_ranges
+                    .iter()
+                    .any(|ignored| ignored.contains(&range.start));
+                if is_ignored || !Tag::is_valid_name(name) {
+                    return None;
+                }
+
+                Some(Tag {
+                    name: name.to_string(),
+                    span: Some(Span::new(&string[range.clone()], range)),
+                })
+            })
+            .collect()
+    }
+
+    /// Obsidian needs at least one character that isn't a number, so `#1984`
+    /// isn't a tag. We also skip anything that looks like a hex color.
+    fn is_valid_name(name: &str) -> bool {
+        let has_non_digit = name.chars().any(|character| !character.is_numeric());
+        let looks_like_hex_color = match_hex_colors.is_match(name)
+            && name.chars().any(|character| character.is_ascii_digit());
+
+        !name.is_empty() && !name.starts_with('/') && has_non_digit && !looks_like_hex_color
+    }
+
+    fn normalize_name(name: &str) -> String {
+        name.trim()
+            .trim_start_matches('#')
+            .trim_matches('/')
+            .to_string()
+    }
+
+    /// The parts of the name, like `["project", "poetry"]`.
+    pub fn segments(&self) -> impl Iterator<Item = &str> {
+        self.name.split('/').filter(|segment| !segment.is_empty())
+    }
+
+    /// `project` for `project/poetry`, or `None` for a top-level tag.
+    pub fn parent(&self) -> Option<Tag> {
+        let (parent, _) = self.name.rsplit_once('/')?;
+        Some(Tag::new(parent))
+    }
+
+    /// True if this tag is `tag_name`, or, when `include_children` is set,
+    /// nested anywhere under it. `tag_name` can start with `#`.
+    pub fn matches(&self, tag_name: &str, include_children: bool) -> bool {
+        let own_name = self.name.to_lowercase();
+        let tag_name = Tag::normalize_name(tag_name).to_lowercase();
+
+        if own_name == tag_name {
+            return true;
+        }
+
+        include_children
+            && own_name
+                .strip_prefix(&tag_name)
+                .is_some_and(|rest| rest.starts_with('/'))
+    }
+}
+
+impl PartialEq for Tag {
+    fn eq(&self, other: &Tag) -> bool {
+        self.name.to_lowercase() == other.name.to_lowercase()
+    }
+}
+
+impl Eq for Tag {}
+
+/// One tag in the vault's tag hierarchy, like the tags pane in Obsidian.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagNode {
+    /// The last part of the tag, like `poetry` for `project/poetry`.
+    pub name: String,
+    /// The whole tag, like `project/poetry`.
+    pub full_name: String,
+    /// How many pages have this tag or one nested under it.
+    pub page_count: usize,
+    /// Sorted by name.
+    pub children: Vec<TagNode>,
+}
+
+impl TagNode {
+    /// Builds the hierarchy from each page's tags. Tags are grouped without
+    /// caring about case, and use the first spelling we see.
+    pub fn build_tree<'t>(tags_by_page: impl IntoIterator<Item = &'t [Tag]>) -> Vec<TagNode> {
+        let mut root = TagTreeBuilder::default();
+
+        for tags in tags_by_page {
+            // A page counts once per tag, however many times the tag appears in it.
+            let mut counted_for_this_page = HashSet::new();
+
+            for tag in tags {
+                let mut node = &mut root;
+                let mut key = String::new();
+
+                for segment in tag.segments() {
+                    if !key.is_empty() {
+                        key.push('/');
+                    }
+                    key.push_str(&segment.to_lowercase());
+
+                    let parent_full_name = node.full_name.clone();
+                    node = node.children.entry(segment.to_lowercase()).or_default();
+                    if node.full_name.is_empty() {
+                        node.name = segment.to_string();
+                        node.full_name = match parent_full_name.as_str() {
+                            "" => segment.to_string(),
+                            parent => format!("{parent}/{segment}"),
+                        };
+                    }
+                    if counted_for_this_page.insert(key.clone()) {
+                        node.page_count += 1;
+                    }
+                }
+            }
+        }
+
+        root.into_nodes()
+    }
+
+    /// Depth-first, parents before their children.
+    pub fn find(&self, tag_name: &str) -> Option<&TagNode> {
+        if Tag::new(&self.full_name).matches(tag_name, false) {
+            return Some(self);
+        }
+        self.children.iter().find_map(|child| child.find(tag_name))
+    }
+}
+
+#[derive(Default)]
+struct TagTreeBuilder {
+    name: String,
+    full_name: String,
+    page_count: usize,
+    children: BTreeMap<String, TagTreeBuilder>,
+}
+
+impl TagTreeBuilder {
+    fn into_nodes(self) -> Vec<TagNode> {
+        self.children
+            .into_values()
+            .map(|child| TagNode {
+                name: child.name.clone(),
+                full_name: child.full_name.clone(),
+                page_count: child.page_count,
+                children: child.into_nodes(),
+            })
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obsidian::BlockTree;
+
+    fn parse_tags(string: &str) -> Vec<Tag> {
+        Tag::parse_tags(string, &BlockTree::parse(string).code_ranges(string))
+    }
+
+    fn tag_names(string: &str) -> Vec<String> {
+        parse_tags(string).into_iter().map(|tag| tag.name).collect()
+    }
+
+    #[test]
+    fn test_parse_tags() {
+        let string = "#poetry, #project/poetry. See https://x.com/#foo and #ff0000 \
+            or #1984 `#not-a-tag` and [[Note#Heading]]\n\
+            ```\n#also-not-a-tag\n```\n\
+            # Heading #last/";
+
+        assert_eq!(tag_names(string), vec!["poetry", "project/poetry", "last"]);
+
+        let tags = parse_tags(string);
+        let span = tags[1].span.as_ref().unwrap();
+        assert_eq!(span.text, "#project/poetry");
+        assert_eq!(&string[span.range.clone()], "#project/poetry");
+    }
+
+    #[test]
+    fn test_matches() {
+        let tag = Tag::new("#Project/Poetry");
+        assert!(tag.matches("project/poetry", false));
+        assert!(tag.matches("#project", true));
+        assert!(!tag.matches("project", false));
+        assert!(!tag.matches("proj", true));
+    }
+
+    #[test]
+    fn test_build_tree() {
+        let first_page = vec![Tag::new("project/poetry"), Tag::new("project")];
+        let second_page = vec![Tag::new("Project/prose")];
+        let tree = TagNode::build_tree([first_page.as_slice(), second_page.as_slice()]);
+
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree[0].full_name, "project");
+        assert_eq!(tree[0].page_count, 2);
+        let children: Vec<_> = tree[0]
+            .children
+            .iter()
+            .map(|child| (child.full_name.as_str(), child.page_count))
+            .collect();
+        assert_eq!(children, vec![("project/poetry", 1), ("project/prose", 1)]);
+    }
+}
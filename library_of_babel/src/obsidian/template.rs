@@ -0,0 +1,306 @@
+use super::{format_date, Page, Period, PeriodicNotes, Vault, VaultItemId};
+use chrono::{Local, NaiveDate};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::HashMap;
+
+/// What a template gets filled in with. See `render_template`.
+#[derive(Debug, Clone, Default)]
+pub struct TemplateVars {
+    /// Values for `{{name}}` placeholders and `{{#if name}}` conditions.
+    /// These win over the built-in placeholders, so a `title` here
+    /// replaces the note's name.
+    pub values: HashMap<String, String>,
+    /// The date for `{{date}}` and `{{date:FORMAT}}`. Today if `None`.
+    pub date: Option<NaiveDate>,
+    /// The period for `{{period.previous}}`, `{{period.next}}` and
+    /// `{{period.parent}}`, with the settings that say where its
+    /// neighbours' notes are.
+    pub period: Option<(Period, PeriodicNotes)>,
+}
+
+impl TemplateVars {
+    pub fn new() -> TemplateVars {
+        TemplateVars::default()
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.values.insert(name.into(), value.into());
+    }
+}
+
+/// A template, broken into text and tags.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Text(String),
+    /// `tag` is the whole tag, braces included, so placeholders we can't
+    /// fill in can be left as they were.
+    Placeholder {
+        tag: String,
+        name: String,
+    },
+    If {
+        condition: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    Include {
+        tag: String,
+        name: String,
+    },
+}
+
+/// An `{{#if}}` we haven't seen the end of yet.
+struct OpenIf {
+    condition: String,
+    then: Vec<Node>,
+    otherwise: Option<Vec<Node>>,
+}
+
+lazy_static! {
+    static ref match_tags: Regex =
+        Regex::new(r"\{\{\s*(.*?)\s*\}\}").expect("Error compiling regex.");
+}
+
+/// Fills in a template, like Obsidian's Templates plugin does, plus a
+/// little more:
+///
+/// - `{{title}}` is the new note's name, and `{{name}}` is any value in `vars`.
+/// - `{{date}}` and `{{date:YYYY.MM.DD}}` format the date in `vars`, or
+///   today. See `format_date`.
+/// - `{{period.previous}}`, `{{period.next}}` and `{{period.parent}}` link
+///   to the notes for the period in `vars`.
+/// - `{{link:Richard Feynman}}` links to a page with the shortest link
+///   text that only points at it.
+/// - `{{#if name}}…{{else}}…{{/if}}` keeps the first part if `name` fills
+///   in as something other than empty text, and the `{{else}}` part if not.
+/// - `{{> Name}}` includes another template from the templates folder.
+///
+/// Templates are written by hand, so mistakes don't stop the note from
+/// being created. Placeholders we can't fill in, includes we can't find
+/// or that would include themselves, and stray `{{else}}`s and `{{/if}}`s
+/// are left as they are. An `{{#if}}` without an `{{/if}}` runs to the end.
+pub fn render_template(vault: &Vault, template: &str, title: &str, vars: &TemplateVars) -> String {
+    let renderer = TemplateRenderer { vault, title, vars };
+    renderer.render(&parse_template(template), &mut vec![])
+}
+
+/// Like `render_template`, for a template from the templates folder, so
+/// it can't include itself either.
+pub(super) fn render_template_page(
+    vault: &Vault,
+    template: &Page,
+    title: &str,
+    vars: &TemplateVars,
+) -> String {
+    let renderer = TemplateRenderer { vault, title, vars };
+    renderer.render(
+        &parse_template(&template.contents),
+        &mut vec![template.id.clone()],
+    )
+}
+
+fn parse_template(template: &str) -> Vec<Node> {
+    let mut nodes = vec![];
+    let mut open_ifs: Vec<OpenIf> = vec![];
+    let mut copied_up_to = 0;
+
+    fn current<'n>(nodes: &'n mut Vec<Node>, open_ifs: &'n mut [OpenIf]) -> &'n mut Vec<Node> {
+        match open_ifs.last_mut() {
+            Some(OpenIf {
+                otherwise: Some(otherwise),
+                ..
+            }) => otherwise,
+            Some(open_if) => &mut open_if.then,
+            None => nodes,
+        }
+    }
+
+    fn close(open_if: OpenIf) -> Node {
+        Node::If {
+            condition: open_if.condition,
+            then: open_if.then,
+            otherwise: open_if.otherwise.unwrap_or_default(),
+        }
+    }
+
+    for captures in match_tags.captures_iter(template) {
+        let tag_match = captures.get(0).expect("Every match has a group 0.");
+        let tag = tag_match.as_str().to_string();
+        let contents = &captures[1];
+
+        let text = &template[copied_up_to..tag_match.start()];
+        if !text.is_empty() {
+            current(&mut nodes, &mut open_ifs).push(Node::Text(text.to_string()));
+        }
+        copied_up_to = tag_match.end();
+
+        if let Some(condition) = contents.strip_prefix("#if ") {
+            open_ifs.push(OpenIf {
+                condition: condition.trim().to_string(),
+                then: vec![],
+                otherwise: None,
+            });
+        } else if contents == "else"
+            && open_ifs
+                .last()
+                .is_some_and(|open_if| open_if.otherwise.is_none())
+        {
+            open_ifs.last_mut().expect("We just checked.").otherwise = Some(vec![]);
+        } else if contents == "/if" && !open_ifs.is_empty() {
+            let open_if = open_ifs.pop().expect("We just checked.");
+            current(&mut nodes, &mut open_ifs).push(close(open_if));
+        } else if let Some(name) = contents.strip_prefix('>') {
+            let name = name.trim().to_string();
+            current(&mut nodes, &mut open_ifs).push(Node::Include { tag, name });
+        } else {
+            let name = contents.to_string();
+            current(&mut nodes, &mut open_ifs).push(Node::Placeholder { tag, name });
+        }
+    }
+
+    let text = &template[copied_up_to..];
+    if !text.is_empty() {
+        current(&mut nodes, &mut open_ifs).push(Node::Text(text.to_string()));
+    }
+    while let Some(open_if) = open_ifs.pop() {
+        current(&mut nodes, &mut open_ifs).push(close(open_if));
+    }
+
+    nodes
+}
+
+struct TemplateRenderer<'r> {
+    vault: &'r Vault,
+    title: &'r str,
+    vars: &'r TemplateVars,
+}
+
+impl TemplateRenderer<'_> {
+    /// `including` holds the templates we're in the middle of including,
+    /// so a template can't include itself.
+    fn render(&self, nodes: &[Node], including: &mut Vec<VaultItemId>) -> String {
+        let mut rendered = String::new();
+
+        for node in nodes {
+            match node {
+                Node::Text(text) => rendered.push_str(text),
+
+                Node::Placeholder { tag, name } => match self.fill_in(name) {
+                    Some(value) => rendered.push_str(&value),
+                    None => rendered.push_str(tag),
+                },
+
+                Node::If {
+                    condition,
+                    then,
+                    otherwise,
+                } => {
+                    let is_true = self
+                        .fill_in(condition)
+                        .is_some_and(|value| !value.is_empty());
+                    let branch = if is_true { then } else { otherwise };
+                    rendered.push_str(&self.render(branch, including));
+                }
+
+                Node::Include { tag, name } => {
+                    let template = self.vault.template(name);
+                    match template {
+                        Some(page) if !including.contains(&page.id) => {
+                            including.push(page.id.clone());
+                            let nodes = parse_template(&page.contents);
+                            rendered.push_str(&self.render(&nodes, including));
+                            including.pop();
+                        }
+                        _ => rendered.push_str(tag),
+                    }
+                }
+            }
+        }
+
+        rendered
+    }
+
+    /// `None` if there's nothing to fill `name` in with.
+    fn fill_in(&self, name: &str) -> Option<String> {
+        if let Some(value) = self.vars.values.get(name) {
+            return Some(value.clone());
+        }
+
+        let date = || self.vars.date.unwrap_or_else(|| Local::now().date_naive());
+        let period = || self.vars.period.as_ref();
+
+        match name {
+            "title" => Some(self.title.to_string()),
+            "date" => Some(format_date(date(), "YYYY-MM-DD")),
+            "period.previous" => {
+                period().map(|(period, periodic_notes)| periodic_notes.link_to(&period.previous()))
+            }
+            "period.next" => {
+                period().map(|(period, periodic_notes)| periodic_notes.link_to(&period.next()))
+            }
+            "period.parent" => period().map(|(period, periodic_notes)| {
+                periodic_notes
+                    .parent(period)
+                    .map(|parent| periodic_notes.link_to(&parent))
+                    .unwrap_or_default()
+            }),
+            _ => {
+                if let Some(format) = name.strip_prefix("date:") {
+                    return Some(format_date(date(), format.trim()));
+                }
+
+                let link_text = name.strip_prefix("link:")?.trim();
+                let link_text = match self.vault.vault_item_by_link_text(link_text) {
+                    Some(item) => self.vault.link_resolver().shortest_link_text(item.file()),
+                    None => link_text.to_string(),
+                };
+                Some(format!("[[{link_text}]]"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::obsidian::*;
+    use chrono::NaiveDate;
+    use std::time::SystemTime;
+
+    #[test]
+    fn test_create_from_template() {
+        let created_at = SystemTime::UNIX_EPOCH;
+        let mut vault = Vault::from_files([
+            (
+                "templates/Person.md",
+                "# {{title}}\n{{#if born}}Born {{born}}.{{else}}Born ?{{/if}}\nAdded {{date:YYYY.MM.DD}}, see {{link:People}}.\n{{> Footer}}",
+                created_at,
+            ),
+            ("templates/Footer.md", "{{unknown}} {{> Footer}}", created_at),
+            ("templates/Loop.md", "Loop {{> Loop.md}}", created_at),
+            ("topics/People.md", "", created_at),
+        ])
+        .unwrap();
+
+        let mut vars = TemplateVars::new();
+        vars.insert("born", "1918");
+        vars.date = NaiveDate::from_ymd_opt(2024, 5, 11);
+        let id = VaultItemId::from("people/Richard Feynman.md");
+        let item = vault.create_from_template(id, "Person", &vars).unwrap();
+
+        assert_eq!(
+            item.try_into_page().unwrap().contents,
+            "# Richard Feynman\nBorn 1918.\nAdded 2024.05.11, see [[People]].\n{{unknown}} {{> Footer}}"
+        );
+
+        let item = vault
+            .create_from_template(VaultItemId::from("Loop.md"), "Loop", &vars)
+            .unwrap();
+        assert_eq!(item.try_into_page().unwrap().contents, "Loop {{> Loop.md}}");
+
+        let error = vault
+            .create_from_template(VaultItemId::from("Other.md"), "Missing", &vars)
+            .unwrap_err();
+        assert!(matches!(error, VaultError::TemplateNotFound { .. }));
+    }
+}
@@ -1,26 +1,189 @@
 use crate::WikiLinkStr;
 
-use super::{vault_items, File, Link, LinkTextStr, Page, VaultItem, VaultItemId};
-use std::collections::HashMap;
+use super::ambiguous_link::find_ambiguous_links;
+use super::embed::Transcluder;
+use super::page::replace_ranges_in;
+use super::parse_cache::ParseCache;
+use super::template::render_template_page;
+use super::vault_item::parse_files;
+use super::watch::WatchedChange;
+use super::{
+    content_hash, file_paths_in_folder, files_in_vault, relative_path, save_unless_changed_on_disk,
+    AmbiguousLink, AttachmentMetadata, AttachmentReport, Canvas, Contents, Embed, EmbedError, File,
+    FileSystemStorage, InMemoryStorage, Link, LinkEdge, LinkGraph, LinkParts, LinkResolver,
+    LinkSyntax, LinkTextStr, LoadDiagnostic, NewLinkFormat, Page, Query, QueryError, QueryTable,
+    SearchIndex, SearchQuery, SearchResult, TagNode, TemplateVars, VaultConfig, VaultError,
+    VaultEvent, VaultItem, VaultItemId, VaultStorage, VaultWatcher,
+};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::{Duration, SystemTime};
+
+/// Where `Vault::from_files` pretends the vault is.
+const in_memory_vault_path: &str = "/in-memory-vault";
 
 #[derive(Debug)]
 pub struct Vault {
     path: PathBuf,
+    config: VaultConfig,
+    storage: Box<dyn VaultStorage>,
     items_by_id: HashMap<VaultItemId, VaultItem>,
+    link_resolver: LinkResolver,
+    link_graph: LinkGraph,
+    search_index: SearchIndex,
+    subscribers: Vec<Sender<VaultEvent>>,
 }
 
 impl Vault {
     pub fn production_vault() -> Vault {
-        Vault::load_from_disk("/Users/photon-garden/library-of-babel")
+        Vault::open_with_obsidian_settings("/Users/photon-garden/library-of-babel")
+            .expect("Error opening the production vault.")
     }
 
     pub fn development_vault() -> Vault {
-        Vault::load_from_disk("/Users/photon-garden/obsidian-dev")
+        Vault::open_with_obsidian_settings("/Users/photon-garden/obsidian-dev")
+            .expect("Error opening the development vault.")
+    }
+
+    /// Like `open`, but with the settings from the vault's own
+    /// `.obsidian/app.json`, so we skip the same excluded files and write
+    /// links the same way the Obsidian app does.
+    pub fn open_with_obsidian_settings(vault_path: impl AsRef<Path>) -> Result<Vault, VaultError> {
+        let config = VaultConfig::from_obsidian_settings(&FileSystemStorage, &vault_path)?;
+        Vault::open(vault_path, config)
+    }
+
+    /// Loads every file in the vault at `vault_path`, skipping hidden files
+    /// and anything `config` ignores. Fails on the first file that can't be
+    /// loaded, or if the parse cache is on and can't be saved.
+    pub fn open(vault_path: impl AsRef<Path>, config: VaultConfig) -> Result<Vault, VaultError> {
+        let vault_path = Vault::canonical_vault_path(vault_path.as_ref())?;
+        Vault::open_with_storage(vault_path, config, FileSystemStorage)
+    }
+
+    /// Like `open`, but reads and writes files through `storage` instead
+    /// of going straight to disk.
+    pub fn open_with_storage(
+        vault_path: impl AsRef<Path>,
+        config: VaultConfig,
+        storage: impl VaultStorage + 'static,
+    ) -> Result<Vault, VaultError> {
+        let vault_path = vault_path.as_ref();
+        let vault_path_str = Vault::check_vault_path(&storage, vault_path)?;
+
+        let files = files_in_vault(&storage, vault_path_str, &config)
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let (vault, diagnostics) =
+            Vault::from_loaded_files(vault_path, config, Box::new(storage), files);
+        match diagnostics.into_iter().next() {
+            Some(diagnostic) => Err(diagnostic.error),
+            None => Ok(vault),
+        }
     }
 
-    fn load_from_disk(vault_path: &'static str) -> Vault {
-        let items = vault_items(vault_path);
+    /// Like `open`, but skips files that can't be loaded and reports them
+    /// as diagnostics instead of failing.
+    pub fn open_leniently(
+        vault_path: impl AsRef<Path>,
+        config: VaultConfig,
+    ) -> Result<(Vault, Vec<LoadDiagnostic>), VaultError> {
+        let vault_path = &Vault::canonical_vault_path(vault_path.as_ref())?;
+        let vault_path_str = Vault::check_vault_path(&FileSystemStorage, vault_path)?;
+
+        let mut files = vec![];
+        let mut diagnostics = vec![];
+        for file_or_error in files_in_vault(&FileSystemStorage, vault_path_str, &config) {
+            match file_or_error {
+                Ok(file) => files.push(file),
+                Err(error) => diagnostics.push(LoadDiagnostic::from(error)),
+            }
+        }
+
+        let (vault, cache_diagnostics) =
+            Vault::from_loaded_files(vault_path, config, Box::new(FileSystemStorage), files);
+        diagnostics.extend(cache_diagnostics);
+        Ok((vault, diagnostics))
+    }
+
+    /// A vault that only exists in memory, made of `(path_from_vault_root,
+    /// contents, created_at)` triples. Handy for tests, since nothing
+    /// touches the disk, even when pages are saved or moved. Include a
+    /// `.obsidian/app.json` to test Obsidian's settings.
+    pub fn from_files<PathFromVaultRoot, FileContents>(
+        files: impl IntoIterator<Item = (PathFromVaultRoot, FileContents, SystemTime)>,
+    ) -> Result<Vault, VaultError>
+    where
+        PathFromVaultRoot: AsRef<str>,
+        FileContents: Into<Vec<u8>>,
+    {
+        let vault_path = Path::new(in_memory_vault_path);
+        let storage = InMemoryStorage::new();
+        storage.create_folder(vault_path);
+        for (path_from_vault_root, contents, created_at) in files {
+            storage.insert_file(
+                vault_path.join(path_from_vault_root.as_ref()),
+                contents,
+                created_at,
+            );
+        }
+
+        let config = VaultConfig::from_obsidian_settings(&storage, vault_path)?;
+        Vault::open_with_storage(vault_path, config, storage)
+    }
+
+    /// The watcher reports absolute paths with symlinks resolved, so the
+    /// vault's path has to look the same, or we couldn't tell which item
+    /// changed.
+    fn canonical_vault_path(vault_path: &Path) -> Result<PathBuf, VaultError> {
+        std::fs::canonicalize(vault_path).map_err(|_| VaultError::VaultNotFound {
+            path: vault_path.to_path_buf(),
+        })
+    }
+
+    fn check_vault_path<'p>(
+        storage: &dyn VaultStorage,
+        vault_path: &'p Path,
+    ) -> Result<&'p str, VaultError> {
+        if !storage.is_dir(vault_path) {
+            return Err(VaultError::VaultNotFound {
+                path: vault_path.to_path_buf(),
+            });
+        }
+
+        vault_path
+            .to_str()
+            .ok_or_else(|| VaultError::non_utf8_path(vault_path))
+    }
+
+    /// The diagnostics are for a parse cache that couldn't be saved. The
+    /// vault is fine without it, it just opens slower next time.
+    fn from_loaded_files(
+        vault_path: &Path,
+        config: VaultConfig,
+        storage: Box<dyn VaultStorage>,
+        files: Vec<File>,
+    ) -> (Vault, Vec<LoadDiagnostic>) {
+        let mut diagnostics = vec![];
+        let mut parse_cache = if config.use_parse_cache {
+            ParseCache::load(storage.as_ref(), vault_path)
+        } else {
+            ParseCache::default()
+        };
+        let (items, link_resolver) = parse_files(files, &mut parse_cache);
+        if config.use_parse_cache && parse_cache.is_stale() {
+            let pages = items.iter().filter_map(VaultItem::try_into_page);
+            if let Err(error) = ParseCache::save(storage.as_ref(), vault_path, pages) {
+                diagnostics.push(LoadDiagnostic::from(error));
+            }
+        }
+        let mut link_graph = LinkGraph::new(items.iter().filter_map(VaultItem::try_into_page));
+        for canvas in items.iter().filter_map(VaultItem::try_into_canvas) {
+            link_graph.update_canvas(canvas);
+        }
+        let search_index = SearchIndex::new(items.iter().filter_map(VaultItem::try_into_page));
         let mut items_by_id: HashMap<VaultItemId, VaultItem> = HashMap::with_capacity(items.len());
 
         for item in items {
@@ -28,10 +191,17 @@ impl Vault {
             items_by_id.insert(id, item);
         }
 
-        Vault {
+        let vault = Vault {
             path: vault_path.into(),
+            config,
+            storage,
             items_by_id,
-        }
+            link_resolver,
+            link_graph,
+            search_index,
+            subscribers: vec![],
+        };
+        (vault, diagnostics)
     }
 
     pub fn item_at_path(&self, path_from_vault_root: &str) -> Option<&VaultItem> {
@@ -67,6 +237,10 @@ impl Vault {
         self.items_mut().filter_map(|item| item.try_into_page_mut())
     }
 
+    pub fn canvases(&self) -> impl Iterator<Item = &Canvas> {
+        self.items().filter_map(|item| item.try_into_canvas())
+    }
+
     pub fn referenced_item(&self, reference: &Link) -> Option<&VaultItem> {
         reference
             .vault_item_id
@@ -78,21 +252,1121 @@ impl Vault {
         &self.path
     }
 
+    pub fn config(&self) -> &VaultConfig {
+        &self.config
+    }
+
+    pub fn storage(&self) -> &dyn VaultStorage {
+        self.storage.as_ref()
+    }
+
+    pub fn link_resolver(&self) -> &LinkResolver {
+        &self.link_resolver
+    }
+
+    pub fn link_graph(&self) -> &LinkGraph {
+        &self.link_graph
+    }
+
+    /// Links and embeds from other pages and canvases that point at `id`.
+    /// Check `LinkEdge::is_embed` to tell them apart.
+    pub fn backlinks(&self, id: &VaultItemId) -> &[LinkEdge] {
+        self.link_graph.backlinks(id)
+    }
+
+    /// Every link and embed in the page or canvas `id`, including ones
+    /// that don't resolve.
+    pub fn outgoing_links(&self, id: &VaultItemId) -> &[LinkEdge] {
+        self.link_graph.outgoing_links(id)
+    }
+
+    /// Items that no other page or canvas links to or embeds.
+    pub fn orphans(&self) -> impl Iterator<Item = &VaultItem> {
+        self.items()
+            .filter(|item| !self.link_graph.has_backlinks(item.id()))
+    }
+
+    /// Links to items that aren't in the vault, grouped by the missing target.
+    pub fn unresolved_links(&self) -> &HashMap<String, Vec<LinkEdge>> {
+        self.link_graph.unresolved_links()
+    }
+
+    /// The size, hash and header information of the attachment `id`, like
+    /// an image's dimensions or a recording's length. The file is read the
+    /// first time this is asked for, and the result is kept until the file
+    /// changes.
+    pub fn attachment_metadata(&self, id: &VaultItemId) -> Result<&AttachmentMetadata, VaultError> {
+        let Some(item) = self.items_by_id.get(id) else {
+            return Err(self.not_found_error(id));
+        };
+        let VaultItem::NonPage { file, metadata, .. } = item else {
+            return Err(VaultError::NotAnAttachment {
+                path: self.absolute_path_to_item(id),
+            });
+        };
+
+        if let Some(metadata) = metadata.get() {
+            return Ok(metadata);
+        }
+        let read_metadata = AttachmentMetadata::read(self.storage.as_ref(), file)?;
+        Ok(metadata.get_or_init(|| read_metadata))
+    }
+
+    /// Attachments that nothing links to or embeds, and attachments that
+    /// are byte-for-byte copies of each other. Reads every attachment, so
+    /// it fails if one can't be read. See `AttachmentReport::cleanup_plan`
+    /// to tidy them up.
+    pub fn attachment_report(&self) -> Result<AttachmentReport, VaultError> {
+        AttachmentReport::new(self)
+    }
+
+    /// Links whose text matches more than one file equally well, with the
+    /// file each one points at and the ones it could also mean. Renaming
+    /// or linking by path fixes them.
+    pub fn ambiguous_links(&self) -> Vec<AmbiguousLink> {
+        find_ambiguous_links(self)
+    }
+
+    /// The target Obsidian would write in a new link in `from_page` to
+    /// `target`, without the brackets, following the config's link format
+    /// and syntax. With the default config, that's the shortest text that
+    /// points at `target` and nothing else, which is usually just its name.
+    /// `None` if `target` isn't in the vault.
+    pub fn link_text_for(&self, target: &VaultItemId, from_page: &VaultItemId) -> Option<String> {
+        let target_file = self.item(target)?.file();
+        Some(self.link_target_for(target_file, from_page, self.new_link_syntax()))
+    }
+
+    /// A whole new link in `from_page` to `target`, like `[[Richard Feynman]]`,
+    /// or `[Richard Feynman](Richard%20Feynman.md)` if the config says to use
+    /// Markdown links. `None` if `target` isn't in the vault.
+    pub fn new_link_to(&self, target: &VaultItemId, from_page: &VaultItemId) -> Option<String> {
+        let target_file = self.item(target)?.file();
+        let syntax = self.new_link_syntax();
+        let alias = match syntax {
+            LinkSyntax::Wiki => None,
+            LinkSyntax::Markdown => Some(target_file.file_name_without_extension.clone()),
+        };
+        let link = Link {
+            is_embed: false,
+            syntax,
+            text: String::new(),
+            link_text: String::new(),
+            parts: LinkParts {
+                alias,
+                ..LinkParts::default()
+            },
+            vault_item_id: Some(target.clone()),
+        };
+        Some(link.text_with_target(&self.link_target_for(target_file, from_page, syntax)))
+    }
+
+    fn new_link_syntax(&self) -> LinkSyntax {
+        if self.config.use_markdown_links {
+            LinkSyntax::Markdown
+        } else {
+            LinkSyntax::Wiki
+        }
+    }
+
+    /// Pages matching `query`, best first, with a snippet of each. See
+    /// `SearchQuery` for the syntax. Searches titles, aliases, tags and
+    /// bodies.
+    ///
+    /// The index keeps up with changes made through the vault. Pages edited
+    /// through `pages_mut` or `item_mut` are re-indexed when they're flushed.
+    pub fn search(&self, query: &str) -> Vec<SearchResult> {
+        let query = SearchQuery::parse(query);
+        self.search_index.search(&query, |id| {
+            self.items_by_id.get(id).and_then(VaultItem::try_into_page)
+        })
+    }
+
+    /// What an embed like `![[Note#Heading]]` shows: the text it
+    /// transcludes, with nested embeds expanded, or the attachment it
+    /// points at.
+    pub fn resolve_embed(&self, link: &Link) -> Result<Embed<'_>, EmbedError> {
+        Transcluder::new(self).resolve(link)
+    }
+
+    /// The page's contents with every embed of another page, section or
+    /// block replaced by what it transcludes. Embeds of attachments and
+    /// embeds that don't resolve are left as they are.
+    pub fn expand_embeds(&self, page: &Page) -> Result<String, EmbedError> {
+        Transcluder::new(self).expand(page, 0..page.contents.len())
+    }
+
+    /// Runs a Dataview-style query, like
+    /// `FROM "people" WHERE links_to([[People]]) SORT file.name`. See `Query`
+    /// for what queries can do.
+    pub fn query(&self, query: &str) -> Result<QueryTable, QueryError> {
+        Query::parse(query)?.run(self)
+    }
+
+    /// Pages tagged `tag_name`, like `#project` or `project`. With
+    /// `include_children`, pages tagged `#project/poetry` count too.
+    pub fn pages_with_tag<'v>(
+        &'v self,
+        tag_name: &'v str,
+        include_children: bool,
+    ) -> impl Iterator<Item = &'v Page> {
+        self.pages()
+            .filter(move |page| page.has_tag(tag_name, include_children))
+    }
+
+    /// Every tag in the vault, nested like Obsidian's tags pane, with how many
+    /// pages use each one.
+    pub fn tag_tree(&self) -> Vec<TagNode> {
+        TagNode::build_tree(self.pages().map(|page| page.tags.as_slice()))
+    }
+
+    /// The template called `name`, like `Person` or `daily/Morning`. We
+    /// look in the templates folder first, then from the vault root, so
+    /// paths from the Periodic Notes plugin's settings work too.
+    pub fn template(&self, name: &str) -> Option<&Page> {
+        let path = if Path::new(name).extension().is_some() {
+            name.to_string()
+        } else {
+            format!("{name}.md")
+        };
+        let in_templates_folder = format!("{}/{path}", self.config.templates_folder);
+
+        self.item_at_path(&in_templates_folder)
+            .or_else(|| self.item_at_path(&path))
+            .and_then(VaultItem::try_into_page)
+    }
+
+    /// Creates a page from the template called `template_name`, filled in
+    /// with `vars`. See `render_template`. Like `find_or_create_page`, this
+    /// leaves the page alone if it already exists.
+    pub fn create_from_template(
+        &mut self,
+        id: VaultItemId,
+        template_name: &str,
+        vars: &TemplateVars,
+    ) -> Result<&VaultItem, VaultError> {
+        if self.item(&id).is_none() {
+            let template =
+                self.template(template_name)
+                    .ok_or_else(|| VaultError::TemplateNotFound {
+                        path: self
+                            .path
+                            .join(&self.config.templates_folder)
+                            .join(template_name),
+                    })?;
+            let contents = render_template_page(self, template, &id.file_stem(), vars);
+            let new_page = self.create_page(&id, contents)?;
+            self.add_item(new_page);
+        }
+
+        Ok(self.item(&id).unwrap())
+    }
+
     pub fn find_or_create_page<GetNewPageContents>(
         &mut self,
         id: VaultItemId,
         get_new_page_contents: GetNewPageContents,
-    ) -> &VaultItem
+    ) -> Result<&VaultItem, VaultError>
     where
         GetNewPageContents: FnOnce() -> String,
     {
         if self.item(&id).is_none() {
             let contents = get_new_page_contents();
-            let new_page = self.create_page(&id, contents);
-            self.items_by_id.insert(id.clone(), new_page);
+            let new_page = self.create_page(&id, contents)?;
+            self.add_item(new_page);
+        }
+
+        Ok(self.item(&id).unwrap())
+    }
+
+    /// Moves an item to `new_path_from_vault_root` and returns its new id.
+    ///
+    /// Use this instead of `File::move_file` so the vault stays keyed by
+    /// the new path and links resolve to the item's new location.
+    pub fn move_item(
+        &mut self,
+        id: &VaultItemId,
+        new_path_from_vault_root: &str,
+    ) -> Result<VaultItemId, VaultError> {
+        // Renaming onto an existing file would silently replace it.
+        let new_absolute_path = self.path.join(new_path_from_vault_root);
+        if self.storage.exists(&new_absolute_path) {
+            return Err(VaultError::io(
+                &new_absolute_path,
+                std::io::ErrorKind::AlreadyExists.into(),
+            ));
+        }
+
+        let Some(mut item) = self.items_by_id.remove(id) else {
+            return Err(self.not_found_error(id));
+        };
+
+        let old_file = item.file().clone();
+        let mut affected_names = names_for(&item);
+        if let Err(error) = item.move_file(self.storage.as_ref(), new_path_from_vault_root) {
+            self.items_by_id.insert(id.clone(), item);
+            return Err(error);
+        }
+
+        self.link_resolver.remove(&old_file);
+        self.link_graph.remove_page(id);
+        self.search_index.remove_page(id);
+        if let Some(page) = item.try_into_page() {
+            self.search_index.update_page(page);
+        }
+
+        let new_id = item.id().clone();
+        affected_names.extend(names_for(&item));
+        self.link_resolver.insert_item(&item);
+        self.items_by_id.insert(new_id.clone(), item);
+
+        self.re_resolve_links(&affected_names, Some(&new_id));
+        Ok(new_id)
+    }
+
+    /// Moves an item to `new_path_from_vault_root`, like `move_item`, then
+    /// fixes links the move broke and saves the pages they're in. Returns
+    /// the item's new id.
+    ///
+    /// Links that still point at the right item, like `[[Richard Feynman]]`
+    /// after moving `Richard Feynman.md` into a folder, are left alone.
+    /// Broken ones get the shortest link text that unambiguously points at
+    /// their old target, keeping their alias, headings and embed marker.
+    /// That includes links to other items that the move made ambiguous,
+    /// and relative links in the moved page itself.
+    pub fn rename_item(
+        &mut self,
+        id: &VaultItemId,
+        new_path_from_vault_root: &str,
+    ) -> Result<VaultItemId, VaultError> {
+        let (new_id, _) = self.rename_item_and_keep_old_contents(id, new_path_from_vault_root)?;
+        Ok(new_id)
+    }
+
+    /// Like `rename_item`, but also returns what each page and canvas it
+    /// rewrote looked like before, so the rename can be undone.
+    pub(super) fn rename_item_and_keep_old_contents(
+        &mut self,
+        id: &VaultItemId,
+        new_path_from_vault_root: &str,
+    ) -> Result<(VaultItemId, Vec<(VaultItemId, String)>), VaultError> {
+        let targets_before_move: Vec<(VaultItemId, Vec<Option<VaultItemId>>)> = self
+            .pages()
+            .map(|page| {
+                let targets = page
+                    .reference_spans
+                    .iter()
+                    .map(|reference_span| reference_span.link.vault_item_id.clone())
+                    .collect();
+                (page.id.clone(), targets)
+            })
+            .collect();
+        let canvas_targets_before_move: Vec<(VaultItemId, Vec<Option<VaultItemId>>)> = self
+            .canvases()
+            .map(|canvas| {
+                let targets = canvas
+                    .links
+                    .iter()
+                    .map(|canvas_link| canvas_link.link.vault_item_id.clone())
+                    .collect();
+                (canvas.id.clone(), targets)
+            })
+            .collect();
+
+        let new_id = self.move_item(id, new_path_from_vault_root)?;
+        let with_new_id = |item_id: VaultItemId| {
+            if &item_id == id {
+                new_id.clone()
+            } else {
+                item_id
+            }
+        };
+
+        // Work out every rewrite before saving any, so a failed save can't
+        // leave some pages rewritten in memory and others not.
+        let mut rewrites = vec![];
+        for (page_id, targets) in targets_before_move {
+            let page_id = with_new_id(page_id);
+            let Some(page) = self
+                .items_by_id
+                .get(&page_id)
+                .and_then(VaultItem::try_into_page)
+            else {
+                continue;
+            };
+
+            let replacements: Vec<_> = page
+                .reference_spans
+                .iter()
+                .zip(targets)
+                .filter_map(|(reference_span, target)| {
+                    let target = with_new_id(target?);
+                    if reference_span.link.vault_item_id.as_ref() == Some(&target) {
+                        return None;
+                    }
+                    let new_text =
+                        self.link_text_pointing_at(&reference_span.link, &page_id, &target)?;
+                    Some((reference_span.range().clone(), new_text))
+                })
+                .collect();
+
+            if !replacements.is_empty() {
+                let new_contents = replace_ranges_in(&page.contents, replacements);
+                rewrites.push((page_id, page.contents.clone(), new_contents));
+            }
+        }
+
+        // Canvases always point at files by their full path, so any move
+        // breaks file nodes.
+        for (canvas_id, targets) in canvas_targets_before_move {
+            let canvas_id = with_new_id(canvas_id);
+            let Some(canvas) = self
+                .items_by_id
+                .get(&canvas_id)
+                .and_then(VaultItem::try_into_canvas)
+            else {
+                continue;
+            };
+
+            let replacements: Vec<_> = canvas
+                .links
+                .iter()
+                .zip(targets)
+                .enumerate()
+                .filter_map(|(index, (canvas_link, target))| {
+                    let target = with_new_id(target?);
+                    if canvas_link.link.vault_item_id.as_ref() == Some(&target) {
+                        return None;
+                    }
+                    let new_text = match canvas_link.range {
+                        Some(_) => {
+                            self.link_text_pointing_at(&canvas_link.link, &canvas_id, &target)?
+                        }
+                        None => target.path_from_vault_root().to_string(),
+                    };
+                    Some((index, new_text))
+                })
+                .collect();
+
+            if !replacements.is_empty() {
+                let mut rewritten = canvas.clone();
+                rewritten.replace_links(replacements, &self.link_resolver);
+                rewrites.push((canvas_id, canvas.contents.clone(), rewritten.contents));
+            }
+        }
+        rewrites.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut old_contents = vec![];
+        for (item_id, contents_before, new_contents) in rewrites {
+            if let Err(error) = self.save_new_contents(&item_id, new_contents) {
+                let rollback_errors = self.undo_rename(id, &new_id, old_contents);
+                return Err(VaultError::with_rollback_errors(error, rollback_errors));
+            }
+            old_contents.push((item_id, contents_before));
         }
 
-        self.item(&id).unwrap()
+        Ok((new_id, old_contents))
+    }
+
+    /// Puts back the pages and canvases a failed rename already saved, then
+    /// moves the item back. Returns whatever couldn't be undone, like files
+    /// that changed on disk in the meantime, which we leave alone.
+    fn undo_rename(
+        &mut self,
+        old_id: &VaultItemId,
+        new_id: &VaultItemId,
+        old_contents: Vec<(VaultItemId, String)>,
+    ) -> Vec<VaultError> {
+        let mut rollback_errors = vec![];
+        for (item_id, contents) in old_contents.into_iter().rev() {
+            if let Err(error) = self.save_new_contents(&item_id, contents) {
+                rollback_errors.push(error);
+            }
+        }
+        if let Err(error) = self.move_item(new_id, old_id.path_from_vault_root()) {
+            rollback_errors.push(error);
+        }
+        rollback_errors
+    }
+
+    /// Writes `contents` to the page or canvas `id`, and only once that
+    /// worked, updates it in memory, along with links, aliases and the
+    /// search index. If saving fails, the vault is left as it was.
+    fn save_new_contents(&mut self, id: &VaultItemId, contents: String) -> Result<(), VaultError> {
+        let Some(item) = self.items_by_id.get(id) else {
+            return Err(self.not_found_error(id));
+        };
+        let (file, disk_state) = match item {
+            VaultItem::Page(page) => (&page.file, &page.disk_state),
+            VaultItem::Canvas(canvas) => (&canvas.file, &canvas.disk_state),
+            VaultItem::NonPage { .. } => {
+                return Err(VaultError::NotAPage {
+                    path: self.absolute_path_to_item(id),
+                })
+            }
+        };
+        let disk_state = save_unless_changed_on_disk(
+            self.storage.as_ref(),
+            &file.absolute_path,
+            disk_state,
+            contents.as_bytes(),
+        )?;
+
+        match self.items_by_id.get_mut(id) {
+            Some(VaultItem::Page(page)) => {
+                page.disk_state = disk_state;
+                page.file.modified_at = disk_state.modified_at;
+            }
+            Some(VaultItem::Canvas(canvas)) => {
+                canvas.disk_state = disk_state;
+                canvas.file.modified_at = disk_state.modified_at;
+            }
+            _ => unreachable!("We just found this page or canvas."),
+        }
+        self.set_contents_in_memory(id, contents);
+        Ok(())
+    }
+
+    /// Re-parses the page or canvas `id` from `contents` without saving it,
+    /// and updates aliases, links and the search index to match.
+    fn set_contents_in_memory(&mut self, id: &VaultItemId, contents: String) {
+        let Some(item) = self.items_by_id.get_mut(id) else {
+            return;
+        };
+        let mut affected_names = names_for(item);
+        match item {
+            VaultItem::Page(page) => {
+                page.set_contents(contents, &self.link_resolver);
+                self.search_index.update_page(page);
+                self.link_resolver
+                    .insert_aliases(&page.file, &page.aliases());
+                affected_names.extend(page.aliases());
+            }
+            VaultItem::Canvas(canvas) => canvas.set_contents(contents, &self.link_resolver),
+            VaultItem::NonPage { .. } => return,
+        }
+
+        self.re_resolve_links(&affected_names, Some(id));
+        match self.items_by_id.get(id) {
+            Some(VaultItem::Page(page)) => self.link_graph.update_page(page),
+            Some(VaultItem::Canvas(canvas)) => self.link_graph.update_canvas(canvas),
+            _ => {}
+        }
+    }
+
+    /// Replaces a page's contents, saves it, and updates links and aliases
+    /// to match. If it can't be saved, the page is left as it was.
+    pub fn edit_page(&mut self, id: &VaultItemId, contents: String) -> Result<(), VaultError> {
+        match self.items_by_id.get(id) {
+            Some(VaultItem::Page(_)) => self.save_new_contents(id, contents),
+            Some(_) => Err(VaultError::NotAPage {
+                path: self.path.join(id.path_from_vault_root()),
+            }),
+            None => Err(self.not_found_error(id)),
+        }
+    }
+
+    /// Replaces a canvas's JSON, saves it, and updates its links to match.
+    /// If it can't be saved, the canvas is left as it was.
+    pub fn edit_canvas(&mut self, id: &VaultItemId, contents: String) -> Result<(), VaultError> {
+        match self.items_by_id.get(id) {
+            Some(VaultItem::Canvas(_)) => self.save_new_contents(id, contents),
+            Some(_) => Err(VaultError::NotACanvas {
+                path: self.path.join(id.path_from_vault_root()),
+            }),
+            None => Err(self.not_found_error(id)),
+        }
+    }
+
+    /// Pages whose contents were changed in memory but not saved yet.
+    pub fn dirty_pages(&self) -> impl Iterator<Item = &Page> {
+        self.pages().filter(|page| page.is_dirty())
+    }
+
+    /// Saves every dirty page and returns the ones it saved. A page that
+    /// can't be saved, for example because it changed on disk, doesn't stop
+    /// the others from being saved, but then we return every error instead.
+    ///
+    /// Pages edited through `pages_mut` or `item_mut` are re-parsed first,
+    /// so their links, tags, properties and backlinks match their new text
+    /// even if they can't be saved.
+    pub fn flush_dirty_pages(&mut self) -> Result<Vec<VaultItemId>, Vec<VaultError>> {
+        let mut saved = vec![];
+        let mut errors = vec![];
+
+        let mut dirty_pages: Vec<(VaultItemId, String)> = self
+            .dirty_pages()
+            .map(|page| (page.id.clone(), page.contents.clone()))
+            .collect();
+        dirty_pages.sort_by(|a, b| a.0.cmp(&b.0));
+
+        for (id, contents) in dirty_pages {
+            self.set_contents_in_memory(&id, contents);
+            let page = self
+                .items_by_id
+                .get_mut(&id)
+                .and_then(VaultItem::try_into_page_mut)
+                .expect("We just found this page.");
+            match page.save(self.storage.as_ref()) {
+                Ok(()) => saved.push(id),
+                Err(error) => errors.push(error),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(saved)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Deletes an item's file and removes it from the vault. Links to it
+    /// become unresolved.
+    pub fn delete_item(&mut self, id: &VaultItemId) -> Result<VaultItem, VaultError> {
+        let Some(item) = self.items_by_id.get(id) else {
+            return Err(self.not_found_error(id));
+        };
+
+        let path = &item.file().absolute_path;
+        self.storage.remove_file(path)?;
+
+        Ok(self.remove_item(id).expect("We just found this item."))
+    }
+
+    /// Writes `bytes` to a new file at `id` and adds it to the vault, to
+    /// undo `delete_item` or create an attachment.
+    pub(super) fn recreate_item(
+        &mut self,
+        id: &VaultItemId,
+        bytes: &[u8],
+    ) -> Result<(), VaultError> {
+        let path = self.absolute_path_to_item(id);
+        if self.storage.exists(&path) {
+            return Err(VaultError::io(
+                &path,
+                std::io::ErrorKind::AlreadyExists.into(),
+            ));
+        }
+
+        self.storage.write(&path, bytes)?;
+        let file = File::load(self.storage.as_ref(), self.path_str(), path, &self.config)?;
+        let item = VaultItem::from_file(&file, &self.link_resolver);
+        self.add_item(item);
+        Ok(())
+    }
+
+    /// Saves `bytes` as a new attachment for `for_page`, in the attachment
+    /// folder from the config, like pasting a file into a page in Obsidian.
+    /// If `file_name` is taken we add a number, like `diagram 1.png`.
+    /// Use `new_link_to` to link the new attachment from the page.
+    pub fn create_attachment(
+        &mut self,
+        for_page: &VaultItemId,
+        file_name: &str,
+        bytes: &[u8],
+    ) -> Result<VaultItemId, VaultError> {
+        let folder = self.config.attachment_folder_for(for_page.folder());
+        let (stem, extension) = match file_name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => (stem, Some(extension)),
+            _ => (file_name, None),
+        };
+        let id = (0..)
+            .map(|n| {
+                let name = match (n, extension) {
+                    (0, _) => file_name.to_string(),
+                    (n, Some(extension)) => format!("{stem} {n}.{extension}"),
+                    (n, None) => format!("{stem} {n}"),
+                };
+                match folder.as_str() {
+                    "" => VaultItemId::from(name.as_str()),
+                    folder => VaultItemId::from(format!("{folder}/{name}").as_str()),
+                }
+            })
+            .find(|id| !self.storage.exists(&self.absolute_path_to_item(id)))
+            .expect("Some number is always free.");
+
+        self.recreate_item(&id, bytes)?;
+        Ok(id)
+    }
+
+    /// Starts watching the vault folder. Changes pile up in the watcher
+    /// until you pass it to `apply_watched_changes`. The watcher reports
+    /// canonical paths, which `open` takes care of, but a vault from
+    /// `open_with_storage` needs to be given one.
+    pub fn watch(&self) -> Result<VaultWatcher, VaultError> {
+        VaultWatcher::new(&self.path)
+    }
+
+    /// Every event `apply_watched_changes` produces from now on gets sent
+    /// to the returned receiver. Dropping it unsubscribes.
+    pub fn subscribe(&mut self) -> Receiver<VaultEvent> {
+        let (sender, receiver) = channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    /// Waits up to `timeout` for changes on disk, then re-reads only the
+    /// files that changed and updates links and backlinks to match.
+    /// Returns what happened, which subscribers also receive, and any files
+    /// that couldn't be read.
+    ///
+    /// Our own saves show up as changes too, but they're skipped because
+    /// the file on disk already matches the page.
+    pub fn apply_watched_changes(
+        &mut self,
+        watcher: &VaultWatcher,
+        timeout: Duration,
+    ) -> (Vec<VaultEvent>, Vec<LoadDiagnostic>) {
+        let mut events = vec![];
+        let mut diagnostics = vec![];
+
+        for change in watcher.changes(timeout) {
+            match change {
+                WatchedChange::Touched(path) => {
+                    for path_from_vault_root in self.paths_under(&path) {
+                        self.sync_item_with_disk(
+                            &path_from_vault_root,
+                            &mut events,
+                            &mut diagnostics,
+                        );
+                    }
+                }
+
+                WatchedChange::Renamed { from, to } => {
+                    self.sync_renamed_items(&from, &to, &mut events, &mut diagnostics);
+                    for path_from_vault_root in self.paths_under(&to) {
+                        self.sync_item_with_disk(
+                            &path_from_vault_root,
+                            &mut events,
+                            &mut diagnostics,
+                        );
+                    }
+                }
+
+                WatchedChange::Rescan => {
+                    let path = self.path.clone();
+                    for path_from_vault_root in self.paths_under(&path) {
+                        self.sync_item_with_disk(
+                            &path_from_vault_root,
+                            &mut events,
+                            &mut diagnostics,
+                        );
+                    }
+                }
+            }
+        }
+
+        self.subscribers.retain(|subscriber| {
+            events
+                .iter()
+                .all(|event| subscriber.send(event.clone()).is_ok())
+        });
+
+        (events, diagnostics)
+    }
+
+    /// Paths from the vault root of every item and every file on disk at or
+    /// under `absolute_path`, leaving out hidden and ignored ones.
+    fn paths_under(&self, absolute_path: &Path) -> BTreeSet<String> {
+        let Some(prefix) = self.path_from_vault_root(absolute_path) else {
+            return BTreeSet::new();
+        };
+        let is_under_prefix = |path_from_vault_root: &str| {
+            prefix.is_empty()
+                || path_from_vault_root == prefix
+                || path_from_vault_root
+                    .strip_prefix(&prefix)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        };
+
+        let on_disk = file_paths_in_folder(self.storage.as_ref(), absolute_path, &self.config)
+            .into_iter()
+            .filter_map(|path| self.path_from_vault_root(&path));
+        let in_vault = self
+            .items_by_id
+            .keys()
+            .map(|id| id.path_from_vault_root().to_string())
+            .filter(|path_from_vault_root| is_under_prefix(path_from_vault_root));
+
+        on_disk
+            .chain(in_vault)
+            .filter(|path_from_vault_root| self.is_watched(path_from_vault_root))
+            .collect()
+    }
+
+    fn path_from_vault_root(&self, absolute_path: &Path) -> Option<String> {
+        absolute_path
+            .strip_prefix(&self.path)
+            .ok()?
+            .to_str()
+            .map(str::to_string)
+    }
+
+    /// Whether a file at this path would have been loaded with the vault.
+    fn is_watched(&self, path_from_vault_root: &str) -> bool {
+        !path_from_vault_root.is_empty()
+            && !path_from_vault_root
+                .split('/')
+                .any(|segment| segment.starts_with('.'))
+            && !self.config.is_ignored(path_from_vault_root)
+    }
+
+    /// Items moved on disk keep their identity, so subscribers see a rename
+    /// instead of a delete and a create. Links aren't rewritten, since
+    /// whatever moved the file is responsible for that.
+    fn sync_renamed_items(
+        &mut self,
+        from: &Path,
+        to: &Path,
+        events: &mut Vec<VaultEvent>,
+        diagnostics: &mut Vec<LoadDiagnostic>,
+    ) {
+        let (Some(from), Some(to)) = (
+            self.path_from_vault_root(from),
+            self.path_from_vault_root(to),
+        ) else {
+            return;
+        };
+
+        let moved_ids: Vec<VaultItemId> = self
+            .items_by_id
+            .keys()
+            .filter(|id| {
+                let path = id.path_from_vault_root();
+                path == from
+                    || path
+                        .strip_prefix(&from)
+                        .is_some_and(|rest| rest.starts_with('/'))
+            })
+            .cloned()
+            .collect();
+
+        for old_id in moved_ids {
+            let new_path_from_vault_root =
+                format!("{to}{}", &old_id.path_from_vault_root()[from.len()..]);
+            let new_id = VaultItemId::from(new_path_from_vault_root.as_str());
+            if !self.is_watched(&new_path_from_vault_root) || self.items_by_id.contains_key(&new_id)
+            {
+                continue;
+            }
+
+            match File::load(
+                self.storage.as_ref(),
+                self.path_str(),
+                self.path.join(&new_path_from_vault_root),
+                &self.config,
+            ) {
+                Ok(file) => {
+                    self.remove_item(&old_id);
+                    let item = VaultItem::from_file(&file, &self.link_resolver);
+                    self.add_item(item);
+                    events.push(VaultEvent::Renamed { old_id, new_id });
+                }
+                // It's gone again already. Syncing `from` later will delete it.
+                Err(VaultError::Io { .. }) => {}
+                Err(error) => diagnostics.push(LoadDiagnostic::from(error)),
+            }
+        }
+
+        // Anything left at the old path has been deleted.
+        for path_from_vault_root in self.paths_under(&self.path.join(&from)) {
+            self.sync_item_with_disk(&path_from_vault_root, events, diagnostics);
+        }
+    }
+
+    /// Adds, reloads or removes the item at `path_from_vault_root` so it
+    /// matches what's on disk.
+    fn sync_item_with_disk(
+        &mut self,
+        path_from_vault_root: &str,
+        events: &mut Vec<VaultEvent>,
+        diagnostics: &mut Vec<LoadDiagnostic>,
+    ) {
+        let id = VaultItemId::from(path_from_vault_root);
+        let absolute_path = self.path.join(path_from_vault_root);
+
+        if !self.storage.is_file(&absolute_path) {
+            if self.remove_item(&id).is_some() {
+                events.push(VaultEvent::Deleted { id });
+            }
+            return;
+        }
+
+        // Nothing to do if the file hasn't been touched since we last read or wrote it.
+        let modified_at_on_disk = self
+            .storage
+            .timestamps(&absolute_path)
+            .ok()
+            .map(|timestamps| timestamps.modified_at);
+        let modified_at_in_vault = self.items_by_id.get(&id).map(|item| match item {
+            VaultItem::Page(page) => page.disk_state.modified_at,
+            VaultItem::Canvas(canvas) => canvas.disk_state.modified_at,
+            VaultItem::NonPage { file, .. } => file.modified_at,
+        });
+        if modified_at_on_disk.is_some() && modified_at_on_disk == modified_at_in_vault {
+            return;
+        }
+
+        let file = match File::load(
+            self.storage.as_ref(),
+            self.path_str(),
+            absolute_path,
+            &self.config,
+        ) {
+            Ok(file) => file,
+            Err(error) => {
+                diagnostics.push(LoadDiagnostic::from(error));
+                return;
+            }
+        };
+
+        let event = match self.items_by_id.get_mut(&id) {
+            None => VaultEvent::Created { id: id.clone() },
+
+            Some(VaultItem::Page(page)) => {
+                let contents_on_disk = match &file.contents {
+                    Contents::Markdown { text } => Some(text.as_str()),
+                    _ => None,
+                };
+                let hash_on_disk = contents_on_disk.map(|text| content_hash(text.as_bytes()));
+
+                if hash_on_disk == Some(page.disk_state.content_hash) {
+                    // Touched, but not changed, like after we save it ourselves.
+                    page.disk_state.modified_at = file.modified_at;
+                    page.file.modified_at = file.modified_at;
+                    return;
+                }
+                if page.is_dirty() {
+                    events.push(VaultEvent::Conflict { id });
+                    return;
+                }
+                VaultEvent::Changed { id: id.clone() }
+            }
+
+            Some(VaultItem::Canvas(canvas)) => {
+                let hash_on_disk = match &file.contents {
+                    Contents::Canvas { text } => Some(content_hash(text.as_bytes())),
+                    _ => None,
+                };
+                if hash_on_disk == Some(canvas.disk_state.content_hash) {
+                    canvas.disk_state.modified_at = file.modified_at;
+                    canvas.file.modified_at = file.modified_at;
+                    return;
+                }
+                VaultEvent::Changed { id: id.clone() }
+            }
+
+            Some(VaultItem::NonPage { .. }) => VaultEvent::Changed { id: id.clone() },
+        };
+
+        let item = VaultItem::from_file(&file, &self.link_resolver);
+        self.add_item(item);
+        events.push(event);
+    }
+
+    /// Takes an item out of the vault without touching its file. Links to
+    /// it become unresolved.
+    fn remove_item(&mut self, id: &VaultItemId) -> Option<VaultItem> {
+        let item = self.items_by_id.remove(id)?;
+        self.link_resolver.remove(item.file());
+        self.link_graph.remove_page(id);
+        self.search_index.remove_page(id);
+        self.re_resolve_links(&names_for(&item), None);
+        Some(item)
+    }
+
+    fn not_found_error(&self, id: &VaultItemId) -> VaultError {
+        VaultError::io(
+            &self.absolute_path_to_item(id),
+            std::io::ErrorKind::NotFound.into(),
+        )
+    }
+
+    /// The text of `link`, in the page `source_id`, pointed at `target`.
+    fn link_text_pointing_at(
+        &self,
+        link: &Link,
+        source_id: &VaultItemId,
+        target: &VaultItemId,
+    ) -> Option<String> {
+        let target_file = self.item(target)?.file();
+        let new_target = self.link_target_for(target_file, source_id, link.syntax);
+        Some(link.text_with_target(&new_target))
+    }
+
+    /// The target of a link in `from_page` to `target_file`, following the
+    /// config's link format. Markdown links never use the shortest format,
+    /// since only Obsidian can follow a bare file name, so they're relative
+    /// to the page unless the format is absolute. If the text we'd write
+    /// points somewhere else from `from_page`, we use the full path instead.
+    fn link_target_for(
+        &self,
+        target_file: &File,
+        from_page: &VaultItemId,
+        syntax: LinkSyntax,
+    ) -> String {
+        let is_page = matches!(target_file.contents, Contents::Markdown { .. });
+        let absolute = if syntax == LinkSyntax::Wiki && is_page {
+            &target_file.path_from_vault_root_without_extension
+        } else {
+            &target_file.path_from_vault_root
+        };
+
+        let link_target = match (syntax, self.config.new_link_format) {
+            (_, NewLinkFormat::Absolute) => absolute.clone(),
+            (LinkSyntax::Wiki, NewLinkFormat::Shortest) => {
+                self.link_resolver.shortest_link_text(target_file)
+            }
+            (LinkSyntax::Wiki, NewLinkFormat::Relative) => {
+                // Wiki links are only relative to the page if they say so.
+                let relative = relative_path(from_page.folder(), absolute);
+                if relative.contains('/') && !relative.starts_with("../") {
+                    format!("./{relative}")
+                } else {
+                    relative
+                }
+            }
+            (LinkSyntax::Markdown, _) => relative_path(from_page.folder(), absolute),
+        };
+
+        let id = VaultItemId::from_file(target_file);
+        let resolved = self
+            .link_resolver
+            .resolve_from(&link_target, syntax, from_page);
+        if resolved == Some(id) {
+            link_target
+        } else {
+            absolute.clone()
+        }
+    }
+
+    /// The contents of the page or canvas `id` with every link to a key
+    /// of `new_targets` pointed at its value instead, keeping aliases,
+    /// headings and embed markers. `None` if no links would change.
+    pub(super) fn contents_with_links_retargeted(
+        &self,
+        id: &VaultItemId,
+        new_targets: &HashMap<VaultItemId, VaultItemId>,
+    ) -> Option<String> {
+        let new_target_for = |link: &Link| new_targets.get(link.vault_item_id.as_ref()?);
+
+        match self.item(id)? {
+            VaultItem::Page(page) => {
+                let replacements: Vec<_> = page
+                    .reference_spans
+                    .iter()
+                    .filter_map(|reference_span| {
+                        let target = new_target_for(&reference_span.link)?;
+                        let new_text =
+                            self.link_text_pointing_at(&reference_span.link, id, target)?;
+                        Some((reference_span.range().clone(), new_text))
+                    })
+                    .collect();
+                if replacements.is_empty() {
+                    return None;
+                }
+                Some(replace_ranges_in(&page.contents, replacements))
+            }
+
+            VaultItem::Canvas(canvas) => {
+                let replacements: Vec<_> = canvas
+                    .links
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, canvas_link)| {
+                        let target = new_target_for(&canvas_link.link)?;
+                        let new_text = match canvas_link.range {
+                            Some(_) => self.link_text_pointing_at(&canvas_link.link, id, target)?,
+                            None => target.path_from_vault_root().to_string(),
+                        };
+                        Some((index, new_text))
+                    })
+                    .collect();
+                if replacements.is_empty() {
+                    return None;
+                }
+                let mut canvas = canvas.clone();
+                canvas.replace_links(replacements, &self.link_resolver);
+                Some(canvas.contents)
+            }
+
+            VaultItem::NonPage { .. } => None,
+        }
+    }
+
+    /// Adds `item`, replacing any item that already has its id.
+    fn add_item(&mut self, item: VaultItem) {
+        let id = item.id().clone();
+        let mut affected_names = names_for(&item);
+        if let Some(old_item) = self.items_by_id.get(&id) {
+            self.link_resolver.remove(old_item.file());
+            affected_names.extend(names_for(old_item));
+        }
+        self.link_resolver.insert_item(&item);
+        if let Some(page) = item.try_into_page() {
+            self.search_index.update_page(page);
+        }
+        self.items_by_id.insert(id.clone(), item);
+        self.re_resolve_links(&affected_names, Some(&id));
+    }
+
+    /// Adding, moving, or removing a file can change what existing links
+    /// resolve to, but only links whose target ends in that file's name
+    /// or is one of its aliases. `changed_page` is the page that was added
+    /// or moved, if any. Its relative links might point somewhere new, so we
+    /// re-resolve all of them.
+    fn re_resolve_links(
+        &mut self,
+        affected_names: &HashSet<String>,
+        changed_page: Option<&VaultItemId>,
+    ) {
+        let link_resolver = &self.link_resolver;
+        let link_graph = &mut self.link_graph;
+
+        // True if the link now points somewhere else.
+        let re_resolve = |link: &mut Link, source_id: &VaultItemId, page_changed: bool| {
+            let target_file_name = link.target().rsplit('/').next().unwrap_or("");
+            if page_changed
+                || affected_names.contains(target_file_name)
+                || affected_names.contains(link.target())
+            {
+                let old_vault_item_id = link.vault_item_id.take();
+                link.resolve(source_id, link_resolver);
+                link.vault_item_id != old_vault_item_id
+            } else {
+                false
+            }
+        };
+
+        for item in self.items_by_id.values_mut() {
+            match item {
+                VaultItem::Page(page) => {
+                    let page_changed = changed_page == Some(&page.id);
+                    let mut links_changed = page_changed;
+                    for reference_span in &mut page.reference_spans {
+                        links_changed |=
+                            re_resolve(&mut reference_span.link, &page.id, page_changed);
+                    }
+                    if links_changed {
+                        link_graph.update_page(page);
+                    }
+                }
+
+                VaultItem::Canvas(canvas) => {
+                    let canvas_changed = changed_page == Some(&canvas.id);
+                    let mut links_changed = canvas_changed;
+                    for canvas_link in &mut canvas.links {
+                        links_changed |=
+                            re_resolve(&mut canvas_link.link, &canvas.id, canvas_changed);
+                    }
+                    if links_changed {
+                        link_graph.update_canvas(canvas);
+                    }
+                }
+
+                VaultItem::NonPage { .. } => {}
+            }
+        }
     }
 
     pub fn absolute_path_to_item(&self, id: &VaultItemId) -> PathBuf {
@@ -106,11 +1380,16 @@ impl Vault {
             .expect("Error converting vault path to str.")
     }
 
-    pub fn create_page(&self, id: &VaultItemId, contents: String) -> VaultItem {
+    pub fn create_page(&self, id: &VaultItemId, contents: String) -> Result<VaultItem, VaultError> {
         let absolute_path_to_new_page = self.absolute_path_to_item(id);
         let vault_path_str = &self.path_str();
-        let file = File::create(vault_path_str, absolute_path_to_new_page, contents);
-        VaultItem::from_file(&file, &self.file_vec())
+        let file = File::create(
+            self.storage.as_ref(),
+            vault_path_str,
+            absolute_path_to_new_page,
+            contents,
+        )?;
+        Ok(VaultItem::from_file(&file, &self.link_resolver))
     }
 
     pub fn vault_item_by_wiki_link(&self, wiki_link: &WikiLinkStr) -> Option<&VaultItem> {
@@ -128,12 +1407,276 @@ impl Vault {
         self.item(&vault_item_id)
     }
 
+    /// `link_text` can include a heading, block id, or alias, like
+    /// `Richard Feynman#Early life|Feynman`. Only the target is used to find the item.
     pub fn vault_item_id_by_link_text(&self, link_text: &LinkTextStr) -> Option<VaultItemId> {
-        let files = self.file_vec();
-        Link::find_vault_item_id(link_text, &files)
+        let parts = LinkParts::parse(link_text);
+        self.link_resolver.resolve(&parts.target).cloned()
     }
+}
+
+/// Every name a link's target could end in to point at `item`.
+fn names_for(item: &VaultItem) -> HashSet<String> {
+    let file = item.file();
+    let mut names = HashSet::from([
+        file.file_name.clone(),
+        file.file_name_without_extension.clone(),
+    ]);
+
+    if let Some(page) = item.try_into_page() {
+        names.extend(page.aliases());
+    }
+
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::obsidian::*;
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use std::sync::Mutex;
+    use std::time::SystemTime;
+
+    /// In-memory storage that refuses every write after the first
+    /// `writes_left`, to make saving fail partway through a change.
+    #[derive(Debug)]
+    struct StorageThatFillsUp {
+        storage: InMemoryStorage,
+        writes_left: Mutex<usize>,
+    }
+
+    impl VaultStorage for StorageThatFillsUp {
+        fn is_file(&self, path: &Path) -> bool {
+            self.storage.is_file(path)
+        }
+
+        fn is_dir(&self, path: &Path) -> bool {
+            self.storage.is_dir(path)
+        }
+
+        fn timestamps(&self, path: &Path) -> Result<Timestamps, VaultError> {
+            self.storage.timestamps(path)
+        }
+
+        fn read(&self, path: &Path) -> Result<Vec<u8>, VaultError> {
+            self.storage.read(path)
+        }
+
+        fn write(&self, path: &Path, contents: &[u8]) -> Result<(), VaultError> {
+            let mut writes_left = self.writes_left.lock().unwrap();
+            if *writes_left == 0 {
+                return Err(VaultError::io(path, io::ErrorKind::StorageFull.into()));
+            }
+            *writes_left -= 1;
+            self.storage.write(path, contents)
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> Result<(), VaultError> {
+            self.storage.rename(from, to)
+        }
+
+        fn remove_file(&self, path: &Path) -> Result<(), VaultError> {
+            self.storage.remove_file(path)
+        }
+
+        fn files_in_folder(
+            &self,
+            folder: &Path,
+            follow_symlinks: bool,
+            skip: &dyn Fn(&Path) -> bool,
+        ) -> Vec<Result<PathBuf, VaultError>> {
+            self.storage.files_in_folder(folder, follow_symlinks, skip)
+        }
+    }
+
+    #[test]
+    fn test_failed_rename_changes_nothing() {
+        let created_at = SystemTime::UNIX_EPOCH;
+        let reading_contents = "I'm reading [[Richard Feynman]].";
+        let mut vault = Vault::from_files([
+            ("Richard Feynman.md", "Physicist.", created_at),
+            ("Reading.md", reading_contents, created_at),
+            ("Zettel.md", "See [[Richard Feynman]].", created_at),
+        ])
+        .unwrap();
+
+        // Obsidian edits one of the pages the rename has to rewrite.
+        let zettel_path = vault.absolute_path_to_item(&VaultItemId::from("Zettel.md"));
+        vault
+            .storage()
+            .write(&zettel_path, b"Edited in Obsidian.")
+            .unwrap();
+
+        let feynman_id = VaultItemId::from("Richard Feynman.md");
+        let error = vault
+            .rename_item(&feynman_id, "people/Dick Feynman.md")
+            .unwrap_err();
+        assert!(matches!(error, VaultError::ChangedOnDisk { .. }));
+
+        assert!(vault.item(&feynman_id).is_some());
+        assert!(vault.item_at_path("people/Dick Feynman.md").is_none());
+        assert!(vault
+            .storage()
+            .is_file(&vault.absolute_path_to_item(&feynman_id)));
+        assert_eq!(vault.backlinks(&feynman_id).len(), 2);
+
+        let reading_id = VaultItemId::from("Reading.md");
+        let reading = vault.item(&reading_id).unwrap().try_into_page().unwrap();
+        assert_eq!(reading.contents, reading_contents);
+        assert!(!reading.is_dirty());
+        assert_eq!(
+            vault
+                .storage()
+                .read(&vault.absolute_path_to_item(&reading_id))
+                .unwrap(),
+            reading_contents.as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_flushing_updates_backlinks() {
+        let created_at = SystemTime::UNIX_EPOCH;
+        let mut vault = Vault::from_files([
+            ("Richard Feynman.md", "", created_at),
+            (
+                "Julian Schwinger.md",
+                "---\naliases: [Schwinger]\n---\nPhysicist.",
+                created_at,
+            ),
+            ("Reading.md", "I'm reading [[Richard Feynman]].", created_at),
+        ])
+        .unwrap();
+
+        let reading = vault
+            .pages_mut()
+            .find(|page| page.id == VaultItemId::from("Reading.md"))
+            .unwrap();
+        reading.find_and_replace_text_for_references(|_| "[[Schwinger]]".to_string());
+        vault.flush_dirty_pages().unwrap();
+
+        let feynman_id = VaultItemId::from("Richard Feynman.md");
+        let schwinger_id = VaultItemId::from("Julian Schwinger.md");
+        assert!(vault.backlinks(&feynman_id).is_empty());
+        assert_eq!(vault.backlinks(&schwinger_id).len(), 1);
+
+        let reading = vault
+            .item(&VaultItemId::from("Reading.md"))
+            .unwrap()
+            .try_into_page()
+            .unwrap();
+        assert_eq!(reading.contents, "I'm reading [[Schwinger]].");
+        assert_eq!(*reading.reference_spans[0].range(), 12..25);
+        assert_eq!(
+            reading.reference_spans[0].link.vault_item_id,
+            Some(schwinger_id)
+        );
+    }
+
+    #[test]
+    fn test_failed_rollback_is_reported() {
+        let vault_path = Path::new("/vault");
+        let storage = InMemoryStorage::new();
+        let created_at = SystemTime::UNIX_EPOCH;
+        storage.create_folder(vault_path);
+        for (path, contents) in [
+            ("Richard Feynman.md", "Physicist."),
+            ("Reading.md", "I'm reading [[Richard Feynman]]."),
+            ("Zettel.md", "See [[Richard Feynman]]."),
+        ] {
+            storage.insert_file(vault_path.join(path), contents, created_at);
+        }
+        // Enough to rewrite `Reading.md`, but not `Zettel.md`, or to put
+        // `Reading.md` back afterwards.
+        let storage = StorageThatFillsUp {
+            storage,
+            writes_left: Mutex::new(1),
+        };
+        let mut vault =
+            Vault::open_with_storage(vault_path, VaultConfig::default(), storage).unwrap();
+
+        let feynman_id = VaultItemId::from("Richard Feynman.md");
+        let error = vault
+            .rename_item(&feynman_id, "people/Dick Feynman.md")
+            .unwrap_err();
+        let VaultError::RollbackFailed {
+            error,
+            rollback_errors,
+        } = error
+        else {
+            panic!("Expected the rollback to fail, got {error:?}.");
+        };
+        assert_eq!(error.path(), vault_path.join("Zettel.md"));
+        assert_eq!(rollback_errors.len(), 1);
+        assert_eq!(rollback_errors[0].path(), vault_path.join("Reading.md"));
+
+        // Moving the item back doesn't need any writes, so that part worked.
+        assert!(vault.item(&feynman_id).is_some());
+        assert!(vault.item_at_path("people/Dick Feynman.md").is_none());
+    }
+
+    #[test]
+    fn test_rename_keeps_table_escapes() {
+        let created_at = SystemTime::UNIX_EPOCH;
+        let mut vault = Vault::from_files([
+            ("Richard Feynman.md", "", created_at),
+            (
+                "Physicists.md",
+                "| Name | Prize |\n| --- | --- |\n| [[Richard Feynman\\|Feynman]] | 1965 |",
+                created_at,
+            ),
+        ])
+        .unwrap();
+
+        vault
+            .rename_item(
+                &VaultItemId::from("Richard Feynman.md"),
+                "people/Dick Feynman.md",
+            )
+            .unwrap();
+
+        let page = vault
+            .item(&VaultItemId::from("Physicists.md"))
+            .unwrap()
+            .try_into_page()
+            .unwrap();
+        assert_eq!(
+            page.contents,
+            "| Name | Prize |\n| --- | --- |\n| [[Dick Feynman\\|Feynman]] | 1965 |"
+        );
+    }
+
+    #[test]
+    fn test_open_leniently_skips_files_that_cant_be_loaded() {
+        let vault_path =
+            std::env::temp_dir().join(format!("library-of-babel-lenient-{}", std::process::id()));
+        std::fs::create_dir_all(vault_path.join("people")).unwrap();
+        std::fs::write(
+            vault_path.join("Reading.md"),
+            "I'm reading [[Richard Feynman]].",
+        )
+        .unwrap();
+        std::fs::write(vault_path.join("people/Richard Feynman.md"), "Physicist.").unwrap();
+        std::fs::write(vault_path.join("Broken.md"), b"Not UTF-8: \xff\xfe").unwrap();
+
+        assert!(Vault::open(&vault_path, VaultConfig::default()).is_err());
+        let (vault, diagnostics) =
+            Vault::open_leniently(&vault_path, VaultConfig::default()).unwrap();
+        std::fs::remove_dir_all(&vault_path).unwrap();
 
-    fn file_vec(&self) -> Vec<&File> {
-        self.items_by_id.values().map(|item| item.file()).collect()
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].path.ends_with("Broken.md"));
+        assert!(matches!(
+            diagnostics[0].error,
+            VaultError::NonUtf8Contents { .. }
+        ));
+        assert_eq!(vault.items().count(), 2);
+        assert!(vault.item_at_path("Broken.md").is_none());
+        assert_eq!(
+            vault
+                .backlinks(&VaultItemId::from("people/Richard Feynman.md"))
+                .len(),
+            1
+        );
     }
 }
@@ -0,0 +1,326 @@
+use super::{VaultError, VaultStorage};
+use regex::Regex;
+use std::path::Path;
+
+/// Settings that control how a vault is read from disk and how new links
+/// are written. `VaultConfig::from_obsidian_settings` fills them in from
+/// the Obsidian app's own settings.
+#[derive(Debug, Clone)]
+pub struct VaultConfig {
+    /// Glob patterns for files and folders to skip, relative to the vault root.
+    /// For example, `templates/**` or `*.excalidraw.md`. `*` and `?` never
+    /// match a `/`, but `**` does.
+    pub ignore: Vec<String>,
+    /// Files and folders to skip whose path from the vault root matches.
+    /// Obsidian's excluded files can be regular expressions like `/\.draft\.md$/`.
+    pub ignore_regexes: Vec<Regex>,
+    /// Where new attachments go, relative to the vault root. `None` means the
+    /// vault root. Like in Obsidian, `./` means the page's own folder, and
+    /// `./attachments` a folder inside it. See `attachment_folder_for`.
+    pub attachment_folder: Option<String>,
+    /// How much of a file's path new links include.
+    pub new_link_format: NewLinkFormat,
+    /// Write new links as `[Richard Feynman](Richard%20Feynman.md)` instead
+    /// of `[[Richard Feynman]]`.
+    pub use_markdown_links: bool,
+    pub follow_symlinks: bool,
+    /// Extensions, without the leading dot, of files that should be parsed as pages.
+    pub page_extensions: Vec<String>,
+    /// Where templates live, relative to the vault root.
+    pub templates_folder: String,
+    /// Save what we parsed from each page to `.library_of_babel/parse-cache.json`
+    /// in the vault, so opening it again only parses the pages that changed.
+    /// Off by default, since it writes into the vault.
+    pub use_parse_cache: bool,
+}
+
+impl Default for VaultConfig {
+    fn default() -> Self {
+        VaultConfig {
+            ignore: vec![],
+            ignore_regexes: vec![],
+            attachment_folder: None,
+            new_link_format: NewLinkFormat::Shortest,
+            use_markdown_links: false,
+            follow_symlinks: false,
+            page_extensions: vec!["md".to_string(), "txt".to_string()],
+            templates_folder: "templates".to_string(),
+            use_parse_cache: false,
+        }
+    }
+}
+
+/// Obsidian's "New link format" setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NewLinkFormat {
+    /// Just the file's name, unless another file has the same name.
+    Shortest,
+    /// The path from the linking page's folder, like `../people/Richard Feynman`.
+    Relative,
+    /// The path from the vault root.
+    Absolute,
+}
+
+/// The parts of `.obsidian/app.json` we care about. Obsidian leaves out
+/// settings that haven't been changed from their defaults.
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct ObsidianAppSettings {
+    attachment_folder_path: Option<String>,
+    new_link_format: Option<NewLinkFormat>,
+    user_ignore_filters: Option<Vec<String>>,
+    use_markdown_links: Option<bool>,
+}
+
+impl VaultConfig {
+    /// The default config, with the attachment folder, link format and
+    /// excluded files from the vault's `.obsidian/app.json`. If there's no
+    /// such file, that's just the default config.
+    pub fn from_obsidian_settings(
+        storage: &dyn VaultStorage,
+        vault_path: impl AsRef<Path>,
+    ) -> Result<VaultConfig, VaultError> {
+        let mut config = VaultConfig::default();
+        let settings_path = vault_path.as_ref().join(".obsidian").join("app.json");
+        if !storage.is_file(&settings_path) {
+            return Ok(config);
+        }
+
+        let bytes = storage.read(&settings_path)?;
+        config
+            .apply_obsidian_settings(&bytes)
+            .map_err(|message| VaultError::InvalidSettings {
+                path: settings_path,
+                message,
+            })?;
+        Ok(config)
+    }
+
+    /// `app_json` is the contents of `.obsidian/app.json`.
+    fn apply_obsidian_settings(&mut self, app_json: &[u8]) -> Result<(), String> {
+        let settings: ObsidianAppSettings =
+            serde_json::from_slice(app_json).map_err(|error| error.to_string())?;
+
+        if let Some(attachment_folder_path) = settings.attachment_folder_path {
+            // Obsidian writes `/` for the vault root.
+            let folder = attachment_folder_path.trim_end_matches('/');
+            self.attachment_folder = (!folder.is_empty()).then(|| folder.to_string());
+        }
+        if let Some(new_link_format) = settings.new_link_format {
+            self.new_link_format = new_link_format;
+        }
+        if let Some(use_markdown_links) = settings.use_markdown_links {
+            self.use_markdown_links = use_markdown_links;
+        }
+        for filter in settings.user_ignore_filters.unwrap_or_default() {
+            self.add_obsidian_ignore_filter(&filter)
+                .map_err(|error| format!("bad excluded files pattern {filter}: {error}"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Obsidian's excluded files are either regular expressions between
+    /// slashes, like `/\.draft\.md$/`, or the start of a path, like
+    /// `Archive/` or `Daily notes/2019`.
+    fn add_obsidian_ignore_filter(&mut self, filter: &str) -> Result<(), regex::Error> {
+        let regex = filter
+            .strip_prefix('/')
+            .and_then(|filter| filter.strip_suffix('/'))
+            .filter(|pattern| !pattern.is_empty());
+        if let Some(pattern) = regex {
+            self.ignore_regexes.push(Regex::new(pattern)?);
+        } else if let Some(folder) = filter.strip_suffix('/') {
+            if !folder.is_empty() {
+                self.ignore.push(folder.to_string());
+            }
+        } else if !filter.is_empty() {
+            // Ignoring a folder ignores everything inside it, so this
+            // matches every path that starts with `filter`.
+            self.ignore.push(format!("{filter}*"));
+        }
+        Ok(())
+    }
+
+    /// `path_from_vault_root` can point at a file or a folder. Ignoring a folder
+    /// ignores everything inside it.
+    pub fn is_ignored(&self, path_from_vault_root: &str) -> bool {
+        let folders = path_from_vault_root
+            .match_indices('/')
+            .map(|(index, _)| &path_from_vault_root[..index]);
+        let mut path_and_its_folders = std::iter::once(path_from_vault_root).chain(folders);
+
+        path_and_its_folders.any(|path| {
+            self.ignore
+                .iter()
+                .any(|pattern| glob_matches(pattern, path))
+                || self.ignore_regexes.iter().any(|regex| regex.is_match(path))
+        })
+    }
+
+    /// Where new attachments for a page in `page_folder` go, relative to the
+    /// vault root. Empty for the vault root.
+    pub fn attachment_folder_for(&self, page_folder: &str) -> String {
+        let page_folder = page_folder.trim_matches('/');
+        match self.attachment_folder.as_deref() {
+            None => String::new(),
+            Some(".") => page_folder.to_string(),
+            Some(folder) => match folder.strip_prefix("./") {
+                Some(subfolder) if page_folder.is_empty() => subfolder.to_string(),
+                Some(subfolder) => format!("{page_folder}/{subfolder}"),
+                None => folder.trim_start_matches('/').to_string(),
+            },
+        }
+    }
+
+    pub fn is_page_extension(&self, extension: &str) -> bool {
+        self.page_extensions
+            .iter()
+            .any(|page_extension| page_extension.eq_ignore_ascii_case(extension))
+    }
+}
+
+fn glob_matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<char> = pattern.trim_matches('/').chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    matches_from(&pattern, &path)
+}
+
+fn matches_from(pattern: &[char], path: &[char]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+
+        ['*', '*', rest @ ..] => {
+            // `**/` can also match zero folders, so `**/drafts` matches `drafts`.
+            if let ['/', after_slash @ ..] = rest {
+                if matches_from(after_slash, path) {
+                    return true;
+                }
+            }
+
+            (0..=path.len()).any(|start| matches_from(rest, &path[start..]))
+        }
+
+        ['*', rest @ ..] => {
+            let end_of_segment = path
+                .iter()
+                .position(|&char| char == '/')
+                .unwrap_or(path.len());
+
+            (0..=end_of_segment).any(|start| matches_from(rest, &path[start..]))
+        }
+
+        ['?', rest @ ..] => match path {
+            [first, path_rest @ ..] if *first != '/' => matches_from(rest, path_rest),
+            _ => false,
+        },
+
+        [expected, rest @ ..] => match path {
+            [first, path_rest @ ..] if first == expected => matches_from(rest, path_rest),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("templates", "templates"));
+        assert!(!glob_matches("templates", "templates/daily.md"));
+        assert!(glob_matches("templates/**", "templates/daily/2024.md"));
+        assert!(glob_matches("*.excalidraw.md", "drawing.excalidraw.md"));
+        assert!(!glob_matches(
+            "*.excalidraw.md",
+            "drawings/drawing.excalidraw.md"
+        ));
+        assert!(glob_matches(
+            "**/*.excalidraw.md",
+            "drawings/drawing.excalidraw.md"
+        ));
+        assert!(glob_matches("**/drafts", "drafts"));
+        assert!(glob_matches("people/?.md", "people/a.md"));
+        assert!(!glob_matches("people/?.md", "people/ab.md"));
+    }
+
+    #[test]
+    fn test_obsidian_settings() {
+        let mut config = VaultConfig::default();
+        config
+            .apply_obsidian_settings(
+                br#"{
+                    "attachmentFolderPath": "./attachments",
+                    "newLinkFormat": "relative",
+                    "useMarkdownLinks": true,
+                    "userIgnoreFilters": ["Archive/", "Daily notes/2019", "/\\.draft\\.md$/"]
+                }"#,
+            )
+            .unwrap();
+
+        assert_eq!(config.new_link_format, NewLinkFormat::Relative);
+        assert!(config.use_markdown_links);
+        assert_eq!(config.attachment_folder_for(""), "attachments");
+        assert_eq!(config.attachment_folder_for("people"), "people/attachments");
+
+        assert!(config.is_ignored("Archive/2020/Trip.md"));
+        assert!(!config.is_ignored("Archived.md"));
+        assert!(config.is_ignored("Daily notes/2019-05-14.md"));
+        assert!(config.is_ignored("Daily notes/2019/05-14.md"));
+        assert!(!config.is_ignored("Daily notes/2020-05-14.md"));
+        assert!(config.is_ignored("people/Richard Feynman.draft.md"));
+        assert!(!config.is_ignored("people/Richard Feynman.md"));
+
+        assert!(config
+            .apply_obsidian_settings(br#"{"userIgnoreFilters": ["/(/"]}"#)
+            .is_err());
+    }
+
+    #[test]
+    fn test_new_links_follow_obsidian_settings() {
+        use crate::obsidian::{Vault, VaultItemId};
+        use std::time::SystemTime;
+
+        let created_at = SystemTime::UNIX_EPOCH;
+        let mut vault = Vault::from_files([
+            (
+                ".obsidian/app.json",
+                r#"{"newLinkFormat": "relative", "useMarkdownLinks": true, "userIgnoreFilters": ["Archive/"], "attachmentFolderPath": "./attachments"}"#,
+                created_at,
+            ),
+            ("Archive/Old.md", "", created_at),
+            ("people/Richard Feynman.md", "", created_at),
+            ("journal/2024/Physics.md", "", created_at),
+        ])
+        .unwrap();
+
+        assert!(vault.item_at_path("Archive/Old.md").is_none());
+        let feynman = VaultItemId::from("people/Richard Feynman.md");
+        let physics = VaultItemId::from("journal/2024/Physics.md");
+        assert_eq!(
+            vault.new_link_to(&feynman, &physics).as_deref(),
+            Some("[Richard Feynman](../../people/Richard%20Feynman.md)")
+        );
+
+        let diagram = vault
+            .create_attachment(&physics, "diagram.png", b"png")
+            .unwrap();
+        assert_eq!(
+            diagram.path_from_vault_root(),
+            "journal/2024/attachments/diagram.png"
+        );
+        let second = vault
+            .create_attachment(&physics, "diagram.png", b"png")
+            .unwrap();
+        assert_eq!(
+            second.path_from_vault_root(),
+            "journal/2024/attachments/diagram 1.png"
+        );
+        assert_eq!(
+            vault.new_link_to(&diagram, &physics).as_deref(),
+            Some("[diagram](attachments/diagram.png)")
+        );
+    }
+}
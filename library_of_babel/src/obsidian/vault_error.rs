@@ -0,0 +1,176 @@
+use std::fmt::Display;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum VaultError {
+    /// The vault folder doesn't exist or isn't a folder.
+    VaultNotFound {
+        path: PathBuf,
+    },
+    /// The path can't be converted to a UTF-8 string.
+    NonUtf8Path {
+        path: PathBuf,
+    },
+    /// The path doesn't live under the vault folder.
+    OutsideVault {
+        path: PathBuf,
+    },
+    /// The path ends in `..` or is otherwise missing a file name.
+    MissingFileName {
+        path: PathBuf,
+    },
+    /// The file is a page or canvas, but its contents aren't valid UTF-8.
+    NonUtf8Contents {
+        path: PathBuf,
+    },
+    /// The item exists, but it's an image or some other file instead of a page.
+    NotAPage {
+        path: PathBuf,
+    },
+    /// The item exists, but it's a page or canvas instead of an attachment.
+    NotAnAttachment {
+        path: PathBuf,
+    },
+    /// The item exists, but it isn't a canvas.
+    NotACanvas {
+        path: PathBuf,
+    },
+    /// There's no template at this path. See `Vault::template`.
+    TemplateNotFound {
+        path: PathBuf,
+    },
+    /// Obsidian's settings file isn't valid JSON, or one of the settings
+    /// we read from it doesn't make sense.
+    InvalidSettings {
+        path: PathBuf,
+        message: String,
+    },
+    /// The file changed on disk since we loaded it, so saving would
+    /// overwrite someone else's edits.
+    ChangedOnDisk {
+        path: PathBuf,
+    },
+    /// The file system reported neither a created nor a modified timestamp.
+    MissingTimestamp {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// We couldn't start watching the vault folder for changes.
+    Watch {
+        path: PathBuf,
+        error: notify::Error,
+    },
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// `error` stopped a change partway through, and undoing what was
+    /// already done failed too, so the vault is only partly changed.
+    RollbackFailed {
+        error: Box<VaultError>,
+        rollback_errors: Vec<VaultError>,
+    },
+}
+
+impl VaultError {
+    pub fn io(path: &Path, error: io::Error) -> Self {
+        VaultError::Io {
+            path: path.to_path_buf(),
+            error,
+        }
+    }
+
+    pub fn non_utf8_path(path: &Path) -> Self {
+        VaultError::NonUtf8Path {
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// `error`, or `RollbackFailed` if anything in `rollback_errors` went
+    /// wrong while undoing what was done before it.
+    pub fn with_rollback_errors(error: VaultError, rollback_errors: Vec<VaultError>) -> Self {
+        if rollback_errors.is_empty() {
+            return error;
+        }
+
+        VaultError::RollbackFailed {
+            error: Box::new(error),
+            rollback_errors,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            VaultError::VaultNotFound { path }
+            | VaultError::NonUtf8Path { path }
+            | VaultError::OutsideVault { path }
+            | VaultError::MissingFileName { path }
+            | VaultError::NonUtf8Contents { path }
+            | VaultError::NotAPage { path }
+            | VaultError::NotACanvas { path }
+            | VaultError::NotAnAttachment { path }
+            | VaultError::TemplateNotFound { path }
+            | VaultError::InvalidSettings { path, .. }
+            | VaultError::ChangedOnDisk { path }
+            | VaultError::MissingTimestamp { path, .. }
+            | VaultError::Watch { path, .. }
+            | VaultError::Io { path, .. } => path,
+            VaultError::RollbackFailed { error, .. } => error.path(),
+        }
+    }
+}
+
+impl Display for VaultError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path = self.path().display();
+        match self {
+            VaultError::VaultNotFound { .. } => write!(f, "{path} isn't a folder"),
+            VaultError::NonUtf8Path { .. } => write!(f, "{path} isn't valid unicode"),
+            VaultError::OutsideVault { .. } => write!(f, "{path} isn't inside the vault"),
+            VaultError::MissingFileName { .. } => write!(f, "{path} doesn't have a file name"),
+            VaultError::NonUtf8Contents { .. } => write!(f, "{path} isn't valid UTF-8"),
+            VaultError::NotAPage { .. } => write!(f, "{path} isn't a page"),
+            VaultError::NotACanvas { .. } => write!(f, "{path} isn't a canvas"),
+            VaultError::NotAnAttachment { .. } => write!(f, "{path} isn't an attachment"),
+            VaultError::TemplateNotFound { .. } => write!(f, "there's no template at {path}"),
+            VaultError::InvalidSettings { message, .. } => {
+                write!(f, "couldn't read the settings in {path}: {message}")
+            }
+            VaultError::ChangedOnDisk { .. } => {
+                write!(f, "{path} changed on disk since it was loaded")
+            }
+            VaultError::MissingTimestamp { error, .. } => {
+                write!(f, "couldn't get a timestamp for {path}: {error}")
+            }
+            VaultError::Watch { error, .. } => write!(f, "couldn't watch {path}: {error}"),
+            VaultError::Io { error, .. } => write!(f, "error accessing {path}: {error}"),
+            VaultError::RollbackFailed {
+                error,
+                rollback_errors,
+            } => write!(
+                f,
+                "{error} ({} earlier changes couldn't be undone)",
+                rollback_errors.len()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VaultError {}
+
+/// A file that was skipped while leniently loading a vault.
+#[derive(Debug)]
+pub struct LoadDiagnostic {
+    pub path: PathBuf,
+    pub error: VaultError,
+}
+
+impl From<VaultError> for LoadDiagnostic {
+    fn from(error: VaultError) -> Self {
+        LoadDiagnostic {
+            path: error.path().to_path_buf(),
+            error,
+        }
+    }
+}
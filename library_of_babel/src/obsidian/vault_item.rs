@@ -1,42 +1,90 @@
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::OnceLock;
 
-use super::file::files_in_vault;
 use super::file::{Contents, File};
+use super::parse_cache::ParseCache;
 use super::*;
+use rayon::prelude::*;
 
 #[derive(Debug, Clone)]
 pub enum VaultItem {
     Page(Page),
-    NonPage { id: VaultItemId, file: File },
+    Canvas(Canvas),
+    NonPage {
+        id: VaultItemId,
+        file: File,
+        /// Filled in the first time `Vault::attachment_metadata` is asked
+        /// for it.
+        metadata: OnceLock<AttachmentMetadata>,
+    },
 }
 
-pub fn vault_items(vault_path: &'static str) -> Vec<VaultItem> {
-    let files = files_in_vault(vault_path).collect();
-    parse_files(files)
-}
+/// Parses pages in parallel. Pages that haven't changed since they were
+/// cached are taken from `parse_cache` instead.
+pub(super) fn parse_files(
+    files: Vec<File>,
+    parse_cache: &mut ParseCache,
+) -> (Vec<VaultItem>, LinkResolver) {
+    let mut link_resolver = LinkResolver::new(&files);
+    let files: Vec<(File, Option<ParsedPageContents>)> = files
+        .into_iter()
+        .map(|file| {
+            let cached = parse_cache.take(&file);
+            (file, cached)
+        })
+        .collect();
+
+    // Links can point at aliases, so we need every page's aliases before we parse any links.
+    for (file, cached) in &files {
+        if let Contents::Markdown { text } = &file.contents {
+            let aliases = match cached {
+                Some(parsed_page_contents) => parsed_page_contents.properties.aliases(),
+                None => match Frontmatter::parse(text) {
+                    Some(frontmatter) => frontmatter.properties.aliases(),
+                    None => continue,
+                },
+            };
+            link_resolver.insert_aliases(file, &aliases);
+        }
+    }
 
-fn parse_files(mut files: Vec<File>) -> Vec<VaultItem> {
-    files.sort_unstable_by_key(|file| file.created_at);
-    let files_slice: Vec<_> = files.iter().collect();
+    let items = files
+        .into_par_iter()
+        .map(|(file, cached)| match (cached, &file.contents) {
+            (Some(parsed_page_contents), Contents::Markdown { text }) => {
+                let text = text.clone();
+                VaultItem::Page(Page::from_cache(
+                    &link_resolver,
+                    file,
+                    text,
+                    parsed_page_contents,
+                ))
+            }
+            _ => VaultItem::from_file(&file, &link_resolver),
+        })
+        .collect();
 
-    files
-        .iter()
-        .map(|file| VaultItem::from_file(file, &files_slice))
-        .collect()
+    (items, link_resolver)
 }
 
 impl VaultItem {
-    pub fn from_file(file: &File, files: &[&File]) -> VaultItem {
+    pub fn from_file(file: &File, link_resolver: &LinkResolver) -> VaultItem {
         match &file.contents {
             Contents::Markdown { text } => {
-                let page = Page::parse(files, file.clone(), text.clone());
+                let page = Page::parse(link_resolver, file.clone(), text.clone());
                 VaultItem::Page(page)
             }
 
+            Contents::Canvas { text } => {
+                let canvas = Canvas::parse(link_resolver, file.clone(), text.clone());
+                VaultItem::Canvas(canvas)
+            }
+
             _ => VaultItem::NonPage {
                 id: VaultItemId::from_file(file),
                 file: file.clone(),
+                metadata: OnceLock::new(),
             },
         }
     }
@@ -44,6 +92,7 @@ impl VaultItem {
     pub fn id(&self) -> &VaultItemId {
         match self {
             VaultItem::Page(page) => &page.id,
+            VaultItem::Canvas(canvas) => &canvas.id,
             VaultItem::NonPage { id, .. } => id,
         }
     }
@@ -51,10 +100,38 @@ impl VaultItem {
     pub fn file(&self) -> &File {
         match self {
             VaultItem::Page(page) => &page.file,
+            VaultItem::Canvas(canvas) => &canvas.file,
+            VaultItem::NonPage { file, .. } => file,
+        }
+    }
+
+    fn file_mut(&mut self) -> &mut File {
+        match self {
+            VaultItem::Page(page) => &mut page.file,
+            VaultItem::Canvas(canvas) => &mut canvas.file,
             VaultItem::NonPage { file, .. } => file,
         }
     }
 
+    /// Moves the underlying file and updates the item's id to match.
+    pub fn move_file(
+        &mut self,
+        storage: &dyn VaultStorage,
+        new_path_from_vault_root: &str,
+    ) -> Result<(), VaultError> {
+        self.file_mut()
+            .move_file(storage, new_path_from_vault_root)?;
+
+        let new_id = VaultItemId::from_file(self.file());
+        match self {
+            VaultItem::Page(page) => page.id = new_id,
+            VaultItem::Canvas(canvas) => canvas.id = new_id,
+            VaultItem::NonPage { id, .. } => *id = new_id,
+        }
+
+        Ok(())
+    }
+
     pub fn try_into_page(&self) -> Option<&Page> {
         self.try_into().ok()
     }
@@ -63,6 +140,14 @@ impl VaultItem {
         self.try_into().ok()
     }
 
+    pub fn try_into_canvas(&self) -> Option<&Canvas> {
+        self.try_into().ok()
+    }
+
+    pub fn try_into_canvas_mut(&mut self) -> Option<&mut Canvas> {
+        self.try_into().ok()
+    }
+
     pub fn is_image(&self) -> bool {
         self.file().is_image()
     }
@@ -79,7 +164,7 @@ impl VaultItem {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize)]
 /// The path from the vault root.
 pub struct VaultItemId(String);
 
@@ -96,6 +181,15 @@ impl VaultItemId {
         &self.0
     }
 
+    /// The folder the item is in, relative to the vault root. Empty for
+    /// items at the root.
+    pub fn folder(&self) -> &str {
+        match self.path_from_vault_root().rsplit_once('/') {
+            Some((folder, _)) => folder,
+            None => "",
+        }
+    }
+
     pub fn file_name(&self) -> String {
         let path = self.path_from_vault_root();
         PathBuf::from_str(path)
@@ -0,0 +1,303 @@
+use super::{VaultError, VaultItemId};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+/// Something that changed in the vault because a file changed on disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VaultEvent {
+    Created {
+        id: VaultItemId,
+    },
+    /// The file's contents changed, and we re-parsed it.
+    Changed {
+        id: VaultItemId,
+    },
+    Deleted {
+        id: VaultItemId,
+    },
+    Renamed {
+        old_id: VaultItemId,
+        new_id: VaultItemId,
+    },
+    /// The page changed on disk while it had unsaved changes in memory.
+    /// We kept the in-memory version, so saving it will fail until the
+    /// conflict is sorted out.
+    Conflict {
+        id: VaultItemId,
+    },
+}
+
+/// Watches a vault's folder for changes. Nothing happens to the vault
+/// until you pass this to `Vault::apply_watched_changes`.
+pub struct VaultWatcher {
+    // Dropping the watcher stops it, so we hold on to it.
+    _watcher: RecommendedWatcher,
+    receiver: Receiver<notify::Result<notify::Event>>,
+}
+
+/// A change to the file system, boiled down to what the vault cares about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum WatchedChange {
+    /// Something happened at this path. We look at what's there now to
+    /// work out what.
+    Touched(PathBuf),
+    Renamed {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    /// We might have missed events, so the whole vault needs checking.
+    Rescan,
+}
+
+impl std::fmt::Debug for VaultWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VaultWatcher").finish_non_exhaustive()
+    }
+}
+
+impl VaultWatcher {
+    pub(super) fn new(vault_path: &Path) -> Result<VaultWatcher, VaultError> {
+        let watch_error = |error| VaultError::Watch {
+            path: vault_path.to_path_buf(),
+            error,
+        };
+
+        let (sender, receiver) = channel();
+        let mut watcher = notify::recommended_watcher(sender).map_err(watch_error)?;
+        watcher
+            .watch(vault_path, RecursiveMode::Recursive)
+            .map_err(watch_error)?;
+
+        Ok(VaultWatcher {
+            _watcher: watcher,
+            receiver,
+        })
+    }
+
+    /// Waits up to `timeout` for the first change, then takes every change
+    /// that's already queued up behind it.
+    pub(super) fn changes(&self, timeout: Duration) -> Vec<WatchedChange> {
+        let mut events = vec![];
+        match self.receiver.recv_timeout(timeout) {
+            Ok(event) => events.push(event),
+            Err(RecvTimeoutError::Timeout) => return vec![],
+            // The watcher is gone, so we can't trust that we've seen everything.
+            Err(RecvTimeoutError::Disconnected) => return vec![WatchedChange::Rescan],
+        }
+        events.extend(self.receiver.try_iter());
+
+        to_watched_changes(events)
+    }
+}
+
+fn to_watched_changes(events: Vec<notify::Result<notify::Event>>) -> Vec<WatchedChange> {
+    let mut changes = vec![];
+    // Some platforms report the two halves of a rename separately, tied
+    // together by a tracker id.
+    let mut renamed_from: HashMap<usize, PathBuf> = HashMap::new();
+
+    for event in events {
+        let Ok(event) = event else {
+            changes.push(WatchedChange::Rescan);
+            continue;
+        };
+
+        if event.need_rescan() {
+            changes.push(WatchedChange::Rescan);
+            continue;
+        }
+
+        match (event.kind, event.paths.as_slice(), event.tracker()) {
+            (EventKind::Access(_), _, _) => {}
+
+            (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), [from, to], _) => {
+                changes.push(WatchedChange::Renamed {
+                    from: from.clone(),
+                    to: to.clone(),
+                });
+            }
+
+            (EventKind::Modify(ModifyKind::Name(RenameMode::From)), [from], Some(tracker)) => {
+                renamed_from.insert(tracker, from.clone());
+            }
+
+            (EventKind::Modify(ModifyKind::Name(RenameMode::To)), [to], Some(tracker)) => {
+                match renamed_from.remove(&tracker) {
+                    Some(from) => changes.push(WatchedChange::Renamed {
+                        from,
+                        to: to.clone(),
+                    }),
+                    None => changes.push(WatchedChange::Touched(to.clone())),
+                }
+            }
+
+            (_, paths, _) => {
+                changes.extend(paths.iter().cloned().map(WatchedChange::Touched));
+            }
+        }
+    }
+
+    // Files moved out of the vault only show up as the first half of a rename.
+    changes.extend(renamed_from.into_values().map(WatchedChange::Touched));
+    changes.dedup();
+
+    // A file that was touched and then renamed isn't at its old path
+    // anymore, so syncing it there would delete it instead of following
+    // the rename. Syncing its new path after the rename picks up the edit.
+    changes
+        .iter()
+        .enumerate()
+        .filter(|(index, change)| {
+            match change {
+            WatchedChange::Touched(path) => !changes[index + 1..].iter().any(|later| {
+                matches!(later, WatchedChange::Renamed { from, .. } if path.starts_with(from))
+            }),
+            _ => true,
+        }
+        })
+        .map(|(_, change)| change.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obsidian::{Vault, VaultConfig};
+    use notify::event::CreateKind;
+    use std::fs;
+    use std::time::Instant;
+
+    #[test]
+    fn test_to_watched_changes() {
+        let events = vec![
+            Ok(notify::Event::new(EventKind::Create(CreateKind::File))
+                .add_path(PathBuf::from("/vault/new.md"))),
+            Ok(notify::Event::new(EventKind::Modify(ModifyKind::Any))
+                .add_path(PathBuf::from("/vault/old name.md"))),
+            Ok(
+                notify::Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::From)))
+                    .add_path(PathBuf::from("/vault/old name.md"))
+                    .set_tracker(1),
+            ),
+            Ok(
+                notify::Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::To)))
+                    .add_path(PathBuf::from("/vault/new name.md"))
+                    .set_tracker(1),
+            ),
+        ];
+
+        assert_eq!(
+            to_watched_changes(events),
+            vec![
+                WatchedChange::Touched(PathBuf::from("/vault/new.md")),
+                WatchedChange::Renamed {
+                    from: PathBuf::from("/vault/old name.md"),
+                    to: PathBuf::from("/vault/new name.md"),
+                },
+            ]
+        );
+    }
+
+    /// Applies watched changes until `is_done` or a few seconds pass, since
+    /// the watcher can report one change from disk across several batches.
+    fn apply_changes_until(
+        vault: &mut Vault,
+        watcher: &VaultWatcher,
+        is_done: impl Fn(&Vault, &[VaultEvent]) -> bool,
+    ) -> Vec<VaultEvent> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut events = vec![];
+        while !is_done(vault, &events) && Instant::now() < deadline {
+            let (new_events, diagnostics) =
+                vault.apply_watched_changes(watcher, Duration::from_millis(100));
+            assert!(diagnostics.is_empty(), "{diagnostics:?}");
+            events.extend(new_events);
+        }
+        events
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_apply_watched_changes() {
+        let temp_folder =
+            std::env::temp_dir().join(format!("library-of-babel-watch-{}", std::process::id()));
+        let real_vault_path = temp_folder.join("vault");
+        fs::create_dir_all(&real_vault_path).unwrap();
+        fs::write(real_vault_path.join("Richard Feynman.md"), "Physicist.").unwrap();
+        fs::write(
+            real_vault_path.join("Reading.md"),
+            "I'm reading [[Richard Feynman]].",
+        )
+        .unwrap();
+        // The watcher reports paths with symlinks resolved, so this checks
+        // they still match the vault's items.
+        let symlinked_vault_path = temp_folder.join("symlinked vault");
+        std::os::unix::fs::symlink(&real_vault_path, &symlinked_vault_path).unwrap();
+
+        let mut vault = Vault::open(&symlinked_vault_path, VaultConfig::default()).unwrap();
+        let watcher = vault.watch().unwrap();
+        let feynman = VaultItemId::from("Richard Feynman.md");
+        let reading = VaultItemId::from("Reading.md");
+        let zettel = VaultItemId::from("Zettel.md");
+        let notes = VaultItemId::from("Notes.md");
+
+        fs::write(
+            symlinked_vault_path.join("Zettel.md"),
+            "See [[Richard Feynman]].",
+        )
+        .unwrap();
+        let events = apply_changes_until(&mut vault, &watcher, |vault, _| {
+            vault.backlinks(&feynman).len() == 2
+        });
+        assert!(events.contains(&VaultEvent::Created { id: zettel.clone() }));
+
+        fs::write(real_vault_path.join("Zettel.md"), "See [[Reading]].").unwrap();
+        let events = apply_changes_until(&mut vault, &watcher, |vault, _| {
+            vault.backlinks(&feynman).len() == 1
+        });
+        assert!(events.contains(&VaultEvent::Changed { id: zettel.clone() }));
+        assert_eq!(vault.backlinks(&reading)[0].source, zettel);
+
+        fs::rename(
+            real_vault_path.join("Zettel.md"),
+            real_vault_path.join("Notes.md"),
+        )
+        .unwrap();
+        let events = apply_changes_until(&mut vault, &watcher, |vault, _| {
+            vault.item(&notes).is_some() && vault.item(&zettel).is_none()
+        });
+        assert!(events.contains(&VaultEvent::Renamed {
+            old_id: zettel.clone(),
+            new_id: notes.clone(),
+        }));
+        assert_eq!(vault.backlinks(&reading)[0].source, notes);
+
+        fs::remove_file(real_vault_path.join("Notes.md")).unwrap();
+        let events = apply_changes_until(&mut vault, &watcher, |vault, _| {
+            vault.item(&notes).is_none()
+        });
+        assert!(events.contains(&VaultEvent::Deleted { id: notes.clone() }));
+        assert!(vault.backlinks(&reading).is_empty());
+
+        let reading_page = vault.pages_mut().find(|page| page.id == reading).unwrap();
+        reading_page.find_and_replace_text_for_references(|_| "[[Julian Schwinger]]".to_string());
+        fs::write(real_vault_path.join("Reading.md"), "Edited in Obsidian.").unwrap();
+        let events = apply_changes_until(&mut vault, &watcher, |_, events| {
+            events.contains(&VaultEvent::Conflict {
+                id: reading.clone(),
+            })
+        });
+        assert!(events.contains(&VaultEvent::Conflict {
+            id: reading.clone()
+        }));
+        let reading_page = vault.item(&reading).unwrap().try_into_page().unwrap();
+        assert_eq!(reading_page.contents, "I'm reading [[Julian Schwinger]].");
+
+        drop(watcher);
+        fs::remove_dir_all(&temp_folder).unwrap();
+    }
+}
@@ -1,20 +1,27 @@
 use crate::Link;
 
-use super::LinkText;
+use super::{LinkParts, LinkText};
 use std::str::FromStr;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct WikiLinkString {
     /// Includes double brackets.
     pub text: String,
     /// Excludes double brackets.
     pub link_text: LinkText,
+    /// The target, alias, heading path, and block id from `link_text`.
+    pub parts: LinkParts,
 }
 
 impl WikiLinkString {
     pub fn new(text: String) -> Self {
         let link_text = Link::extract_link_text(&text);
-        WikiLinkString { text, link_text }
+        let parts = LinkParts::parse(&link_text);
+        WikiLinkString {
+            text,
+            link_text,
+            parts,
+        }
     }
 }
 
@@ -1,13 +1,11 @@
 use crate::extensions::VecExtension;
-use crate::obsidian::Vault;
-use crate::File;
+use crate::obsidian::{LinkResolver, Vault, VaultItemId};
 use crate::Link;
 
 pub fn convert_2023_haiku_to_csv() {
     println!("Converting 2023 haiku to CSV...");
 
     let vault = Vault::production_vault();
-    let vault_files: Vec<_> = vault.files().collect();
 
     let haiku_2023_note = vault
         .item_at_path("creations/Haiku 2023.md")
@@ -20,7 +18,9 @@ pub fn convert_2023_haiku_to_csv() {
         .split(|line| line.trim() == "---")
         .into_iter()
         .skip(1) // Everything before the first "---" is the intro to the doc and can be ignored.
-        .flat_map(|lines_in_section| parse_lines(lines_in_section, &vault_files))
+        .flat_map(|lines_in_section| {
+            parse_lines(lines_in_section, &haiku_2023_note.id, vault.link_resolver())
+        })
         .collect();
 
     let mut csv_writer =
@@ -50,7 +50,11 @@ enum EmbedOrPoem {
     MaybePoem,
 }
 
-fn parse_lines(lines_in_section: Vec<&str>, vault_files: &[&File]) -> Vec<ParsedLine> {
+fn parse_lines(
+    lines_in_section: Vec<&str>,
+    note_id: &VaultItemId,
+    link_resolver: &LinkResolver,
+) -> Vec<ParsedLine> {
     let mut lines_with_letters_or_numbers = lines_in_section
         .into_iter()
         .filter(contains_letters_or_numbers);
@@ -58,7 +62,7 @@ fn parse_lines(lines_in_section: Vec<&str>, vault_files: &[&File]) -> Vec<Parsed
         .next()
         .expect("Expected each section to have at least one line with letters.");
     let references_on_first_line =
-        Link::parse_references(first_line_with_letters_or_numbers, vault_files);
+        Link::parse_references(first_line_with_letters_or_numbers, note_id, link_resolver);
     if references_on_first_line.len() != 1 {
         panic!("Expected only a single reference on the first line.");
     }
@@ -71,7 +75,7 @@ fn parse_lines(lines_in_section: Vec<&str>, vault_files: &[&File]) -> Vec<Parsed
 
     lines_with_letters_or_numbers
         .map(|line| {
-            let references = Link::parse_references(line, vault_files);
+            let references = Link::parse_references(line, note_id, link_resolver);
             let (text, kind) = if references.len() == 1 && references.first().unwrap().is_embed {
                 let reference = references.into_iter().next().unwrap();
                 let vault_item_id = reference.vault_item_id.unwrap();
@@ -12,7 +12,7 @@ pub fn test_leaflet() {
         .try_into_page()
         .expect("Haiku 2024 note wasn't a page.");
 
-    let note_contents = haiku_2024_note.contents.clone();
+    let note_contents = haiku_2024_note.body().to_string();
     dbg!(&note_contents);
     let leaflet_document = leaflet::Document::from_str(&vault, note_contents).unwrap();
     dbg!(&leaflet_document);